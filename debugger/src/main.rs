@@ -0,0 +1,382 @@
+use rchip8::cpu::Cpu;
+use rchip8::cpu::Mode;
+use rchip8::debugger::{Access, Breakpoint, StepTarget, StopReason, Watchpoint};
+use rchip8::gdb::GdbServer;
+use std::io::{self, BufRead, Write};
+use std::process::exit;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+mod session;
+use session::Session;
+
+#[cfg(test)]
+mod test_commands;
+
+// instructions run between checks for input while continuing,
+// stepping over a CALL or finishing a subroutine
+const POLL_CYCLES: u64 = 10_000;
+
+const USAGE: &str =
+    "usage: rchip8-debugger [--mode chip8|schip|xochip] [--gdb <host:port>] <rom.ch8>";
+
+const HELP: &str = "\
+s, step [n]           execute n instructions (default 1)
+n, next               step over a CALL, running the whole subroutine
+c, continue           run until a breakpoint, watchpoint, exit or fault
+f, finish             run until the current subroutine returns
+b, break <addr>       break when PC reaches addr
+b, break <addr> if <cond>
+                      break at addr only if a condition holds (e.g. V3 == 5)
+b, break when <cond>  break as soon as a condition becomes true
+b, break op <XXXX>    break on an opcode, ? matches any digit (e.g. Dxy?)
+watch <addr> [len] [r|w|rw]
+                      break after an instruction reads or writes memory
+d, delete <n>         delete breakpoint n
+unwatch <n>           delete watchpoint n
+i, info               list breakpoints and watchpoints
+r, regs               dump registers, I, timers and stack
+x <addr> [len]        hexdump memory
+w, write <addr> <b>.. write bytes to memory
+l, list [addr] [n]    disassemble n instructions (default at PC)
+k, key <k> [up]       press (or release) key 0-F
+screen                render the display
+reset                 reload the ROM
+q, quit               exit the debugger
+While c, n or f run, `key` still works and Enter interrupts.
+Numbers are decimal unless prefixed with 0x. An empty line repeats the last command.";
+
+/**
+ * Parse a decimal or 0x prefixed number
+ */
+fn number(s: &str) -> Option<usize> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/**
+ * Read stdin on its own thread, so the prompt can keep
+ * listening for input while the program runs
+ */
+fn spawn_input() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/**
+ * Run until target is reached, or like continue if there is
+ * none, in chunks of POLL_CYCLES. Key commands are applied
+ * between chunks and any other input stops the run.
+ */
+fn resume(
+    session: &mut Session,
+    input: &Receiver<String>,
+    target: Option<StepTarget>,
+) -> StopReason {
+    let mut announced = false;
+    loop {
+        let stop = match target {
+            Some(target) => session.debugger.run_to(target, POLL_CYCLES),
+            None => session.debugger.run(POLL_CYCLES),
+        };
+        if let Some(stop) = stop {
+            return stop;
+        }
+        if !announced {
+            println!("[*] running, press Enter to interrupt");
+            announced = true;
+        }
+        match input.try_recv() {
+            Ok(line) if matches!(line.split_whitespace().next(), Some("k" | "key")) => {
+                if let Err(e) = run_command(session, &line, input) {
+                    eprintln!("[!] {}", e);
+                }
+            }
+            Ok(_) => {
+                println!("[*] interrupted");
+                return StopReason::Step;
+            }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => return StopReason::Step,
+        }
+    }
+}
+
+/**
+ * Run a single command, returning false when the user quits
+ */
+fn run_command(
+    session: &mut Session,
+    line: &str,
+    input: &Receiver<String>,
+) -> Result<bool, String> {
+    let args: Vec<&str> = line.split_whitespace().collect();
+    let arg = |i: usize| -> Result<usize, String> {
+        let text = args.get(i).ok_or("missing argument")?;
+        number(text).ok_or_else(|| format!("invalid number '{}'", text))
+    };
+    let optional = |i: usize, default: usize| -> Result<usize, String> {
+        if args.len() > i {
+            arg(i)
+        } else {
+            Ok(default)
+        }
+    };
+
+    let stop = match args[0] {
+        "s" | "step" => match optional(1, 1)? {
+            0 => return Err("step count must be at least 1".into()),
+            count => Some(session.step(count)),
+        },
+        "n" | "next" => match session.debugger.over_target() {
+            Some(target) => Some(resume(session, input, Some(target))),
+            None => Some(session.step(1)),
+        },
+        "c" | "continue" => Some(resume(session, input, None)),
+        "f" | "finish" => match session.debugger.out_target() {
+            Some(target) => Some(resume(session, input, Some(target))),
+            None => return Err("not inside a subroutine".into()),
+        },
+        "b" | "break" => {
+            let breakpoint = match args.get(1) {
+                Some(&"op") => {
+                    let pattern = args.get(2).ok_or("missing opcode pattern")?;
+                    Breakpoint::opcode(pattern)
+                        .ok_or_else(|| format!("invalid opcode pattern '{}'", pattern))?
+                }
+                Some(&"when") => Breakpoint::Condition(args[2..].join(" ").parse()?),
+                _ => Breakpoint::Address {
+                    addr: arg(1)?,
+                    condition: match args.get(2) {
+                        Some(&"if") => Some(args[3..].join(" ").parse()?),
+                        Some(other) => return Err(format!("expected 'if', got '{}'", other)),
+                        None => None,
+                    },
+                },
+            };
+            let breakpoints = &mut session.debugger.breakpoints;
+            breakpoints.push(breakpoint);
+            println!("breakpoint {}: {}", breakpoints.len() - 1, breakpoint);
+            None
+        }
+        "watch" => {
+            let addr = arg(1)?;
+            let (len, access) = match args.get(2).and_then(|a| number(a)) {
+                Some(len) => (len, args.get(3)),
+                None => (1, args.get(2)),
+            };
+            let access = match access {
+                Some(access) => access.parse()?,
+                None => Access::ReadWrite,
+            };
+            let watchpoint = Watchpoint {
+                range: addr..addr.saturating_add(len.max(1)),
+                access,
+            };
+            println!(
+                "watchpoint {}: {}",
+                session.debugger.watchpoints.len(),
+                watchpoint
+            );
+            session.debugger.watchpoints.push(watchpoint);
+            None
+        }
+        "d" | "delete" => {
+            let index = arg(1)?;
+            let breakpoints = &mut session.debugger.breakpoints;
+            if index >= breakpoints.len() {
+                return Err(format!("no breakpoint {}", index));
+            }
+            breakpoints.remove(index);
+            None
+        }
+        "unwatch" => {
+            let index = arg(1)?;
+            let watchpoints = &mut session.debugger.watchpoints;
+            if index >= watchpoints.len() {
+                return Err(format!("no watchpoint {}", index));
+            }
+            watchpoints.remove(index);
+            None
+        }
+        "i" | "info" => {
+            for (index, breakpoint) in session.debugger.breakpoints.iter().enumerate() {
+                println!("breakpoint {}: {}", index, breakpoint);
+            }
+            for (index, watchpoint) in session.debugger.watchpoints.iter().enumerate() {
+                println!("watchpoint {}: {}", index, watchpoint);
+            }
+            None
+        }
+        "r" | "regs" => {
+            print!("{}", session.registers());
+            None
+        }
+        "x" => {
+            print!("{}", session.hexdump(arg(1)?, optional(2, 64)?));
+            None
+        }
+        "w" | "write" => {
+            let addr = arg(1)?;
+            let bytes = (2..args.len())
+                .map(|i| match arg(i)? {
+                    b if b <= 0xFF => Ok(b as u8),
+                    b => Err(format!("{} does not fit in a byte", b)),
+                })
+                .collect::<Result<Vec<u8>, String>>()?;
+            if !session.write(addr, &bytes) {
+                return Err(format!("0x{:X} is out of bounds", addr));
+            }
+            None
+        }
+        "l" | "list" => {
+            let pc = session.debugger.cpu.program_counter;
+            print!("{}", session.list(optional(1, pc)?, optional(2, 10)?));
+            None
+        }
+        "k" | "key" => {
+            let key = args
+                .get(1)
+                .and_then(|k| u8::from_str_radix(k, 16).ok())
+                .filter(|k| *k < 16)
+                .ok_or("expected a key 0-F")?;
+            session.key(key, args.get(2) != Some(&"up"));
+            None
+        }
+        "screen" => {
+            print!("{}", session.screen());
+            None
+        }
+        "reset" => {
+            session.reset().map_err(|e| e.to_string())?;
+            None
+        }
+        "h" | "help" => {
+            println!("{}", HELP);
+            None
+        }
+        "q" | "quit" => return Ok(false),
+        other => return Err(format!("unknown command '{}', try help", other)),
+    };
+
+    if let Some(stop) = stop {
+        match stop {
+            StopReason::Step => {}
+            StopReason::WaitingForKey => println!("[*] {} (use `key`)", stop),
+            stop => println!("[*] {}", stop),
+        }
+        print!("{}", session.list(session.debugger.cpu.program_counter, 1));
+    }
+    Ok(true)
+}
+
+/**
+ * Serve the ROM to a single GDB client instead of the prompt
+ */
+fn serve_gdb(addr: &str, rom: Vec<u8>, mode: Mode) {
+    let mut cpu = Cpu::with_mode(mode);
+    if let Err(e) = cpu.load_from_bytes(&rom) {
+        eprintln!("[!] {}", e);
+        exit(1);
+    }
+
+    let listener = std::net::TcpListener::bind(addr).unwrap_or_else(|e| {
+        eprintln!("[!] failed to listen on {}: {}", addr, e);
+        exit(1);
+    });
+    println!("[+] waiting for gdb on {}", addr);
+
+    let mut server = GdbServer::new(cpu);
+    if let Err(e) = server.serve(&listener) {
+        eprintln!("[!] gdb connection failed: {}", e);
+        exit(1);
+    }
+    println!("[+] gdb disconnected");
+}
+
+/**
+ * Interactive debugger for Chip8 programs
+ */
+fn main() {
+    let mut mode = Mode::Chip8;
+    let mut path = None;
+    let mut gdb = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-m" | "--mode" => {
+                let value = args.next().unwrap_or_default();
+                mode = value.parse().unwrap_or_else(|e| {
+                    eprintln!("[!] {}", e);
+                    exit(2);
+                });
+            }
+            "-g" | "--gdb" => gdb = args.next(),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ => path = Some(arg),
+        }
+    }
+
+    let path = path.unwrap_or_else(|| {
+        eprintln!("{}", USAGE);
+        exit(2);
+    });
+
+    let rom = std::fs::read(&path).unwrap_or_else(|e| {
+        eprintln!("[!] failed to read {}: {}", path, e);
+        exit(1);
+    });
+
+    if let Some(addr) = gdb {
+        serve_gdb(&addr, rom, mode);
+        return;
+    }
+
+    let mut session = Session::new(rom, mode).unwrap_or_else(|e| {
+        eprintln!("[!] {}", e);
+        exit(1);
+    });
+
+    println!("[+] loaded {}, type help for a list of commands", path);
+    print!("{}", session.list(session.debugger.cpu.program_counter, 1));
+
+    let input = spawn_input();
+    let mut last = String::new();
+    loop {
+        print!("(rchip8) ");
+        io::stdout().flush().ok();
+
+        let line = match input.recv() {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+
+        let line = match line.trim() {
+            "" => last.clone(),
+            line => line.to_string(),
+        };
+        if line.is_empty() {
+            continue;
+        }
+
+        match run_command(&mut session, &line, &input) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => eprintln!("[!] {}", e),
+        }
+        last = line;
+    }
+}
@@ -0,0 +1,186 @@
+use rchip8::cpu::{Cpu, Mode};
+use rchip8::debugger::{Debugger, StopReason};
+use rchip8::error::Chip8Error;
+use rchip8::instruction::Instruction;
+
+// bytes per hexdump row
+const HEXDUMP_WIDTH: usize = 16;
+
+/**
+ * A ROM loaded into a Debugger
+ */
+pub struct Session {
+    pub debugger: Debugger,
+    rom: Vec<u8>,
+}
+
+impl Session {
+    pub fn new(rom: Vec<u8>, mode: Mode) -> Result<Self, Chip8Error> {
+        let mut cpu = Cpu::with_mode(mode);
+        cpu.load_from_bytes(&rom)?;
+        Ok(Session {
+            debugger: Debugger::new(cpu),
+            rom,
+        })
+    }
+
+    /**
+     * Reload the ROM into a fresh Cpu, keeping breakpoints
+     * and watchpoints
+     */
+    pub fn reset(&mut self) -> Result<(), Chip8Error> {
+        let mut cpu = Cpu::with_mode(self.debugger.cpu.mode);
+        cpu.quirks = self.debugger.cpu.quirks;
+        cpu.load_from_bytes(&self.rom)?;
+
+        let mut debugger = Debugger::new(cpu);
+        debugger.breakpoints = std::mem::take(&mut self.debugger.breakpoints);
+        debugger.watchpoints = std::mem::take(&mut self.debugger.watchpoints);
+        self.debugger = debugger;
+        Ok(())
+    }
+
+    /**
+     * Step into count instructions, stopping early at
+     * breakpoints and watchpoints, a count of 0 does nothing
+     */
+    pub fn step(&mut self, count: usize) -> StopReason {
+        for _ in 0..count {
+            match self.debugger.step_into() {
+                StopReason::Step => {}
+                stop => return stop,
+            }
+        }
+        StopReason::Step
+    }
+
+    /**
+     * Press or release key 0-F
+     */
+    pub fn key(&mut self, key: u8, down: bool) {
+        if down {
+            self.debugger.cpu.key_down_chip8(key);
+        } else {
+            self.debugger.cpu.key_up_chip8(key);
+        }
+    }
+
+    /**
+     * Registers, I, timers, program counter and stack
+     */
+    pub fn registers(&self) -> String {
+        let cpu = &self.debugger.cpu;
+        let mut out = String::new();
+        for (i, value) in cpu.registers.iter().enumerate() {
+            out += &format!("V{:X}={:02X}", i, value);
+            out += if i % 8 == 7 { "\n" } else { " " };
+        }
+        out += &format!(
+            "PC={:03X} I={:03X} DT={:02X} ST={:02X}\n",
+            cpu.program_counter, cpu.i_register, cpu.delay_timer, cpu.sound_timer
+        );
+        let stack: Vec<String> = cpu.stack.iter().map(|a| format!("{:03X}", a)).collect();
+        out += &format!(
+            "stack [{}] cycles {}\n",
+            stack.join(" "),
+            self.debugger.cycles()
+        );
+        out
+    }
+
+    /**
+     * Hex and ASCII dump of len bytes starting at addr
+     */
+    pub fn hexdump(&self, addr: usize, len: usize) -> String {
+        let memory = &self.debugger.cpu.memory;
+        let start = addr.min(memory.len());
+        let end = addr.saturating_add(len).min(memory.len());
+        let mut out = String::new();
+        for (row, chunk) in memory[start..end].chunks(HEXDUMP_WIDTH).enumerate() {
+            let hex: Vec<String> = chunk.iter().map(|b| format!("{:02X}", b)).collect();
+            let text: String = chunk
+                .iter()
+                .map(|&b| if b.is_ascii_graphic() { b as char } else { '.' })
+                .collect();
+            out += &format!(
+                "{:04X}  {:<width$}  {}\n",
+                start + row * HEXDUMP_WIDTH,
+                hex.join(" "),
+                text,
+                width = HEXDUMP_WIDTH * 3 - 1
+            );
+        }
+        out
+    }
+
+    /**
+     * Overwrite memory at addr, returning false if it is out of bounds
+     */
+    pub fn write(&mut self, addr: usize, bytes: &[u8]) -> bool {
+        match addr.checked_add(bytes.len()) {
+            Some(end) if end <= self.debugger.cpu.memory.len() => {
+                self.debugger.cpu.memory[addr..end].copy_from_slice(bytes);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /**
+     * Decode count instructions starting at addr
+     */
+    pub fn list(&self, addr: usize, count: usize) -> String {
+        let mut out = String::new();
+        let mut addr = addr;
+        for _ in 0..count {
+            if addr + 1 >= self.debugger.cpu.memory.len() {
+                break;
+            }
+            let marker = if addr == self.debugger.cpu.program_counter {
+                "=>"
+            } else {
+                "  "
+            };
+            let word = u16::from_be_bytes([
+                self.debugger.cpu.memory[addr],
+                self.debugger.cpu.memory[addr + 1],
+            ]);
+            match Instruction::decode_at(&self.debugger.cpu.memory, addr) {
+                Ok(inst) if inst.available_in(self.debugger.cpu.mode) => {
+                    out += &format!("{} {:03X}: {:04X}  {}\n", marker, addr, word, inst);
+                    addr += inst.size();
+                }
+                _ => {
+                    out += &format!("{} {:03X}: {:04X}  ???\n", marker, addr, word);
+                    addr += 2;
+                }
+            }
+        }
+        out
+    }
+
+    /**
+     * Render the display as text, two pixel rows per line
+     */
+    pub fn screen(&self) -> String {
+        let (width, height) = self.debugger.cpu.resolution();
+        let lit = |x: usize, y: usize| y < height && self.debugger.cpu.display.get(x, y) != 0;
+
+        let border = format!("+{}+\n", "-".repeat(width));
+        let mut out = border.clone();
+        for y in (0..height).step_by(2) {
+            out.push('|');
+            for x in 0..width {
+                out.push(match (lit(x, y), lit(x, y + 1)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                });
+            }
+            out += "|\n";
+        }
+        out += &border;
+        out
+    }
+}
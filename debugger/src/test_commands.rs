@@ -0,0 +1,121 @@
+use crate::run_command;
+use crate::session::Session;
+use rchip8::cpu::Mode;
+use rchip8::debugger::{Access, Breakpoint, StopReason};
+use std::sync::mpsc::{self, Receiver};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROM: [u8; 8] = [
+        0x60, 0x01, // LD V0, 0x01
+        0x70, 0x01, // ADD V0, 0x01
+        0x70, 0x01, // ADD V0, 0x01
+        0x12, 0x06, // JP 0x206
+    ];
+
+    fn session() -> Session {
+        Session::new(ROM.to_vec(), Mode::Chip8).unwrap()
+    }
+
+    // no input arrives while a command runs
+    fn no_input() -> Receiver<String> {
+        mpsc::channel().1
+    }
+
+    #[test]
+    fn test_step_counts() {
+        let mut session = session();
+        assert!(matches!(session.step(0), StopReason::Step));
+        assert_eq!(session.debugger.cpu.program_counter, 0x200);
+
+        assert!(matches!(session.step(2), StopReason::Step));
+        assert_eq!(session.debugger.cpu.program_counter, 0x204);
+        assert_eq!(session.debugger.cpu.registers[0], 2);
+
+        // stops early at the jump to itself
+        assert!(matches!(session.step(10), StopReason::Stalled));
+        assert_eq!(session.debugger.cpu.program_counter, 0x206);
+    }
+
+    #[test]
+    fn test_step_commands() {
+        let mut session = session();
+        let input = no_input();
+        assert!(run_command(&mut session, "step 0", &input).is_err());
+        assert_eq!(session.debugger.cpu.program_counter, 0x200);
+
+        assert_eq!(run_command(&mut session, "s", &input), Ok(true));
+        assert_eq!(session.debugger.cpu.program_counter, 0x202);
+        assert_eq!(run_command(&mut session, "step 0x2", &input), Ok(true));
+        assert_eq!(session.debugger.cpu.program_counter, 0x206);
+        assert!(run_command(&mut session, "step many", &input).is_err());
+    }
+
+    #[test]
+    fn test_next_and_finish_interrupt() {
+        let rom = [
+            0x22, 0x04, // CALL 0x204
+            0x12, 0x02, // JP 0x202
+            0x70, 0x01, // ADD V0, 0x01
+            0x12, 0x04, // JP 0x204
+        ];
+        let mut session = Session::new(rom.to_vec(), Mode::Chip8).unwrap();
+        let (sender, input) = mpsc::channel();
+        assert!(run_command(&mut session, "finish", &input).is_err());
+
+        // the subroutine never returns, so only input stops it
+        sender.send("key 1".to_string()).unwrap();
+        sender.send(String::new()).unwrap();
+        assert_eq!(run_command(&mut session, "n", &input), Ok(true));
+        assert_eq!(session.debugger.cpu.stack.len(), 1);
+        assert!(session.debugger.cpu.is_key_pressed(1));
+
+        sender.send(String::new()).unwrap();
+        assert_eq!(run_command(&mut session, "f", &input), Ok(true));
+        assert_eq!(session.debugger.cpu.stack.len(), 1);
+        assert!(session.debugger.cycles() > 0);
+    }
+
+    #[test]
+    fn test_breakpoint_commands() {
+        let mut session = session();
+        let input = no_input();
+        run_command(&mut session, "b 0x204 if V0 == 2", &input).unwrap();
+        run_command(&mut session, "break op 70??", &input).unwrap();
+        run_command(&mut session, "watch 0x300 2 w", &input).unwrap();
+
+        let debugger = &session.debugger;
+        assert_eq!(debugger.breakpoints.len(), 2);
+        assert_eq!(
+            debugger.breakpoints[0].to_string(),
+            "address 0x204 if V0 == 0x02"
+        );
+        assert_eq!(debugger.breakpoints[1], Breakpoint::opcode("70??").unwrap());
+        assert_eq!(debugger.watchpoints[0].range, 0x300..0x302);
+        assert_eq!(debugger.watchpoints[0].access, Access::Write);
+
+        assert!(run_command(&mut session, "b 0x204 unless V0", &input).is_err());
+        assert!(run_command(&mut session, "d 5", &input).is_err());
+        assert_eq!(run_command(&mut session, "d 1", &input), Ok(true));
+        assert_eq!(session.debugger.breakpoints.len(), 1);
+        assert_eq!(run_command(&mut session, "unwatch 0", &input), Ok(true));
+        assert!(session.debugger.watchpoints.is_empty());
+    }
+
+    #[test]
+    fn test_other_commands() {
+        let mut session = session();
+        let input = no_input();
+        assert!(run_command(&mut session, "bogus", &input).is_err());
+        assert!(run_command(&mut session, "key 10", &input).is_err());
+        assert_eq!(run_command(&mut session, "key a", &input), Ok(true));
+        assert!(session.debugger.cpu.is_key_pressed(0xA));
+
+        assert!(run_command(&mut session, "w 0x300 1 256", &input).is_err());
+        assert_eq!(run_command(&mut session, "w 0x300 1 2", &input), Ok(true));
+        assert_eq!(session.debugger.cpu.memory[0x300..0x302], [1, 2]);
+        assert_eq!(run_command(&mut session, "q", &input), Ok(false));
+    }
+}
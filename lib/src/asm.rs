@@ -0,0 +1,700 @@
+use crate::cpu::TXT_OFFSET;
+use crate::instruction::Instruction;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+// guard against include cycles and self referencing defines
+const MAX_INCLUDE_DEPTH: usize = 16;
+const MAX_DEFINE_DEPTH: usize = 32;
+
+/**
+ * An assembly error with the location it occurred at,
+ * lines and columns start at 1
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.file, self.line, self.column, self.message
+        )
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+pub type Result<T> = std::result::Result<T, AsmError>;
+
+/**
+ * A piece of source text and where it came from
+ */
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    file: String,
+    line: usize,
+    column: usize,
+}
+
+impl Token {
+    fn error<T, S: Into<String>>(&self, message: S) -> Result<T> {
+        Err(AsmError {
+            file: self.file.clone(),
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        })
+    }
+}
+
+/**
+ * A statement that emits bytes
+ */
+#[derive(Debug)]
+enum Statement {
+    Instruction {
+        mnemonic: Token,
+        operands: Vec<Token>,
+    },
+    Bytes(Vec<Token>),
+    Words(Vec<Token>),
+}
+
+impl Statement {
+    fn size(&self) -> usize {
+        match self {
+            Statement::Instruction { mnemonic, operands } => {
+                let long = mnemonic.text.eq_ignore_ascii_case("LD")
+                    && operands.len() == 2
+                    && upper(&operands[1].text).starts_with("LONG ");
+                if long {
+                    4
+                } else {
+                    2
+                }
+            }
+            Statement::Bytes(values) => values.len(),
+            Statement::Words(values) => values.len() * 2,
+        }
+    }
+}
+
+enum Symbol {
+    Label(usize),
+    Define(Token),
+}
+
+/**
+ * Two-pass assembler for the mnemonic syntax printed by
+ * the disassembler
+ *
+ * Supports `label:` definitions, `db`/`dw` data directives,
+ * `define NAME value` constants and `include "file"`.
+ * Comments start with `;`. The output is loaded at TXT_OFFSET.
+ *
+ * Register and keyword names such as V1, I, DT or B cannot be
+ * used as labels or constants. Bytes and words may be negative
+ * and are stored as two's complement.
+ */
+#[derive(Default)]
+pub struct Assembler {
+    statements: Vec<Statement>,
+    symbols: HashMap<String, Symbol>,
+    addr: usize,
+}
+
+/**
+ * Assemble source text, includes are resolved
+ * relative to the current directory
+ */
+pub fn assemble(source: &str) -> Result<Vec<u8>> {
+    let mut asm = Assembler::new();
+    asm.parse(source, "<input>", Path::new("."), 0)?;
+    asm.finish()
+}
+
+/**
+ * Assemble a source file, includes are resolved
+ * relative to the file's directory
+ */
+pub fn assemble_file<P: AsRef<Path>>(path: P) -> Result<Vec<u8>> {
+    let mut asm = Assembler::new();
+    asm.include(path.as_ref(), None, 0)?;
+    asm.finish()
+}
+
+fn upper(s: &str) -> String {
+    s.to_ascii_uppercase()
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+/**
+ * Split s on commas, returning each trimmed piece
+ * with its column in the line
+ */
+fn split_operands(s: &str, column: usize, file: &str, line: usize) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut in_string = false;
+    for (i, c) in s.char_indices().chain(std::iter::once((s.len(), ','))) {
+        match c {
+            '"' => in_string = !in_string,
+            ',' if !in_string => {
+                let piece = &s[start..i];
+                let trimmed = piece.trim_start();
+                tokens.push(Token {
+                    text: trimmed.trim_end().to_string(),
+                    file: file.to_string(),
+                    line,
+                    column: column + start + (piece.len() - trimmed.len()),
+                });
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    tokens
+}
+
+impl Assembler {
+    pub fn new() -> Self {
+        Assembler {
+            addr: TXT_OFFSET,
+            ..Default::default()
+        }
+    }
+
+    fn include(&mut self, path: &Path, at: Option<&Token>, depth: usize) -> Result<()> {
+        let name = path.display().to_string();
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => {
+                let message = format!("failed to read {}: {}", name, e);
+                return match at {
+                    Some(token) => token.error(message),
+                    None => Err(AsmError {
+                        file: name,
+                        line: 0,
+                        column: 0,
+                        message,
+                    }),
+                };
+            }
+        };
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        self.parse(&source, &name, dir, depth)
+    }
+
+    /**
+     * First pass, split the source into statements
+     * and assign addresses to labels
+     */
+    fn parse(&mut self, source: &str, file: &str, dir: &Path, depth: usize) -> Result<()> {
+        for (index, raw) in source.lines().enumerate() {
+            let line = index + 1;
+            let text = match raw.find(';') {
+                Some(comment) => &raw[..comment],
+                None => raw,
+            };
+
+            let mut column = text.len() - text.trim_start().len() + 1;
+            let mut rest = text.trim();
+
+            // any number of labels may prefix a statement
+            while let Some(colon) = rest.find(':') {
+                let name = rest[..colon].trim_end();
+                if !is_identifier(name) {
+                    break;
+                }
+                let token = Token {
+                    text: name.to_string(),
+                    file: file.to_string(),
+                    line,
+                    column,
+                };
+                self.define(token, Symbol::Label(self.addr))?;
+                let after = &rest[colon + 1..];
+                column += colon + 1 + (after.len() - after.trim_start().len());
+                rest = after.trim();
+            }
+
+            if rest.is_empty() {
+                continue;
+            }
+
+            let (word, args) = match rest.find(char::is_whitespace) {
+                Some(end) => (&rest[..end], rest[end..].trim_start()),
+                None => (rest, ""),
+            };
+            let args_column = column + (rest.len() - args.len());
+            let mnemonic = Token {
+                text: word.to_string(),
+                file: file.to_string(),
+                line,
+                column,
+            };
+            let operands = if args.is_empty() {
+                Vec::new()
+            } else {
+                split_operands(args, args_column, file, line)
+            };
+
+            let statement = match word.to_ascii_lowercase().as_str() {
+                "db" => Statement::Bytes(operands),
+                "dw" => Statement::Words(operands),
+                "define" => {
+                    let (name, value) = match args.find(char::is_whitespace) {
+                        Some(end) => (&args[..end], args[end..].trim()),
+                        None => return mnemonic.error("expected define NAME value"),
+                    };
+                    let name = Token {
+                        text: name.to_string(),
+                        file: file.to_string(),
+                        line,
+                        column: args_column,
+                    };
+                    if !is_identifier(&name.text) {
+                        return name.error(format!("invalid constant name '{}'", name.text));
+                    }
+                    let value = Token {
+                        text: value.to_string(),
+                        file: file.to_string(),
+                        line,
+                        column: args_column + (args.len() - value.len()),
+                    };
+                    self.define(name, Symbol::Define(value))?;
+                    continue;
+                }
+                "include" => {
+                    let target = Token {
+                        text: args.to_string(),
+                        file: file.to_string(),
+                        line,
+                        column: args_column,
+                    };
+                    let quoted = args.len() >= 2 && args.starts_with('"') && args.ends_with('"');
+                    if !quoted {
+                        return target.error("expected include \"file\"");
+                    }
+                    if depth >= MAX_INCLUDE_DEPTH {
+                        return target.error("includes nested too deeply");
+                    }
+                    let path: PathBuf = dir.join(&args[1..args.len() - 1]);
+                    self.include(&path, Some(&target), depth + 1)?;
+                    continue;
+                }
+                _ => Statement::Instruction { mnemonic, operands },
+            };
+
+            let size = statement.size();
+            self.statements.push(statement);
+            self.addr += size;
+        }
+        Ok(())
+    }
+
+    fn define(&mut self, name: Token, symbol: Symbol) -> Result<()> {
+        // registers and keywords are matched before symbols
+        if Operand::classify(&name.text) != Operand::Value {
+            return name.error(format!("'{}' is reserved and cannot be a name", name.text));
+        }
+        if self.symbols.contains_key(&name.text) {
+            return name.error(format!("'{}' is already defined", name.text));
+        }
+        self.symbols.insert(name.text, symbol);
+        Ok(())
+    }
+
+    /**
+     * Second pass, evaluate operands and encode every statement
+     */
+    fn finish(self) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        for statement in &self.statements {
+            match statement {
+                Statement::Bytes(values) => {
+                    for value in values {
+                        out.push(self.signed_value(value, 0xFF)? as u8);
+                    }
+                }
+                Statement::Words(values) => {
+                    for value in values {
+                        let word = self.signed_value(value, 0xFFFF)? as u16;
+                        out.extend_from_slice(&word.to_be_bytes());
+                    }
+                }
+                Statement::Instruction { mnemonic, operands } => {
+                    let inst = self.instruction(mnemonic, operands)?;
+                    out.extend_from_slice(&inst.to_bytes());
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /**
+     * Evaluate an expression of numbers, labels and constants
+     * joined by + and -, checking it fits in max
+     */
+    fn value(&self, token: &Token, max: usize) -> Result<usize> {
+        let value = self.evaluate(token, 0)?;
+        if value < 0 || value as usize > max {
+            return token.error(format!("value {} out of range (max 0x{:X})", value, max));
+        }
+        Ok(value as usize)
+    }
+
+    /**
+     * Like value, but negative values down to -(max + 1) / 2
+     * are accepted as two's complement, e.g. -1 is 0xFF
+     */
+    fn signed_value(&self, token: &Token, max: usize) -> Result<usize> {
+        let value = self.evaluate(token, 0)?;
+        let min = -(max as i64 + 1) / 2;
+        if value < min || value > max as i64 {
+            return token.error(format!(
+                "value {} out of range (min {}, max 0x{:X})",
+                value, min, max
+            ));
+        }
+        Ok((value & max as i64) as usize)
+    }
+
+    fn evaluate(&self, token: &Token, depth: usize) -> Result<i64> {
+        if depth > MAX_DEFINE_DEPTH {
+            return token.error("constant definition is recursive");
+        }
+
+        let text = token.text.as_str();
+        if text.is_empty() {
+            return token.error("expected a value");
+        }
+
+        let mut total = 0i64;
+        let mut sign = 1;
+        let mut start = 0;
+        // a leading minus negates the first term
+        if text.starts_with('-') {
+            sign = -1;
+            start = 1;
+        }
+        let bytes = text.as_bytes();
+        for i in 0..=bytes.len() {
+            let end = i == bytes.len();
+            if !end && !(i > start && (bytes[i] == b'+' || bytes[i] == b'-')) {
+                continue;
+            }
+            let raw = &text[start..i];
+            let term = raw.trim();
+            let column = token.column + start + (raw.len() - raw.trim_start().len());
+            let term_token = Token {
+                text: term.to_string(),
+                file: token.file.clone(),
+                line: token.line,
+                column,
+            };
+            total += sign * self.term(&term_token, depth)?;
+            if !end {
+                sign = if bytes[i] == b'+' { 1 } else { -1 };
+                start = i + 1;
+            }
+        }
+        Ok(total)
+    }
+
+    fn term(&self, token: &Token, depth: usize) -> Result<i64> {
+        let text = token.text.as_str();
+        let lower = text.to_ascii_lowercase();
+        let parsed = if let Some(hex) = lower.strip_prefix("0x") {
+            i64::from_str_radix(hex, 16).ok()
+        } else if let Some(bin) = lower.strip_prefix("0b") {
+            i64::from_str_radix(bin, 2).ok()
+        } else if text.starts_with(|c: char| c.is_ascii_digit()) {
+            text.parse().ok()
+        } else {
+            match self.symbols.get(text) {
+                Some(Symbol::Label(addr)) => Some(*addr as i64),
+                Some(Symbol::Define(value)) => return self.evaluate(value, depth + 1),
+                None if is_identifier(text) => {
+                    return token.error(format!("undefined symbol '{}'", text))
+                }
+                None => None,
+            }
+        };
+        match parsed {
+            Some(value) => Ok(value),
+            None => token.error(format!("invalid value '{}'", text)),
+        }
+    }
+
+    fn register(&self, token: &Token) -> Result<u8> {
+        match parse_register(&token.text) {
+            Some(reg) => Ok(reg),
+            None => token.error(format!("expected a register, found '{}'", token.text)),
+        }
+    }
+
+    fn range(&self, token: &Token) -> Result<(u8, u8)> {
+        let parts: Vec<&str> = token.text.split('-').map(str::trim).collect();
+        match parts.as_slice() {
+            [x, y] => match (parse_register(x), parse_register(y)) {
+                (Some(x), Some(y)) => Ok((x, y)),
+                _ => token.error(format!("expected Vx - Vy, found '{}'", token.text)),
+            },
+            _ => token.error(format!("expected Vx - Vy, found '{}'", token.text)),
+        }
+    }
+
+    /**
+     * Translate a mnemonic and its operands into an Instruction
+     */
+    fn instruction(&self, mnemonic: &Token, ops: &[Token]) -> Result<Instruction> {
+        use Instruction::*;
+
+        let name = upper(&mnemonic.text);
+        let kinds: Vec<Operand> = ops.iter().map(|op| Operand::classify(&op.text)).collect();
+
+        let expect = |count: usize| -> Result<()> {
+            if ops.len() == count {
+                Ok(())
+            } else {
+                mnemonic.error(format!(
+                    "{} expects {} operand(s), found {}",
+                    name,
+                    count,
+                    ops.len()
+                ))
+            }
+        };
+        let addr = |op: &Token| self.value(op, 0xFFF).map(|v| v as u16);
+        let byte = |op: &Token| self.signed_value(op, 0xFF).map(|v| v as u8);
+        let nibble = |op: &Token| self.value(op, 0xF).map(|v| v as u8);
+        let invalid = || -> Result<Instruction> {
+            let text: Vec<&str> = ops.iter().map(|op| op.text.as_str()).collect();
+            mnemonic.error(format!(
+                "invalid operands for {}: {}",
+                name,
+                text.join(", ")
+            ))
+        };
+
+        let inst = match name.as_str() {
+            "CLS" | "RET" | "SCR" | "SCL" | "EXIT" | "LOW" | "HIGH" | "AUDIO" => {
+                expect(0)?;
+                match name.as_str() {
+                    "CLS" => Cls,
+                    "RET" => Ret,
+                    "SCR" => ScrollRight,
+                    "SCL" => ScrollLeft,
+                    "EXIT" => Exit,
+                    "LOW" => Low,
+                    "HIGH" => High,
+                    _ => Audio,
+                }
+            }
+            "SYS" => {
+                expect(1)?;
+                Sys(addr(&ops[0])?)
+            }
+            "SCD" => {
+                expect(1)?;
+                ScrollDown(nibble(&ops[0])?)
+            }
+            "SCU" => {
+                expect(1)?;
+                ScrollUp(nibble(&ops[0])?)
+            }
+            "PLANE" => {
+                expect(1)?;
+                Plane(nibble(&ops[0])?)
+            }
+            "CALL" => {
+                expect(1)?;
+                Call(addr(&ops[0])?)
+            }
+            "JP" => match kinds.as_slice() {
+                [Operand::Value] => Jp(addr(&ops[0])?),
+                [Operand::Register(0), Operand::Value] => JpV0(addr(&ops[1])?),
+                _ => return invalid(),
+            },
+            "SE" | "SNE" => {
+                expect(2)?;
+                let x = self.register(&ops[0])?;
+                match (kinds[1], name.as_str()) {
+                    (Operand::Register(y), "SE") => SeVxVy { x, y },
+                    (Operand::Register(y), _) => SneVxVy { x, y },
+                    (Operand::Value, "SE") => SeVxByte {
+                        x,
+                        kk: byte(&ops[1])?,
+                    },
+                    (Operand::Value, _) => SneVxByte {
+                        x,
+                        kk: byte(&ops[1])?,
+                    },
+                    _ => return invalid(),
+                }
+            }
+            "ADD" => match kinds.as_slice() {
+                [Operand::Register(x), Operand::Register(y)] => AddVxVy { x: *x, y: *y },
+                [Operand::Register(x), Operand::Value] => AddVxByte {
+                    x: *x,
+                    kk: byte(&ops[1])?,
+                },
+                [Operand::Keyword("I"), Operand::Register(x)] => AddIVx { x: *x },
+                _ => return invalid(),
+            },
+            "OR" | "AND" | "XOR" | "SUB" | "SUBN" => {
+                expect(2)?;
+                let x = self.register(&ops[0])?;
+                let y = self.register(&ops[1])?;
+                match name.as_str() {
+                    "OR" => OrVxVy { x, y },
+                    "AND" => AndVxVy { x, y },
+                    "XOR" => XorVxVy { x, y },
+                    "SUB" => SubVxVy { x, y },
+                    _ => SubnVxVy { x, y },
+                }
+            }
+            "SHR" | "SHL" => {
+                let x = match ops.first() {
+                    Some(op) => self.register(op)?,
+                    None => return invalid(),
+                };
+                let y = match ops.len() {
+                    1 => x,
+                    2 => self.register(&ops[1])?,
+                    _ => return invalid(),
+                };
+                if name == "SHR" {
+                    ShrVxVy { x, y }
+                } else {
+                    ShlVxVy { x, y }
+                }
+            }
+            "RND" => {
+                expect(2)?;
+                Rnd {
+                    x: self.register(&ops[0])?,
+                    kk: byte(&ops[1])?,
+                }
+            }
+            "DRW" => {
+                expect(3)?;
+                Drw {
+                    x: self.register(&ops[0])?,
+                    y: self.register(&ops[1])?,
+                    n: nibble(&ops[2])?,
+                }
+            }
+            "SKP" | "SKNP" | "PITCH" => {
+                expect(1)?;
+                let x = self.register(&ops[0])?;
+                match name.as_str() {
+                    "SKP" => Skp { x },
+                    "SKNP" => Sknp { x },
+                    _ => Pitch { x },
+                }
+            }
+            "LD" => match kinds.as_slice() {
+                [Operand::Register(x), Operand::Register(y)] => LdVxVy { x: *x, y: *y },
+                [Operand::Register(x), Operand::Value] => LdVxByte {
+                    x: *x,
+                    kk: byte(&ops[1])?,
+                },
+                [Operand::Keyword("I"), Operand::Value] => LdI(addr(&ops[1])?),
+                [Operand::Keyword("I"), Operand::Long] => {
+                    let value = Token {
+                        text: ops[1].text[4..].trim().to_string(),
+                        column: ops[1].column + 5,
+                        ..ops[1].clone()
+                    };
+                    LdILong(self.value(&value, 0xFFFF)? as u16)
+                }
+                [Operand::Register(x), Operand::Keyword("DT")] => LdVxDt { x: *x },
+                [Operand::Register(x), Operand::Keyword("K")] => LdVxK { x: *x },
+                [Operand::Keyword("DT"), Operand::Register(x)] => LdDtVx { x: *x },
+                [Operand::Keyword("ST"), Operand::Register(x)] => LdStVx { x: *x },
+                [Operand::Keyword("F"), Operand::Register(x)] => LdFVx { x: *x },
+                [Operand::Keyword("HF"), Operand::Register(x)] => LdHfVx { x: *x },
+                [Operand::Keyword("B"), Operand::Register(x)] => LdBVx { x: *x },
+                [Operand::Keyword("[I]"), Operand::Register(x)] => LdIVx { x: *x },
+                [Operand::Register(x), Operand::Keyword("[I]")] => LdVxI { x: *x },
+                [Operand::Keyword("R"), Operand::Register(x)] => LdRVx { x: *x },
+                [Operand::Register(x), Operand::Keyword("R")] => LdVxR { x: *x },
+                [Operand::Keyword("[I]"), Operand::Range] => {
+                    let (x, y) = self.range(&ops[1])?;
+                    SaveRange { x, y }
+                }
+                [Operand::Range, Operand::Keyword("[I]")] => {
+                    let (x, y) = self.range(&ops[0])?;
+                    LoadRange { x, y }
+                }
+                _ => return invalid(),
+            },
+            _ => return mnemonic.error(format!("unknown instruction '{}'", mnemonic.text)),
+        };
+        Ok(inst)
+    }
+}
+
+fn parse_register(s: &str) -> Option<u8> {
+    let s = s.trim();
+    if s.len() != 2 || !s.starts_with(['V', 'v']) {
+        return None;
+    }
+    u8::from_str_radix(&s[1..], 16).ok()
+}
+
+/**
+ * The syntactic kind of an operand
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand {
+    Register(u8),
+    Range,
+    Keyword(&'static str),
+    Long,
+    Value,
+}
+
+impl Operand {
+    fn classify(text: &str) -> Operand {
+        const KEYWORDS: [&str; 8] = ["I", "[I]", "DT", "ST", "K", "F", "HF", "B"];
+
+        let up = upper(text);
+        if let Some(reg) = parse_register(text) {
+            return Operand::Register(reg);
+        }
+        if let Some(keyword) = KEYWORDS.iter().find(|k| **k == up) {
+            return Operand::Keyword(keyword);
+        }
+        if up == "R" {
+            return Operand::Keyword("R");
+        }
+        if up.starts_with("LONG ") {
+            return Operand::Long;
+        }
+        let parts: Vec<&str> = text.split('-').collect();
+        if parts.len() == 2 && parts.iter().all(|p| parse_register(p).is_some()) {
+            return Operand::Range;
+        }
+        Operand::Value
+    }
+}
@@ -0,0 +1,182 @@
+use crate::cpu::Cpu;
+use crate::machine::FrameEvents;
+use std::f32::consts::PI;
+use std::io::{self, Write};
+
+// rate the sound timer counts down at
+const TIMER_HZ: u32 = 60;
+
+// bits in the XO-CHIP audio pattern buffer
+const PATTERN_BITS: f32 = 128.0;
+
+/**
+ * Shape of the generated tone
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    Square,
+    Triangle,
+    Sawtooth,
+    Sine,
+    /// The XO-CHIP audio pattern buffer played at the
+    /// rate set by PITCH, frequency is ignored
+    Pattern,
+}
+
+impl std::str::FromStr for Waveform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "square" => Ok(Waveform::Square),
+            "triangle" => Ok(Waveform::Triangle),
+            "sawtooth" | "saw" => Ok(Waveform::Sawtooth),
+            "sine" => Ok(Waveform::Sine),
+            "pattern" => Ok(Waveform::Pattern),
+            _ => Err(format!(
+                "unknown waveform '{}', expected square, triangle, sawtooth, sine or pattern",
+                s
+            )),
+        }
+    }
+}
+
+/**
+ * Generates mono PCM samples in [-1.0, 1.0] for the
+ * tone played while the sound timer is non-zero
+ */
+#[derive(Debug, Clone)]
+pub struct Synth {
+    pub sample_rate: u32,
+    pub frequency: f32,
+    pub volume: f32,
+    pub waveform: Waveform,
+    pattern: [u8; 16],
+    pitch: u8,
+    // position in the current period, in [0, 1)
+    phase: f32,
+}
+
+impl Synth {
+    /**
+     * A 440Hz square wave at a quarter volume
+     */
+    pub fn new(sample_rate: u32) -> Self {
+        Synth {
+            sample_rate,
+            frequency: 440.0,
+            volume: 0.25,
+            waveform: Waveform::Square,
+            pattern: [0; 16],
+            pitch: 64,
+            phase: 0.0,
+        }
+    }
+
+    /**
+     * Number of samples generated per 60Hz timer tick
+     */
+    pub fn samples_per_frame(&self) -> usize {
+        (self.sample_rate / TIMER_HZ) as usize
+    }
+
+    /**
+     * Copy the XO-CHIP audio pattern and pitch from the CPU
+     */
+    pub fn sync(&mut self, cpu: &Cpu) {
+        self.pattern = cpu.audio_pattern;
+        self.pitch = cpu.pitch;
+    }
+
+    /**
+     * Fill out with the tone if active or silence otherwise,
+     * the phase carries over between calls so consecutive
+     * buffers join without clicks
+     */
+    pub fn fill(&mut self, active: bool, out: &mut [f32]) {
+        if !active {
+            out.iter_mut().for_each(|s| *s = 0.0);
+            self.phase = 0.0;
+            return;
+        }
+
+        let step = match self.waveform {
+            // 4000Hz at the default pitch of 64, one octave per 48 steps
+            Waveform::Pattern => {
+                let rate = 4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0);
+                rate / PATTERN_BITS / self.sample_rate as f32
+            }
+            _ => self.frequency / self.sample_rate as f32,
+        };
+
+        for sample in out.iter_mut() {
+            *sample = self.volume * self.sample_at(self.phase);
+            self.phase = (self.phase + step).fract();
+        }
+    }
+
+    /**
+     * Generate one timer tick worth of samples for a frame
+     * returned by Machine::run_frame. The tone sounds for
+     * every frame the timer was running, including the one
+     * where it ticked down to zero.
+     */
+    pub fn render_frame(&mut self, cpu: &Cpu, events: &FrameEvents) -> Vec<f32> {
+        self.sync(cpu);
+        let mut out = vec![0.0; self.samples_per_frame()];
+        self.fill(events.sound_on || events.sound_stopped, &mut out);
+        out
+    }
+
+    fn sample_at(&self, phase: f32) -> f32 {
+        match self.waveform {
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+            Waveform::Sine => (2.0 * PI * phase).sin(),
+            Waveform::Pattern => {
+                let bit = (phase * PATTERN_BITS) as usize;
+                if self.pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+        }
+    }
+}
+
+/**
+ * Write mono samples as a 16 bit PCM WAV file
+ */
+pub fn write_wav<W: Write>(mut out: W, sample_rate: u32, samples: &[f32]) -> io::Result<()> {
+    let data_len = samples.len() as u32 * 2;
+
+    out.write_all(b"RIFF")?;
+    out.write_all(&(36 + data_len).to_le_bytes())?;
+    out.write_all(b"WAVE")?;
+
+    // format chunk: PCM, mono, 16 bits per sample
+    out.write_all(b"fmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    out.write_all(&1u16.to_le_bytes())?;
+    out.write_all(&1u16.to_le_bytes())?;
+    out.write_all(&sample_rate.to_le_bytes())?;
+    out.write_all(&(sample_rate * 2).to_le_bytes())?;
+    out.write_all(&2u16.to_le_bytes())?;
+    out.write_all(&16u16.to_le_bytes())?;
+
+    out.write_all(b"data")?;
+    out.write_all(&data_len.to_le_bytes())?;
+    for sample in samples {
+        let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        out.write_all(&value.to_le_bytes())?;
+    }
+    out.flush()
+}
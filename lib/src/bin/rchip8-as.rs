@@ -0,0 +1,47 @@
+use rchip8::asm::assemble_file;
+use std::path::Path;
+use std::process::exit;
+
+const USAGE: &str = "usage: rchip8-as <source.asm> [-o <rom.ch8>]";
+
+/**
+ * Assemble a source file into a ROM
+ */
+fn main() {
+    let mut input = None;
+    let mut output = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output = args.next(),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ => input = Some(arg),
+        }
+    }
+
+    let input = input.unwrap_or_else(|| {
+        eprintln!("{}", USAGE);
+        exit(2);
+    });
+    let output = output.unwrap_or_else(|| {
+        Path::new(&input)
+            .with_extension("ch8")
+            .display()
+            .to_string()
+    });
+
+    let rom = assemble_file(&input).unwrap_or_else(|e| {
+        eprintln!("[!] {}", e);
+        exit(1);
+    });
+
+    if let Err(e) = std::fs::write(&output, &rom) {
+        eprintln!("[!] failed to write {}: {}", output, e);
+        exit(1);
+    }
+    println!("[+] wrote {} bytes to {}", rom.len(), output);
+}
@@ -0,0 +1,44 @@
+use rchip8::cpu::Mode;
+use rchip8::disasm::disassemble;
+use std::process::exit;
+
+const USAGE: &str = "usage: rchip8-dis [--mode chip8|schip|xochip] <rom.ch8>";
+
+/**
+ * Print a labelled listing of a ROM
+ */
+fn main() {
+    let mut mode = Mode::Chip8;
+    let mut path = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-m" | "--mode" => {
+                let value = args.next().unwrap_or_default();
+                mode = value.parse().unwrap_or_else(|e| {
+                    eprintln!("[!] {}", e);
+                    exit(2);
+                });
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ => path = Some(arg),
+        }
+    }
+
+    let path = path.unwrap_or_else(|| {
+        eprintln!("{}", USAGE);
+        exit(2);
+    });
+
+    let rom = std::fs::read(&path).unwrap_or_else(|e| {
+        eprintln!("[!] failed to read {}: {}", path, e);
+        exit(1);
+    });
+
+    println!("; {} ({} bytes)", path, rom.len());
+    print!("{}", disassemble(&rom, mode));
+}
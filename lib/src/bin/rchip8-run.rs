@@ -0,0 +1,310 @@
+use rchip8::audio::{self, Synth};
+use rchip8::cpu::{Cpu, Mode};
+use rchip8::error::Chip8Error;
+use rchip8::machine::{Machine, DEFAULT_CYCLES_PER_FRAME, FRAME_DURATION};
+use rchip8::rng::SplitMix64;
+use rchip8::trace::{JsonLines, OpClass, TextLog, Tracer};
+use std::fmt;
+use std::fs::File;
+use std::io::BufWriter;
+use std::ops::Range;
+use std::process::exit;
+use std::time::Instant;
+
+const USAGE: &str = "\
+usage: rchip8-run [options] <rom.ch8>
+
+options:
+  -m, --mode <chip8|schip|xochip>  instruction set (default chip8)
+  -i, --ipf <n>                    instructions per 60Hz frame (default 10)
+  -f, --frames <n>                 stop after n frames (default 600)
+  -c, --cycles <n>                 stop after n instructions
+  -s, --seed <n>                   seed for RND, random if omitted
+  -r, --realtime                   sleep to run frames at 60Hz
+  -q, --quiet                      don't print the framebuffer
+  -w, --wav <file>                 record the sound timer tone as a WAV file
+  -t, --trace <file>               log every instruction, as JSON Lines if
+                                   the file ends in .jsonl, text otherwise
+      --trace-range <from>-<to>    only trace instructions in [from, to)
+      --trace-class <class,..>     only trace flow, alu, memory, display,
+                                   input, timer or sound instructions";
+
+/**
+ * Why the run ended
+ */
+enum Status {
+    Exited,
+    FrameLimit,
+    CycleLimit,
+    WaitingForKey,
+    Fault(Chip8Error),
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Exited => write!(f, "exited"),
+            Status::FrameLimit => write!(f, "frame limit reached"),
+            Status::CycleLimit => write!(f, "cycle limit reached"),
+            Status::WaitingForKey => write!(f, "waiting for a key press"),
+            Status::Fault(e) => write!(f, "fault: {}", e),
+        }
+    }
+}
+
+struct Options {
+    mode: Mode,
+    ipf: usize,
+    frames: u64,
+    cycles: Option<u64>,
+    seed: Option<u64>,
+    realtime: bool,
+    quiet: bool,
+    wav: Option<String>,
+    trace: Option<String>,
+    trace_range: Option<Range<usize>>,
+    trace_classes: Option<Vec<OpClass>>,
+    path: String,
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    exit(2);
+}
+
+fn parse_args() -> Options {
+    let mut options = Options {
+        mode: Mode::Chip8,
+        ipf: DEFAULT_CYCLES_PER_FRAME,
+        frames: 600,
+        cycles: None,
+        seed: None,
+        realtime: false,
+        quiet: false,
+        wav: None,
+        trace: None,
+        trace_range: None,
+        trace_classes: None,
+        path: String::new(),
+    };
+
+    let mut args = std::env::args().skip(1);
+    let number = |name: &str, value: Option<String>| -> u64 {
+        value.and_then(|v| v.parse().ok()).unwrap_or_else(|| {
+            eprintln!("[!] {} expects a number", name);
+            exit(2);
+        })
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-m" | "--mode" => {
+                let value = args.next().unwrap_or_default();
+                options.mode = value.parse().unwrap_or_else(|e| {
+                    eprintln!("[!] {}", e);
+                    exit(2);
+                });
+            }
+            "-i" | "--ipf" => options.ipf = number(&arg, args.next()) as usize,
+            "-f" | "--frames" => options.frames = number(&arg, args.next()),
+            "-c" | "--cycles" => options.cycles = Some(number(&arg, args.next())),
+            "-s" | "--seed" => options.seed = Some(number(&arg, args.next())),
+            "-r" | "--realtime" => options.realtime = true,
+            "-q" | "--quiet" => options.quiet = true,
+            "-w" | "--wav" => options.wav = Some(args.next().unwrap_or_else(|| usage())),
+            "-t" | "--trace" => options.trace = Some(args.next().unwrap_or_else(|| usage())),
+            "--trace-range" => {
+                let value = args.next().unwrap_or_default();
+                options.trace_range = Some(address_range(&value).unwrap_or_else(|| {
+                    eprintln!("[!] --trace-range expects <from>-<to>");
+                    exit(2);
+                }));
+            }
+            "--trace-class" => {
+                let value = args.next().unwrap_or_default();
+                let classes: Result<Vec<OpClass>, String> =
+                    value.split(',').map(|c| c.trim().parse()).collect();
+                options.trace_classes = Some(classes.unwrap_or_else(|e| {
+                    eprintln!("[!] {}", e);
+                    exit(2);
+                }));
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                exit(0);
+            }
+            _ if arg.starts_with('-') => usage(),
+            _ => options.path = arg,
+        }
+    }
+
+    if options.path.is_empty() {
+        usage();
+    }
+    options
+}
+
+/**
+ * Parse `from-to`, each decimal or 0x prefixed
+ */
+fn address_range(s: &str) -> Option<Range<usize>> {
+    let parse = |s: &str| match s.trim().strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => s.trim().parse().ok(),
+    };
+    let dash = s.find('-')?;
+    Some(parse(&s[..dash])?..parse(&s[dash + 1..])?)
+}
+
+/**
+ * A tracer writing to path, as JSON Lines or text
+ */
+fn tracer(path: &str, options: &Options) -> std::io::Result<Tracer> {
+    let out = BufWriter::new(File::create(path)?);
+    let mut tracer = if path.ends_with(".jsonl") {
+        Tracer::new().with_sink(JsonLines::new(out))
+    } else {
+        Tracer::new().with_sink(TextLog::new(out))
+    };
+    if let Some(range) = &options.trace_range {
+        tracer = tracer.with_addresses(range.clone());
+    }
+    if let Some(classes) = &options.trace_classes {
+        tracer = tracer.with_classes(classes);
+    }
+    Ok(tracer)
+}
+
+/**
+ * Run frames of ipf instructions followed by a single
+ * timer tick until a limit is reached or the program stops
+ */
+fn run(
+    machine: &mut Machine,
+    options: &Options,
+    mut synth: Option<&mut (Synth, Vec<f32>)>,
+) -> (Status, u64, u64) {
+    let mut cycles = 0u64;
+    let mut frames = 0u64;
+
+    loop {
+        if frames >= options.frames {
+            return (Status::FrameLimit, frames, cycles);
+        }
+        if let Some(limit) = options.cycles {
+            if cycles >= limit {
+                return (Status::CycleLimit, frames, cycles);
+            }
+            // shorten the last frame to stop exactly at the limit
+            let remaining = (limit - cycles).min(options.ipf as u64);
+            machine.set_cycles_per_frame(remaining as usize);
+        }
+
+        let start = Instant::now();
+        let events = machine.run_frame();
+        cycles += events.cycles as u64;
+        frames += 1;
+
+        if let Some((synth, samples)) = synth.as_mut() {
+            samples.extend(synth.render_frame(&machine.cpu, &events));
+        }
+
+        if let Some(e) = events.fault {
+            return (Status::Fault(e), frames, cycles);
+        }
+        if events.exited {
+            return (Status::Exited, frames, cycles);
+        }
+        if events.waiting_for_key {
+            return (Status::WaitingForKey, frames, cycles);
+        }
+
+        if options.realtime {
+            if let Some(remaining) = FRAME_DURATION.checked_sub(start.elapsed()) {
+                std::thread::sleep(remaining);
+            }
+        }
+    }
+}
+
+/**
+ * Render the framebuffer as text, one character per pixel
+ */
+fn framebuffer(cpu: &Cpu) -> String {
+    let (width, height) = cpu.resolution();
+    let mut out = String::new();
+    for y in 0..height {
+        out.extend((0..width).map(|x| if cpu.display.get(x, y) != 0 { '#' } else { '.' }));
+        out.push('\n');
+    }
+    out
+}
+
+/**
+ * Run a ROM without a browser
+ */
+fn main() {
+    let options = parse_args();
+
+    let mut cpu = Cpu::with_mode(options.mode);
+    if let Some(seed) = options.seed {
+        cpu.set_rng(SplitMix64::new(seed));
+    }
+    if let Err(e) = cpu.load_program(&options.path) {
+        eprintln!("[!] failed to load {}: {}", options.path, e);
+        exit(1);
+    }
+    if let Some(path) = &options.trace {
+        let tracer = tracer(path, &options).unwrap_or_else(|e| {
+            eprintln!("[!] failed to create {}: {}", path, e);
+            exit(1);
+        });
+        cpu.set_tracer(tracer);
+    }
+
+    let mut machine = Machine::new(cpu);
+    machine.set_cycles_per_frame(options.ipf);
+    let mut audio = options
+        .wav
+        .as_ref()
+        .map(|_| (Synth::new(44100), Vec::new()));
+    let (status, frames, cycles) = run(&mut machine, &options, audio.as_mut());
+    // flushes the trace file
+    drop(machine.cpu.take_tracer());
+    let cpu = &machine.cpu;
+
+    if let (Some(path), Some((synth, samples))) = (&options.wav, &audio) {
+        let written = std::fs::File::create(path).and_then(|file| {
+            audio::write_wav(std::io::BufWriter::new(file), synth.sample_rate, samples)
+        });
+        if let Err(e) = written {
+            eprintln!("[!] failed to write {}: {}", path, e);
+            exit(1);
+        }
+    }
+
+    if !options.quiet {
+        print!("{}", framebuffer(cpu));
+    }
+    for (i, value) in cpu.registers.iter().enumerate() {
+        print!(
+            "V{:X}={:02X}{}",
+            i,
+            value,
+            if i % 8 == 7 { "\n" } else { " " }
+        );
+    }
+    println!(
+        "PC={:03X} I={:03X} DT={:02X} ST={:02X} SP={}",
+        cpu.program_counter,
+        cpu.i_register,
+        cpu.delay_timer,
+        cpu.sound_timer,
+        cpu.stack.len()
+    );
+    println!("[*] {} after {} frames, {} cycles", status, frames, cycles);
+
+    if let Status::Fault(_) = status {
+        exit(1);
+    }
+}
@@ -1,20 +1,167 @@
+use crate::error::{Chip8Error, Result};
+use crate::framebuffer::Framebuffer;
+use crate::instruction::Instruction;
 use crate::instructions::inst;
-use anyhow::{bail, Result};
-use bitvec::prelude::*;
+use crate::keymap::Keymap;
+use crate::peripherals::{Buzzer, Display, Keyboard, Keypad, NullBuzzer, NullDisplay};
+use crate::quirks::Quirks;
+use crate::rng::{RandomSource, SplitMix64};
+use crate::snapshot::{Reader, Writer};
+use crate::trace::{TraceEntry, Tracer};
 use byteorder::{BigEndian, ByteOrder};
 use std::fs::File;
 use std::io::Read;
+use std::ops::Range;
 
 pub const MEM_SIZE: usize = 0xFFF;
+pub const XO_MEM_SIZE: usize = 0x10000;
 pub const TXT_OFFSET: usize = 0x200;
 pub const FLAG_REGISTER: usize = 15; // VF register
+pub const STACK_SIZE: usize = 16; // nested subroutine limit
 
 pub const DISP_WIDTH: usize = 64;
 pub const DISP_HEIGHT: usize = 32;
 
+// frames an erased pixel keeps glowing for, see Cpu::phosphor_decay
+pub const DEFAULT_PHOSPHOR_DECAY: u8 = 2;
+
+// dirty regions kept before they are merged into their bounding box
+const MAX_DIRTY_REGIONS: usize = 16;
+
+// SCHIP high resolution mode
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+
+// the large SCHIP font is stored right after FONT_SET
+pub const BIG_FONT_OFFSET: usize = 0x50;
+
+// save state header, the version is bumped
+// whenever the layout changes
+const STATE_MAGIC: &[u8; 4] = b"RC8S";
+const STATE_VERSION: u16 = 4;
+
+/**
+ * The instruction set the CPU decodes
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// The original CHIP-8 instruction set
+    Chip8,
+    /// SUPER-CHIP 1.1, adds scrolling, 128x64 high
+    /// resolution mode, 16x16 sprites and RPL flags
+    SuperChip,
+    /// XO-CHIP (Octo), extends SUPER-CHIP with 64K of
+    /// memory, two drawing planes and an audio pattern buffer
+    XoChip,
+}
+
+impl std::str::FromStr for Mode {
+    type Err = String;
+
+    /**
+     * Parse a mode name as given on the command line
+     */
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "chip8" | "chip-8" => Ok(Mode::Chip8),
+            "schip" | "superchip" | "super-chip" => Ok(Mode::SuperChip),
+            "xochip" | "xo-chip" => Ok(Mode::XoChip),
+            _ => Err(format!(
+                "unknown mode '{}', expected chip8, schip or xochip",
+                s
+            )),
+        }
+    }
+}
+
+/**
+ * Progress of Fx0A - LD Vx, K
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyWait {
+    /// Not waiting, instructions execute normally
+    Idle,
+    /// Waiting for a key to be pressed, keys in the held
+    /// bitmask were already down and must be released first
+    Press { x: u8, held: u16 },
+    /// Key was pressed, waiting for its release
+    /// before storing it in Vx
+    Release { x: u8, key: u8 },
+}
+
+impl Mode {
+    /**
+     * The quirks profile ROMs written for this mode expect
+     */
+    pub fn default_quirks(self) -> Quirks {
+        match self {
+            Mode::Chip8 => Quirks::default(),
+            Mode::SuperChip => Quirks::schip(),
+            Mode::XoChip => Quirks::xo_chip(),
+        }
+    }
+
+    /**
+     * Size of the addressable memory
+     */
+    pub fn memory_size(self) -> usize {
+        match self {
+            Mode::XoChip => XO_MEM_SIZE,
+            _ => MEM_SIZE,
+        }
+    }
+}
+
+/**
+ * A rectangle of display pixels
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn contains(&self, other: &Rect) -> bool {
+        other.x >= self.x
+            && other.y >= self.y
+            && other.x + other.width <= self.x + self.width
+            && other.y + other.height <= self.y + self.height
+    }
+
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.x < other.x + other.width
+            && other.x < self.x + self.width
+            && self.y < other.y + other.height
+            && other.y < self.y + self.height
+    }
+
+    /**
+     * The smallest rectangle covering both
+     */
+    pub fn union(&self, other: &Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = (self.x + self.width).max(other.x + other.width);
+        let bottom = (self.y + self.height).max(other.y + other.height);
+        Rect::new(x, y, right - x, bottom - y)
+    }
+}
+
 pub struct Cpu {
     pub stack: Vec<u16>,
-    pub memory: [u8; MEM_SIZE],
+    pub memory: Vec<u8>,
 
     pub registers: [u8; 16],
 
@@ -26,15 +173,53 @@ pub struct Cpu {
     // pc
     pub program_counter: usize,
 
-    // peripherals
-    pub keyboard: bitvec::vec::BitVec<LocalBits, usize>,
-    pub display: [u8; DISP_HEIGHT * DISP_WIDTH],
-    pub phosphor_glow: [u8; DISP_HEIGHT * DISP_WIDTH],
+    // peripherals, the display is resized
+    // when switching to/from high resolution mode,
+    // the glow keeps one byte per pixel
+    pub display: Framebuffer,
+    pub phosphor_glow: Vec<u8>,
+
+    // frames a pixel erased by DRW glows for before it
+    // goes dark, counted down by the renderer, 0 disables it
+    pub phosphor_decay: u8,
+
+    // display areas changed since take_dirty_regions
+    dirty: Vec<Rect>,
+
+    // SCHIP persistent user flags
+    pub rpl_flags: [u8; 16],
+
+    // XO-CHIP drawing planes selected by Fn01, each display
+    // pixel holds a bitmask of the planes it is lit on
+    pub planes: u8,
 
-    // internal state, true if halted
-    // to pause for a key press event
-    halted: bool,
-    store_key: usize,
+    // XO-CHIP audio pattern buffer and pitch register
+    pub audio_pattern: [u8; 16],
+    pub pitch: u8,
+
+    // instruction set and interpreter
+    // behavior for ambiguous opcodes
+    pub mode: Mode,
+    pub quirks: Quirks,
+
+    // host key codes accepted by key_down/key_up
+    pub keymap: Keymap,
+
+    // pluggable hardware, see the peripherals module
+    pub(crate) output: Box<dyn Display>,
+    pub(crate) keypad: Box<dyn Keypad>,
+    pub(crate) buzzer: Box<dyn Buzzer>,
+    pub(crate) rng: Box<dyn RandomSource>,
+
+    // internal state, execution is paused
+    // while Fx0A waits for a key
+    key_wait: KeyWait,
+
+    // true once the program executed 00FD
+    exited: bool,
+
+    // opt-in instruction tracing
+    tracer: Option<Tracer>,
 }
 
 pub static FONT_SET: [u8; 80] = [
@@ -56,31 +241,24 @@ pub static FONT_SET: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
-/**
- * Translate a modern key code to its
- * chip8 equivalent
- */
-fn translate_key(code: usize) -> usize {
-    match code {
-        49 => 1,   // 1
-        50 => 2,   // 2
-        51 => 3,   // 3
-        52 => 0xC, // 4
-        81 => 4,   // Q
-        87 => 5,   // W
-        69 => 6,   // E
-        82 => 0xD, // R
-        64 => 7,   // A
-        83 => 8,   // S
-        68 => 9,   // D
-        70 => 0xE, // F
-        90 => 0xA, // Z
-        88 => 0x0, // X
-        67 => 0xB, // C
-        86 => 0xF, // V
-        _ => 0,
-    }
-}
+pub static BIG_FONT_SET: [u8; 160] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
 
 impl Default for Cpu {
     fn default() -> Self {
@@ -93,25 +271,96 @@ impl Cpu {
      * Create a new CPU instance
      */
     pub fn new() -> Self {
+        Self::with_quirks(Quirks::default())
+    }
+
+    /**
+     * Create a new CPU instance that follows the given
+     * quirks profile
+     */
+    pub fn with_quirks(quirks: Quirks) -> Self {
+        let mut res = Self::with_mode(Mode::Chip8);
+        res.quirks = quirks;
+        res
+    }
+
+    /**
+     * Create a new CPU instance whose RND results are
+     * reproducible for a given seed
+     */
+    pub fn with_seed(seed: u64) -> Self {
+        let mut res = Self::new();
+        res.set_rng(SplitMix64::new(seed));
+        res
+    }
+
+    /**
+     * Create a new CPU instance decoding the given instruction
+     * set, using the quirks profile expected by that platform
+     */
+    pub fn with_mode(mode: Mode) -> Self {
         let mut res = Cpu {
             stack: Vec::<u16>::new(),
-            memory: [0; MEM_SIZE],
+            memory: vec![0; mode.memory_size()],
             registers: [0; 16],
             i_register: 0u16,
             program_counter: TXT_OFFSET,
             delay_timer: 0,
             sound_timer: 0,
-            keyboard: bitvec![mut 0u8; 16],
-            display: [0u8; DISP_HEIGHT * DISP_WIDTH],
-            phosphor_glow: [0u8; DISP_HEIGHT * DISP_WIDTH],
-            halted: false,
-            store_key: 0,
+            display: Framebuffer::new(DISP_WIDTH, DISP_HEIGHT),
+            phosphor_glow: vec![0u8; DISP_HEIGHT * DISP_WIDTH],
+            phosphor_decay: DEFAULT_PHOSPHOR_DECAY,
+            // nothing has been presented yet
+            dirty: vec![Rect::new(0, 0, DISP_WIDTH, DISP_HEIGHT)],
+            rpl_flags: [0; 16],
+            planes: 1,
+            audio_pattern: [0; 16],
+            pitch: 64,
+            mode,
+            quirks: mode.default_quirks(),
+            keymap: Keymap::default(),
+            output: Box::new(NullDisplay),
+            keypad: Box::new(Keyboard::default()),
+            buzzer: Box::new(NullBuzzer),
+            rng: Box::new(SplitMix64::from_entropy()),
+            key_wait: KeyWait::Idle,
+            exited: false,
+            tracer: None,
         };
 
         res.memory[0..FONT_SET.len()].copy_from_slice(&FONT_SET);
+        res.memory[BIG_FONT_OFFSET..BIG_FONT_OFFSET + BIG_FONT_SET.len()]
+            .copy_from_slice(&BIG_FONT_SET);
         res
     }
 
+    /**
+     * Replace the source of random bytes used by Cxkk
+     */
+    pub fn set_rng<R: RandomSource + 'static>(&mut self, rng: R) {
+        self.rng = Box::new(rng);
+    }
+
+    /**
+     * Send the framebuffer to the given display whenever it changes
+     */
+    pub fn set_display<D: Display + 'static>(&mut self, display: D) {
+        self.output = Box::new(display);
+    }
+
+    /**
+     * Read key state from the given keypad
+     */
+    pub fn set_keypad<K: Keypad + 'static>(&mut self, keypad: K) {
+        self.keypad = Box::new(keypad);
+    }
+
+    /**
+     * Drive the given buzzer from the sound timer
+     */
+    pub fn set_buzzer<B: Buzzer + 'static>(&mut self, buzzer: B) {
+        self.buzzer = Box::new(buzzer);
+    }
 
     /**
      * Load a chip8 program into memory
@@ -119,13 +368,17 @@ impl Cpu {
     pub fn load_program(&mut self, path: &str) -> Result<usize> {
         // sanity check the file size
         let metadata = std::fs::metadata(path)?;
-        if !metadata.is_file() && (metadata.len() > (MEM_SIZE - TXT_OFFSET) as u64) {
-            bail!("[!] ROM too large to load.")
+        let max = self.memory.len() - TXT_OFFSET;
+        if metadata.len() > max as u64 {
+            return Err(Chip8Error::RomTooLarge {
+                size: metadata.len() as usize,
+                max,
+            });
         }
 
         // load into memory at TXT_OFFSET
         let mut f = File::open(path)?;
-        let len = f.read(&mut self.memory[TXT_OFFSET..MEM_SIZE])?;
+        let len = f.read(&mut self.memory[TXT_OFFSET..])?;
         println!("[+] read {} bytes", len);
         Ok(len)
     }
@@ -134,8 +387,12 @@ impl Cpu {
      * Load a chip8 program into memory
      */
     pub fn load_from_bytes(&mut self, bytes: &[u8]) -> Result<()> {
-        if bytes.len() > MEM_SIZE - TXT_OFFSET {
-            bail!("[!] ROM too large to load.")
+        let max = self.memory.len() - TXT_OFFSET;
+        if bytes.len() > max {
+            return Err(Chip8Error::RomTooLarge {
+                size: bytes.len(),
+                max,
+            });
         }
         // the source is of unknown length, so we must get the length first
         let len = bytes.len();
@@ -144,29 +401,209 @@ impl Cpu {
     }
 
     /**
-     * Obtain a reference to the display buffer
+     * Obtain a reference to the display buffer along
+     * with the current (width, height) resolution
      */
-    pub fn get_display(&mut self) -> (&[u8], &mut [u8]) {
-        (&self.display, &mut self.phosphor_glow)
+    pub fn get_display(&mut self) -> (&Framebuffer, &mut [u8], (usize, usize)) {
+        let resolution = self.resolution();
+        (&self.display, &mut self.phosphor_glow, resolution)
     }
 
     /**
-     * Set the given key to the down position
-     * If halted, resume execution
+     * The current (width, height) of the display
      */
-    pub fn key_down(&mut self, key: usize) {
-        if self.halted {
-            self.halted = false;
-            self.registers[self.store_key] = translate_key(key) as u8;
+    pub fn resolution(&self) -> (usize, usize) {
+        (self.display.width(), self.display.height())
+    }
+
+    /**
+     * Resize the display, clearing its contents
+     */
+    pub(crate) fn set_resolution(&mut self, width: usize, height: usize) {
+        self.display = Framebuffer::new(width, height);
+        self.phosphor_glow = vec![0u8; width * height];
+        self.dirty = vec![Rect::new(0, 0, width, height)];
+    }
+
+    /**
+     * Record that pixels in rect changed
+     */
+    pub(crate) fn mark_dirty(&mut self, rect: Rect) {
+        if rect.width == 0 || rect.height == 0 || self.dirty.iter().any(|r| r.contains(&rect)) {
+            return;
+        }
+        self.dirty.retain(|r| !rect.contains(r));
+        self.dirty.push(rect);
+
+        if self.dirty.len() > MAX_DIRTY_REGIONS {
+            let bounds = self.dirty.iter().fold(rect, |acc, r| acc.union(r));
+            self.dirty = vec![bounds];
         }
-        self.keyboard.set(translate_key(key), true);
     }
 
     /**
-     * Set the given key to the up position
+     * The display areas changed since the last call, so
+     * front-ends can redraw only those and skip frames
+     * where nothing changed. Regions may overlap.
      */
-    pub fn key_up(&mut self, key: usize) {
-        self.keyboard.set(translate_key(key), false);
+    pub fn take_dirty_regions(&mut self) -> Vec<Rect> {
+        std::mem::take(&mut self.dirty)
+    }
+
+    /**
+     * True once the program has executed 00FD - EXIT
+     */
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    /**
+     * True while Fx0A is blocking execution until a key is pressed
+     */
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.key_wait != KeyWait::Idle
+    }
+
+    /**
+     * The current state of Fx0A
+     */
+    pub fn key_wait(&self) -> KeyWait {
+        self.key_wait
+    }
+
+    /**
+     * Stop waiting for a key, leaving Vx unchanged and
+     * resuming at the instruction after Fx0A
+     */
+    pub fn cancel_key_wait(&mut self) {
+        self.key_wait = KeyWait::Idle;
+    }
+
+    /**
+     * Bitmask of the keys currently held down
+     */
+    fn pressed_keys(&self) -> u16 {
+        (0..16).fold(0u16, |keys, key| {
+            keys | (self.keypad.is_pressed(key) as u16) << key
+        })
+    }
+
+    /**
+     * Advance Fx0A from the keypad state, completing on
+     * press or on release depending on the quirks
+     *
+     * This happens on key_down/key_up and before each
+     * instruction, keypads that change on their own can
+     * call it to complete the wait without executing
+     */
+    pub fn poll_key_wait(&mut self) {
+        let pressed = self.pressed_keys();
+        match self.key_wait {
+            KeyWait::Idle => {}
+            KeyWait::Press { x, held } => {
+                let new = pressed & !held;
+                if new == 0 {
+                    // keys released since the wait began count when pressed again
+                    self.key_wait = KeyWait::Press {
+                        x,
+                        held: held & pressed,
+                    };
+                    return;
+                }
+                let key = new.trailing_zeros() as u8;
+                if self.quirks.wait_for_release {
+                    self.key_wait = KeyWait::Release { x, key };
+                } else {
+                    self.registers[x as usize] = key;
+                    self.key_wait = KeyWait::Idle;
+                }
+            }
+            KeyWait::Release { x, key } => {
+                if pressed & (1 << key) == 0 {
+                    self.registers[x as usize] = key;
+                    self.key_wait = KeyWait::Idle;
+                }
+            }
+        }
+    }
+
+    /**
+     * True if no instruction may run, either because the
+     * program exited or Fx0A is still waiting for a key
+     */
+    fn is_blocked(&mut self) -> bool {
+        if self.is_waiting_for_key() {
+            self.poll_key_wait();
+        }
+        self.exited || self.is_waiting_for_key()
+    }
+
+    /**
+     * Stop executing instructions
+     */
+    pub(crate) fn exit(&mut self) {
+        self.exited = true;
+    }
+
+    /**
+     * Press the CHIP-8 key mapped to a host key code,
+     * unmapped codes are ignored
+     */
+    pub fn key_down(&mut self, code: usize) {
+        if let Some(key) = self.keymap.translate(code) {
+            self.key_down_chip8(key);
+        }
+    }
+
+    /**
+     * Release the CHIP-8 key mapped to a host key code,
+     * unmapped codes are ignored
+     */
+    pub fn key_up(&mut self, code: usize) {
+        if let Some(key) = self.keymap.translate(code) {
+            self.key_up_chip8(key);
+        }
+    }
+
+    /**
+     * Set CHIP-8 key 0-F to the down position
+     * This may complete a pending Fx0A
+     */
+    pub fn key_down_chip8(&mut self, key: u8) {
+        if key <= 0xF {
+            self.keypad.set_pressed(key, true);
+            self.poll_key_wait();
+        }
+    }
+
+    /**
+     * Set CHIP-8 key 0-F to the up position
+     * This may complete a pending Fx0A
+     */
+    pub fn key_up_chip8(&mut self, key: u8) {
+        if key <= 0xF {
+            self.keypad.set_pressed(key, false);
+            self.poll_key_wait();
+        }
+    }
+
+    /**
+     * True if key 0-F is held down, keypads are
+     * never asked about keys past F
+     */
+    pub fn is_key_pressed(&self, key: u8) -> bool {
+        key < 16 && self.keypad.is_pressed(key)
+    }
+
+    /**
+     * Set the sound timer, starting or stopping the buzzer
+     */
+    pub(crate) fn set_sound_timer(&mut self, value: u8) {
+        let was_active = self.sound_timer > 0;
+        self.sound_timer = value;
+        if was_active != (value > 0) {
+            self.buzzer.set_active(value > 0);
+        }
     }
 
     /**
@@ -177,15 +614,81 @@ impl Cpu {
             self.delay_timer -= 1;
         }
         if self.sound_timer > 0 {
-            self.sound_timer -= 1;
+            self.set_sound_timer(self.sound_timer - 1);
+        }
+    }
+
+    /**
+     * Skip over the next instruction, in XO-CHIP mode this
+     * is 4 bytes long when it is F000 NNNN
+     */
+    pub(crate) fn skip_next_instruction(&mut self) {
+        let next = self.program_counter + 2;
+        let long =
+            self.mode == Mode::XoChip && self.memory.get(next..next + 2) == Some(&[0xF0, 0x00]);
+        self.program_counter += if long { 4 } else { 2 };
+    }
+
+    /**
+     * Bounds check an access of len bytes starting at addr
+     */
+    pub(crate) fn memory_range(&self, addr: usize, len: usize) -> Result<Range<usize>> {
+        match addr.checked_add(len) {
+            Some(end) if end <= self.memory.len() => Ok(addr..end),
+            _ => Err(Chip8Error::MemoryOutOfBounds {
+                addr: addr.max(self.memory.len()),
+                pc: self.program_counter,
+            }),
+        }
+    }
+
+    /**
+     * Sprite (columns, rows) drawn by Dxyn, n = 0 draws
+     * a 16x16 sprite outside of CHIP-8 mode
+     */
+    pub(crate) fn sprite_size(&self, n: u8) -> (usize, usize) {
+        match n {
+            0 if self.mode != Mode::Chip8 => (16, 16),
+            _ => (8, n as usize),
+        }
+    }
+
+    /**
+     * The memory an instruction will read and write when
+     * executed in the current state, as (reads, writes).
+     * Instruction fetches are not included and the ranges
+     * are not bounds checked.
+     */
+    pub fn memory_accesses(&self, inst: &Instruction) -> (Range<usize>, Range<usize>) {
+        let i = self.i_register as usize;
+        let none = i..i;
+        let span = |len: usize| i..i + len;
+        let between = |x: u8, y: u8| (x as isize - y as isize).unsigned_abs() + 1;
+
+        match *inst {
+            Instruction::Drw { n, .. } => {
+                let (cols, rows) = self.sprite_size(n);
+                (
+                    span(rows * cols / 8 * self.planes.count_ones() as usize),
+                    none,
+                )
+            }
+            Instruction::LdVxI { x } => (span(x as usize + 1), none),
+            Instruction::LoadRange { x, y } => (span(between(x, y)), none),
+            Instruction::Audio => (span(self.audio_pattern.len()), none),
+            Instruction::LdBVx { .. } => (none, span(3)),
+            Instruction::LdIVx { x } => (none, span(x as usize + 1)),
+            Instruction::SaveRange { x, y } => (none, span(between(x, y))),
+            _ => (none.clone(), none),
         }
     }
 
     /**
      * Fetch the next 16 bit opcode from memory
      */
-    pub fn fetch_instruction(&self) -> u16 {
-        BigEndian::read_u16(&self.memory[self.program_counter..self.program_counter + 2])
+    pub fn fetch_instruction(&self) -> Result<u16> {
+        let range = self.memory_range(self.program_counter, 2)?;
+        Ok(BigEndian::read_u16(&self.memory[range]))
     }
 
     /**
@@ -193,63 +696,357 @@ impl Cpu {
      */
     pub fn execute_instruction(&mut self, opcode: u16) -> Result<()> {
         // All execution will be halted until
-        // a key down event occurs
-        if self.halted {
+        // Fx0A receives a key
+        if self.is_blocked() {
             return Ok(());
         }
 
-        let nums = [
-            (opcode >> 12) & 0xF,
-            (opcode >> 8) & 0xF,
-            (opcode >> 4) & 0xF,
-            opcode & 0xF,
-        ];
-
-        match nums {
-            [0, 0, 0xE, 0] => inst::cls(self),
-            [0, 0, 0xE, 0xE] => inst::ret(self),
-            [0, _, _, _] => inst::sys(),
-            [1, _, _, _] => inst::jmp_nnn(self, opcode),
-            [2, _, _, _] => inst::call(self, opcode),
-            [3, x, _, _] => inst::se_vx_kk(self, x, opcode),
-            [4, x, _, _] => inst::sne_vx_kk(self, x, opcode),
-            [5, x, y, _] => inst::se_vx_vy(self, x, y),
-            [6, x, _, _] => inst::ld_vx(self, x, opcode),
-            [7, x, _, _] => inst::add_vx_kk(self, x, opcode),
-            [8, x, y, 0] => inst::ld_vx_vy(self, x, y),
-            [8, x, y, 1] => inst::or_vx_vy(self, x, y),
-            [8, x, y, 2] => inst::and_vx_vy(self, x, y),
-            [8, x, y, 3] => inst::xor_vx_vy(self, x, y),
-            [8, x, y, 4] => inst::add_vx_vy(self, x, y),
-            [8, x, y, 5] => inst::sub_vx_vy(self, x, y),
-            [8, x, y, 6] => inst::shr_vx_vy(self, x, y),
-            [8, x, y, 7] => inst::subn_vx_vy(self, x, y),
-            [8, x, y, 0xE] => inst::shl_vx_vy(self, x, y),
-            [9, x, y, 0] => inst::sne_vx_vy(self, x, y),
-            [0xA, _, _, _] => inst::ld_i_nnn(self, opcode),
-            [0xB, _, _, _] => inst::jmp_v0_nnn(self, opcode),
-            [0xC, x, _, _] => inst::rnd_vx_kk(self, x, opcode),
-            [0xD, x, y, n] => inst::drw_vx_vy_n(self, x, y, n),
-            [0xE, x, 9, 0xE] => inst::skp_vx(self, x),
-            [0xE, x, 0xA, 1] => inst::sknp_vx(self, x),
-            [0xF, x, 0, 7] => inst::ld_vx_dt(self, x),
-            [0xF, x, 0, 0xA] => {
-                self.halted = true;
-                self.store_key = x.into();
+        let instruction = match Instruction::decode(opcode) {
+            Ok(inst) if inst.available_in(self.mode) => inst,
+            // the SCHIP/XO-CHIP 00xx opcodes are SYS calls on older platforms
+            Ok(_) if opcode & 0xF000 == 0 => Instruction::Sys(opcode & 0x0FFF),
+            // before XO-CHIP the low nibble of 5xyN was ignored
+            _ if opcode & 0xF000 == 0x5000 && self.mode != Mode::XoChip => Instruction::SeVxVy {
+                x: (opcode >> 8 & 0xF) as u8,
+                y: (opcode >> 4 & 0xF) as u8,
+            },
+            _ => {
+                let pc = self.program_counter;
+                let err = Chip8Error::InvalidOpcode { opcode, pc };
+                if let Some(tracer) = self.tracer.as_mut().filter(|t| t.wants_address(pc)) {
+                    tracer.record(&TraceEntry {
+                        pc,
+                        opcode,
+                        instruction: None,
+                        registers: Vec::new(),
+                        i: None,
+                        memory: Vec::new(),
+                        fault: Some(err.to_string()),
+                    });
+                }
+                return Err(err);
             }
-            [0xF, x, 1, 5] => inst::ld_dt_vx(self, x),
-            [0xF, x, 1, 8] => inst::ld_st_vx(self, x),
-            [0xF, x, 1, 0xE] => inst::add_i_vx(self, x),
-            [0xF, x, 2, 9] => inst::ld_f_vx(self, x),
-            [0xF, x, 3, 3] => inst::ld_b_vx(self, x),
-            [0xF, x, 5, 5] => inst::ld_i_vx(self, x),
-            [0xF, x, 6, 5] => inst::ld_vx_i(self, x),
-            [_, _, _, _] => {
-                bail!("[-] opcode 0x{:x} not implemented", opcode);
+        };
+
+        // the address for F000 is stored in the next word
+        let instruction = match instruction {
+            Instruction::LdILong(_) => Instruction::decode_at(&self.memory, self.program_counter)?,
+            inst => inst,
+        };
+
+        self.execute_opcode(instruction, opcode)
+    }
+
+    /**
+     * Execute a decoded instruction located at the program counter
+     */
+    pub fn execute(&mut self, instruction: Instruction) -> Result<()> {
+        self.execute_opcode(instruction, instruction.encode())
+    }
+
+    /**
+     * Execute instruction, tracing it as the opcode it was
+     * decoded from, which differs from its encoding when
+     * an opcode falls back to SYS or SE on older platforms
+     */
+    fn execute_opcode(&mut self, instruction: Instruction, opcode: u16) -> Result<()> {
+        // All execution will be halted until
+        // Fx0A receives a key
+        if self.is_blocked() {
+            return Ok(());
+        }
+
+        let pc = self.program_counter;
+        if !self
+            .tracer
+            .as_ref()
+            .is_some_and(|tracer| tracer.wants(pc, &instruction))
+        {
+            return self.dispatch(instruction);
+        }
+
+        // compare the state the instruction can touch before and after
+        let registers = self.registers;
+        let i = self.i_register;
+        let (_, written) = self.memory_accesses(&instruction);
+        let written = written.start.min(self.memory.len())..written.end.min(self.memory.len());
+        let memory = self.memory[written.clone()].to_vec();
+
+        let result = self.dispatch(instruction);
+
+        // every byte in the written range is reported once the
+        // write happened, a faulting instruction only what changed
+        let memory = match result {
+            Ok(()) => written.map(|addr| (addr, self.memory[addr])).collect(),
+            Err(_) => written
+                .zip(memory)
+                .filter(|(addr, old)| self.memory[*addr] != *old)
+                .map(|(addr, _)| (addr, self.memory[addr]))
+                .collect(),
+        };
+        let entry = TraceEntry {
+            pc,
+            opcode,
+            instruction: Some(instruction),
+            registers: (0..16u8)
+                .filter(|x| self.registers[*x as usize] != registers[*x as usize])
+                .map(|x| (x, self.registers[x as usize]))
+                .collect(),
+            i: Some(self.i_register).filter(|value| *value != i),
+            memory,
+            fault: result.as_ref().err().map(|e| e.to_string()),
+        };
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.record(&entry);
+        }
+        result
+    }
+
+    /**
+     * Start passing every executed instruction to tracer
+     */
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    /**
+     * Stop tracing, returning the tracer so its
+     * sinks can be flushed or inspected
+     */
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    fn dispatch(&mut self, instruction: Instruction) -> Result<()> {
+        use Instruction::*;
+
+        match instruction {
+            Sys(_) => inst::sys(),
+            Cls => inst::cls(self),
+            Ret => inst::ret(self),
+            ScrollDown(n) => inst::scd_n(self, n),
+            ScrollUp(n) => inst::scu_n(self, n),
+            ScrollRight => inst::scr(self),
+            ScrollLeft => inst::scl(self),
+            Exit => {
+                self.exit();
+                return Ok(());
+            }
+            Low => inst::low(self),
+            High => inst::high(self),
+            Jp(nnn) => inst::jmp_nnn(self, nnn),
+            Call(nnn) => inst::call(self, nnn),
+            SeVxByte { x, kk } => inst::se_vx_kk(self, x, kk),
+            SneVxByte { x, kk } => inst::sne_vx_kk(self, x, kk),
+            SeVxVy { x, y } => inst::se_vx_vy(self, x, y),
+            SaveRange { x, y } => inst::ld_i_vx_vy(self, x, y),
+            LoadRange { x, y } => inst::ld_vx_vy_i(self, x, y),
+            LdVxByte { x, kk } => inst::ld_vx(self, x, kk),
+            AddVxByte { x, kk } => inst::add_vx_kk(self, x, kk),
+            LdVxVy { x, y } => inst::ld_vx_vy(self, x, y),
+            OrVxVy { x, y } => inst::or_vx_vy(self, x, y),
+            AndVxVy { x, y } => inst::and_vx_vy(self, x, y),
+            XorVxVy { x, y } => inst::xor_vx_vy(self, x, y),
+            AddVxVy { x, y } => inst::add_vx_vy(self, x, y),
+            SubVxVy { x, y } => inst::sub_vx_vy(self, x, y),
+            ShrVxVy { x, y } => inst::shr_vx_vy(self, x, y),
+            SubnVxVy { x, y } => inst::subn_vx_vy(self, x, y),
+            ShlVxVy { x, y } => inst::shl_vx_vy(self, x, y),
+            SneVxVy { x, y } => inst::sne_vx_vy(self, x, y),
+            LdI(nnn) => inst::ld_i_nnn(self, nnn),
+            JpV0(nnn) => inst::jmp_v0_nnn(self, nnn),
+            Rnd { x, kk } => inst::rnd_vx_kk(self, x, kk),
+            Drw { x, y, n } => inst::drw_vx_vy_n(self, x, y, n),
+            Skp { x } => inst::skp_vx(self, x),
+            Sknp { x } => inst::sknp_vx(self, x),
+            LdILong(nnnn) => inst::ld_i_nnnn(self, nnnn),
+            Plane(n) => inst::plane_n(self, n),
+            Audio => inst::audio(self),
+            LdVxDt { x } => inst::ld_vx_dt(self, x),
+            LdVxK { x } => {
+                // keys already down don't count until pressed again
+                self.key_wait = KeyWait::Press {
+                    x,
+                    held: self.pressed_keys(),
+                };
+                Ok(())
             }
+            LdDtVx { x } => inst::ld_dt_vx(self, x),
+            LdStVx { x } => inst::ld_st_vx(self, x),
+            AddIVx { x } => inst::add_i_vx(self, x),
+            LdFVx { x } => inst::ld_f_vx(self, x),
+            LdHfVx { x } => inst::ld_hf_vx(self, x),
+            LdBVx { x } => inst::ld_b_vx(self, x),
+            Pitch { x } => inst::pitch_vx(self, x),
+            LdIVx { x } => inst::ld_i_vx(self, x),
+            LdVxI { x } => inst::ld_vx_i(self, x),
+            LdRVx { x } => inst::ld_r_vx(self, x),
+            LdVxR { x } => inst::ld_vx_r(self, x),
+        }?;
+
+        if instruction.modifies_display() {
+            self.output.update(&self.display);
         }
+
         // move to next opcode
-        self.program_counter += 2;
+        self.program_counter = self.program_counter.wrapping_add(2);
+        Ok(())
+    }
+
+    /**
+     * Serialize the full machine state, including the
+     * interpreter state that is not otherwise visible
+     */
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.bytes(STATE_MAGIC);
+        w.u16(STATE_VERSION);
+
+        w.mode_and_quirks(self.mode, &self.quirks);
+
+        w.buffer(&self.memory);
+        w.bytes(&self.registers);
+        w.u16(self.i_register);
+        w.u8(self.delay_timer);
+        w.u8(self.sound_timer);
+        w.u32(self.program_counter as u32);
+
+        w.u8(self.stack.len() as u8);
+        for addr in &self.stack {
+            w.u16(*addr);
+        }
+
+        w.u16(self.pressed_keys());
+
+        w.u16(self.display.width() as u16);
+        w.u16(self.display.height() as u16);
+        w.bytes(&self.display.to_bytes());
+        w.bytes(&self.phosphor_glow);
+
+        w.bytes(&self.rpl_flags);
+        w.u8(self.planes);
+        w.bytes(&self.audio_pattern);
+        w.u8(self.pitch);
+
+        match self.key_wait {
+            KeyWait::Idle => w.u8(0),
+            KeyWait::Press { x, held } => {
+                w.u8(1);
+                w.u8(x);
+                w.u16(held);
+            }
+            KeyWait::Release { x, key } => {
+                w.u8(2);
+                w.u8(x);
+                w.u16(key as u16);
+            }
+        }
+        w.bool(self.exited);
+
+        match self.rng.state() {
+            Some(state) => {
+                w.bool(true);
+                w.u64(state);
+            }
+            None => w.bool(false),
+        }
+        w.finish()
+    }
+
+    /**
+     * Restore a state produced by save_state, the CPU is
+     * left untouched if the state is invalid
+     */
+    pub fn load_state(&mut self, state: &[u8]) -> Result<()> {
+        let mut r = Reader::new(state);
+        if r.bytes(STATE_MAGIC.len())? != STATE_MAGIC {
+            return Err(Chip8Error::InvalidState("not a save state"));
+        }
+        if r.u16()? != STATE_VERSION {
+            return Err(Chip8Error::InvalidState("unsupported version"));
+        }
+
+        let (mode, quirks) = r.mode_and_quirks()?;
+        let mut cpu = Cpu::with_mode(mode);
+        cpu.quirks = quirks;
+
+        let memory = r.buffer()?;
+        if memory.len() != mode.memory_size() {
+            return Err(Chip8Error::InvalidState("memory size does not match mode"));
+        }
+        cpu.memory.copy_from_slice(memory);
+        cpu.registers = r.array()?;
+        cpu.i_register = r.u16()?;
+        cpu.delay_timer = r.u8()?;
+        cpu.sound_timer = r.u8()?;
+        cpu.program_counter = r.u32()? as usize;
+
+        let depth = r.u8()? as usize;
+        if depth > STACK_SIZE {
+            return Err(Chip8Error::InvalidState("stack too deep"));
+        }
+        for _ in 0..depth {
+            cpu.stack.push(r.u16()?);
+        }
+
+        let keys = r.u16()?;
+
+        let resolution = (r.u16()? as usize, r.u16()? as usize);
+        match resolution {
+            (DISP_WIDTH, DISP_HEIGHT) | (HIRES_WIDTH, HIRES_HEIGHT) => {
+                cpu.set_resolution(resolution.0, resolution.1)
+            }
+            _ => return Err(Chip8Error::InvalidState("unsupported resolution")),
+        }
+        let packed = cpu.display.packed_len();
+        cpu.display.load_bytes(r.bytes(packed)?);
+        let pixels = cpu.display.len();
+        cpu.phosphor_glow.copy_from_slice(r.bytes(pixels)?);
+
+        cpu.rpl_flags = r.array()?;
+        cpu.planes = r.u8()?;
+        cpu.audio_pattern = r.array()?;
+        cpu.pitch = r.u8()?;
+
+        cpu.key_wait = match r.u8()? {
+            0 => KeyWait::Idle,
+            tag @ 1..=2 => {
+                let x = r.u8()?;
+                let data = r.u16()?;
+                if x > 0xF || (tag == 2 && data > 0xF) {
+                    return Err(Chip8Error::InvalidState("invalid key wait"));
+                }
+                if tag == 1 {
+                    KeyWait::Press { x, held: data }
+                } else {
+                    KeyWait::Release { x, key: data as u8 }
+                }
+            }
+            _ => return Err(Chip8Error::InvalidState("invalid key wait")),
+        };
+        cpu.exited = r.bool()?;
+        let rng_state = if r.bool()? { Some(r.u64()?) } else { None };
+        r.finish()?;
+
+        // keep the current peripherals, bringing them
+        // in line with the restored state
+        std::mem::swap(&mut cpu.output, &mut self.output);
+        std::mem::swap(&mut cpu.keypad, &mut self.keypad);
+        std::mem::swap(&mut cpu.buzzer, &mut self.buzzer);
+        std::mem::swap(&mut cpu.rng, &mut self.rng);
+        std::mem::swap(&mut cpu.keymap, &mut self.keymap);
+        std::mem::swap(&mut cpu.tracer, &mut self.tracer);
+        cpu.phosphor_decay = self.phosphor_decay;
+        for key in 0..16 {
+            cpu.keypad.set_pressed(key, keys & (1 << key) != 0);
+        }
+        cpu.buzzer.set_active(cpu.sound_timer > 0);
+        cpu.output.update(&cpu.display);
+        if let Some(state) = rng_state {
+            cpu.rng.restore(state);
+        }
+
+        *self = cpu;
         Ok(())
     }
 }
@@ -0,0 +1,599 @@
+use crate::cpu::Cpu;
+use crate::error::Chip8Error;
+use crate::instruction::Instruction;
+use crate::machine::DEFAULT_CYCLES_PER_FRAME;
+use std::fmt;
+use std::ops::Range;
+
+/**
+ * A register a breakpoint condition can test
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    V(u8),
+    I,
+    Dt,
+    St,
+}
+
+impl Register {
+    pub fn read(&self, cpu: &Cpu) -> u16 {
+        match *self {
+            Register::V(x) => cpu.registers[x as usize & 0xF] as u16,
+            Register::I => cpu.i_register,
+            Register::Dt => cpu.delay_timer as u16,
+            Register::St => cpu.sound_timer as u16,
+        }
+    }
+}
+
+impl std::str::FromStr for Register {
+    type Err = String;
+
+    /**
+     * V0-VF, I, DT or ST
+     */
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let upper = s.trim().to_uppercase();
+        match upper.as_str() {
+            "I" => Ok(Register::I),
+            "DT" => Ok(Register::Dt),
+            "ST" => Ok(Register::St),
+            v if v.len() == 2 && v.starts_with('V') => u8::from_str_radix(&v[1..], 16)
+                .map(Register::V)
+                .map_err(|_| format!("unknown register '{}'", s)),
+            _ => Err(format!("unknown register '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Register::V(x) => write!(f, "V{:X}", x),
+            Register::I => write!(f, "I"),
+            Register::Dt => write!(f, "DT"),
+            Register::St => write!(f, "ST"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compare {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Compare {
+    // longest first, so <= isn't read as <
+    const OPERATORS: [(&'static str, Compare); 6] = [
+        ("==", Compare::Eq),
+        ("!=", Compare::Ne),
+        ("<=", Compare::Le),
+        (">=", Compare::Ge),
+        ("<", Compare::Lt),
+        (">", Compare::Gt),
+    ];
+
+    pub fn test(&self, left: u16, right: u16) -> bool {
+        match self {
+            Compare::Eq => left == right,
+            Compare::Ne => left != right,
+            Compare::Lt => left < right,
+            Compare::Le => left <= right,
+            Compare::Gt => left > right,
+            Compare::Ge => left >= right,
+        }
+    }
+}
+
+impl fmt::Display for Compare {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (op, _) = Compare::OPERATORS
+            .iter()
+            .find(|(_, compare)| compare == self)
+            .unwrap();
+        write!(f, "{}", op)
+    }
+}
+
+/**
+ * A register compared against a constant, e.g. `V3 == 0x05`
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub register: Register,
+    pub compare: Compare,
+    pub value: u16,
+}
+
+impl Condition {
+    pub fn holds(&self, cpu: &Cpu) -> bool {
+        self.compare.test(self.register.read(cpu), self.value)
+    }
+}
+
+impl std::str::FromStr for Condition {
+    type Err = String;
+
+    /**
+     * `<register> <op> <value>`, the value is decimal or 0x prefixed
+     */
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (pos, op, compare) = Compare::OPERATORS
+            .iter()
+            .filter_map(|(op, compare)| s.find(op).map(|pos| (pos, *op, *compare)))
+            .min_by_key(|(pos, op, _)| (*pos, usize::MAX - op.len()))
+            .ok_or_else(|| format!("expected a comparison in '{}'", s))?;
+
+        let register = s[..pos].parse()?;
+        let value = s[pos + op.len()..].trim();
+        let value = match value.strip_prefix("0x") {
+            Some(hex) => u16::from_str_radix(hex, 16),
+            None => value.parse(),
+        }
+        .map_err(|_| format!("invalid value '{}'", value))?;
+
+        Ok(Condition {
+            register,
+            compare,
+            value,
+        })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} 0x{:02X}", self.register, self.compare, self.value)
+    }
+}
+
+/**
+ * A condition that stops execution before an instruction
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// The program counter reaches addr, and condition
+    /// holds if there is one
+    Address {
+        addr: usize,
+        condition: Option<Condition>,
+    },
+    /// The next opcode matches value in the bits set in mask
+    Opcode { value: u16, mask: u16 },
+    /// The condition becomes true, wherever that happens
+    Condition(Condition),
+}
+
+impl Breakpoint {
+    pub fn address(addr: usize) -> Breakpoint {
+        Breakpoint::Address {
+            addr,
+            condition: None,
+        }
+    }
+
+    /**
+     * Parse an opcode pattern such as "D01F" or "Dxy?", where any
+     * digit that is not hex matches anything
+     */
+    pub fn opcode(pattern: &str) -> Option<Breakpoint> {
+        if pattern.len() != 4 {
+            return None;
+        }
+        let mut value = 0u16;
+        let mut mask = 0u16;
+        for c in pattern.chars() {
+            value <<= 4;
+            mask <<= 4;
+            match c {
+                '0'..='9' | 'A'..='F' | 'a'..='f' => {
+                    value |= c.to_digit(16)? as u16;
+                    mask |= 0xF;
+                }
+                '?' | 'x' | 'y' | 'n' | 'k' | 'X' | 'Y' | 'N' | 'K' => {}
+                _ => return None,
+            }
+        }
+        Some(Breakpoint::Opcode { value, mask })
+    }
+
+    /**
+     * Whether the breakpoint stops before the next instruction,
+     * armed is false for a Condition that already held before
+     * the last instruction ran
+     */
+    fn hit(&self, cpu: &Cpu, armed: bool) -> bool {
+        match *self {
+            Breakpoint::Address { addr, condition } => {
+                cpu.program_counter == addr && condition.is_none_or(|c| c.holds(cpu))
+            }
+            Breakpoint::Opcode { value, mask } => cpu
+                .fetch_instruction()
+                .is_ok_and(|opcode| opcode & mask == value),
+            Breakpoint::Condition(condition) => armed && condition.holds(cpu),
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Breakpoint::Address { addr, condition } => {
+                write!(f, "address 0x{:03X}", addr)?;
+                match condition {
+                    Some(condition) => write!(f, " if {}", condition),
+                    None => Ok(()),
+                }
+            }
+            Breakpoint::Opcode { value, mask } => {
+                write!(f, "opcode ")?;
+                for shift in [12, 8, 4, 0].iter() {
+                    if (mask >> shift) & 0xF == 0 {
+                        write!(f, "?")?;
+                    } else {
+                        write!(f, "{:X}", (value >> shift) & 0xF)?;
+                    }
+                }
+                Ok(())
+            }
+            Breakpoint::Condition(condition) => write!(f, "when {}", condition),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl std::str::FromStr for Access {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "r" | "read" => Ok(Access::Read),
+            "w" | "write" => Ok(Access::Write),
+            "rw" | "access" => Ok(Access::ReadWrite),
+            _ => Err(format!("unknown access '{}', expected r, w or rw", s)),
+        }
+    }
+}
+
+/**
+ * Stops execution after an instruction reads or writes
+ * memory in range, instruction fetches are not watched
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub range: Range<usize>,
+    pub access: Access,
+}
+
+impl Watchpoint {
+    /**
+     * The first watched address in accessed, if any
+     */
+    fn overlap(&self, accessed: &Range<usize>) -> Option<usize> {
+        let start = self.range.start.max(accessed.start);
+        if start < self.range.end.min(accessed.end) {
+            Some(start)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let access = match self.access {
+            Access::Read => "read",
+            Access::Write => "write",
+            Access::ReadWrite => "access",
+        };
+        write!(
+            f,
+            "{} 0x{:03X}-0x{:03X}",
+            access,
+            self.range.start,
+            self.range.end.saturating_sub(1)
+        )
+    }
+}
+
+/**
+ * Where a step over or step out finishes, so it
+ * can be run in chunks with Debugger::run_to
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepTarget {
+    /// Back at pc with the stack at depth, after a CALL returns
+    Return { pc: usize, depth: usize },
+    /// The stack is shallower than depth
+    Out { depth: usize },
+}
+
+impl StepTarget {
+    fn reached(&self, cpu: &Cpu) -> bool {
+        match *self {
+            StepTarget::Return { pc, depth } => {
+                cpu.stack.len() == depth && cpu.program_counter == pc
+            }
+            StepTarget::Out { depth } => cpu.stack.len() < depth,
+        }
+    }
+}
+
+/**
+ * Why execution stopped
+ */
+#[derive(Debug)]
+pub enum StopReason {
+    /// The step finished
+    Step,
+    /// Hit the breakpoint with the given index
+    Breakpoint(usize),
+    /// The instruction at pc read or wrote addr, watched
+    /// by the watchpoint with the given index
+    Watchpoint {
+        index: usize,
+        addr: usize,
+        write: bool,
+        pc: usize,
+    },
+    /// The program jumped to itself and will never make progress
+    Stalled,
+    /// Fx0A is blocking until a key is pressed
+    WaitingForKey,
+    /// The program executed 00FD - EXIT
+    Exited,
+    /// An instruction failed
+    Fault(Chip8Error),
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::Step => write!(f, "stepped"),
+            StopReason::Breakpoint(index) => write!(f, "hit breakpoint {}", index),
+            StopReason::Watchpoint {
+                index,
+                addr,
+                write,
+                pc,
+            } => write!(
+                f,
+                "watchpoint {}: 0x{:03X} {} by 0x{:03X}",
+                index,
+                addr,
+                if *write { "written" } else { "read" },
+                pc
+            ),
+            StopReason::Stalled => write!(f, "program is stuck in a jump to itself"),
+            StopReason::WaitingForKey => write!(f, "waiting for a key press"),
+            StopReason::Exited => write!(f, "program exited"),
+            StopReason::Fault(e) => write!(f, "fault: {}", e),
+        }
+    }
+}
+
+/**
+ * Runs a Cpu one instruction at a time, stopping at
+ * breakpoints and watchpoints. The timers tick once
+ * every cycles_per_tick instructions.
+ */
+pub struct Debugger {
+    pub cpu: Cpu,
+    pub breakpoints: Vec<Breakpoint>,
+    pub watchpoints: Vec<Watchpoint>,
+    cycles: u64,
+    cycles_per_tick: u64,
+    // which Condition breakpoints were false before the last instruction
+    armed: Vec<bool>,
+}
+
+impl Debugger {
+    pub fn new(cpu: Cpu) -> Self {
+        Debugger {
+            cpu,
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            cycles: 0,
+            cycles_per_tick: DEFAULT_CYCLES_PER_FRAME as u64,
+            armed: Vec::new(),
+        }
+    }
+
+    /**
+     * Instructions executed so far
+     */
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn set_cycles_per_tick(&mut self, cycles: u64) {
+        self.cycles_per_tick = cycles.max(1);
+    }
+
+    /**
+     * Execute a single instruction
+     */
+    pub fn step_into(&mut self) -> StopReason {
+        self.run_until(None, |_| true).unwrap()
+    }
+
+    /**
+     * Execute a single instruction, running a CALL
+     * until the subroutine returns
+     */
+    pub fn step_over(&mut self) -> StopReason {
+        match self.over_target() {
+            Some(target) => self.run_until(None, |cpu| target.reached(cpu)).unwrap(),
+            None => self.step_into(),
+        }
+    }
+
+    /**
+     * Run until the current subroutine returns, None
+     * if the stack is empty
+     */
+    pub fn step_out(&mut self) -> Option<StopReason> {
+        let target = self.out_target()?;
+        self.run_until(None, |cpu| target.reached(cpu))
+    }
+
+    /**
+     * Where step_over stops if the instruction at pc is
+     * a CALL, None if it is a single step instead
+     */
+    pub fn over_target(&self) -> Option<StepTarget> {
+        let pc = self.cpu.program_counter;
+        match Instruction::decode_at(&self.cpu.memory, pc) {
+            Ok(Instruction::Call(_)) => Some(StepTarget::Return {
+                pc: pc + 2,
+                depth: self.cpu.stack.len(),
+            }),
+            _ => None,
+        }
+    }
+
+    /**
+     * Where step_out stops, None if the stack is empty
+     */
+    pub fn out_target(&self) -> Option<StepTarget> {
+        match self.cpu.stack.len() {
+            0 => None,
+            depth => Some(StepTarget::Out { depth }),
+        }
+    }
+
+    /**
+     * Like run, but stop with StopReason::Step once target
+     * is reached, so step_over and step_out can be run in
+     * chunks of max_cycles
+     */
+    pub fn run_to(&mut self, target: StepTarget, max_cycles: u64) -> Option<StopReason> {
+        self.run_until(Some(max_cycles), |cpu| target.reached(cpu))
+    }
+
+    /**
+     * Run until a breakpoint or the program stops on its own,
+     * which may be never. Interactive front-ends should call
+     * run instead, so they can poll for input in between.
+     */
+    pub fn resume(&mut self) -> StopReason {
+        self.run_until(None, |_| false).unwrap()
+    }
+
+    /**
+     * Like resume, but give control back after max_cycles
+     * instructions with None so the caller can poll for
+     * input while the program runs
+     */
+    pub fn run(&mut self, max_cycles: u64) -> Option<StopReason> {
+        self.run_until(Some(max_cycles), |_| false)
+    }
+
+    fn run_until<F: Fn(&Cpu) -> bool>(
+        &mut self,
+        limit: Option<u64>,
+        done: F,
+    ) -> Option<StopReason> {
+        let mut count = 0;
+        loop {
+            if limit.is_some_and(|limit| count >= limit) {
+                return None;
+            }
+            if let Some(stop) = self.cycle() {
+                return Some(stop);
+            }
+            count += 1;
+
+            if let Some(index) = self.breakpoint() {
+                return Some(StopReason::Breakpoint(index));
+            }
+            if done(&self.cpu) {
+                return Some(StopReason::Step);
+            }
+        }
+    }
+
+    /**
+     * Execute one instruction, ticking the timers at 60Hz
+     * relative to the instruction rate
+     */
+    fn cycle(&mut self) -> Option<StopReason> {
+        if self.cpu.has_exited() {
+            return Some(StopReason::Exited);
+        }
+        if self.cpu.is_waiting_for_key() {
+            return Some(StopReason::WaitingForKey);
+        }
+
+        let pc = self.cpu.program_counter;
+        let (reads, writes) = match Instruction::decode_at(&self.cpu.memory, pc) {
+            Ok(inst) => self.cpu.memory_accesses(&inst),
+            Err(_) => (0..0, 0..0),
+        };
+        let cpu = &self.cpu;
+        self.armed = self
+            .breakpoints
+            .iter()
+            .map(|b| matches!(b, Breakpoint::Condition(c) if !c.holds(cpu)))
+            .collect();
+
+        let result = self
+            .cpu
+            .fetch_instruction()
+            .and_then(|opcode| self.cpu.execute_instruction(opcode));
+        if let Err(e) = result {
+            return Some(StopReason::Fault(e));
+        }
+
+        self.cycles += 1;
+        if self.cycles.is_multiple_of(self.cycles_per_tick) {
+            self.cpu.decrement_timers();
+        }
+
+        for (index, watchpoint) in self.watchpoints.iter().enumerate() {
+            let read = match watchpoint.access {
+                Access::Write => None,
+                _ => watchpoint.overlap(&reads),
+            };
+            let written = match watchpoint.access {
+                Access::Read => None,
+                _ => watchpoint.overlap(&writes),
+            };
+            if let Some(addr) = written.or(read) {
+                return Some(StopReason::Watchpoint {
+                    index,
+                    addr,
+                    write: written.is_some(),
+                    pc,
+                });
+            }
+        }
+
+        if self.cpu.has_exited() {
+            Some(StopReason::Exited)
+        } else if self.cpu.is_waiting_for_key() {
+            Some(StopReason::WaitingForKey)
+        } else if self.cpu.program_counter == pc {
+            Some(StopReason::Stalled)
+        } else {
+            None
+        }
+    }
+
+    fn breakpoint(&self) -> Option<usize> {
+        self.breakpoints.iter().enumerate().position(|(index, b)| {
+            b.hit(&self.cpu, self.armed.get(index).copied().unwrap_or(false))
+        })
+    }
+}
@@ -0,0 +1,217 @@
+use crate::cpu::{Mode, TXT_OFFSET};
+use crate::instruction::Instruction;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+// data bytes printed per db directive
+const DATA_PER_LINE: usize = 8;
+
+/**
+ * A single line of the listing
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Entry {
+    /// A reachable instruction
+    Code { addr: usize, inst: Instruction },
+    /// Bytes never reached by the control flow, usually sprites
+    Data { addr: usize, bytes: Vec<u8> },
+}
+
+impl Entry {
+    /**
+     * Address of the first byte of the entry
+     */
+    pub fn addr(&self) -> usize {
+        match self {
+            Entry::Code { addr, .. } | Entry::Data { addr, .. } => *addr,
+        }
+    }
+}
+
+/**
+ * Kinds of auto-generated labels, in increasing priority
+ * when an address is referenced in more than one way
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LabelKind {
+    Data,
+    Jump,
+    Subroutine,
+}
+
+/**
+ * A disassembled ROM
+ *
+ * The Display implementation prints a labelled listing in the
+ * standard mnemonic syntax, with the address and raw bytes of
+ * each line as a trailing comment
+ */
+#[derive(Debug, Clone)]
+pub struct Disassembly {
+    pub entries: Vec<Entry>,
+    pub labels: BTreeMap<usize, LabelKind>,
+}
+
+/**
+ * Disassemble a ROM loaded at TXT_OFFSET
+ *
+ * Code is separated from data by recursive descent, following
+ * every jump, call and skip from the entry point. Bytes that are
+ * never reached are reported as data.
+ */
+pub fn disassemble(rom: &[u8], mode: Mode) -> Disassembly {
+    let end = TXT_OFFSET + rom.len();
+    let mut memory = vec![0u8; end];
+    memory[TXT_OFFSET..].copy_from_slice(rom);
+
+    let mut code = BTreeMap::new();
+    let mut labels = BTreeMap::new();
+    let mut pending = vec![TXT_OFFSET];
+    labels.insert(TXT_OFFSET, LabelKind::Jump);
+
+    let label = |labels: &mut BTreeMap<usize, LabelKind>, addr: u16, kind: LabelKind| {
+        let entry = labels.entry(addr as usize).or_insert(kind);
+        *entry = (*entry).max(kind);
+    };
+
+    while let Some(mut addr) = pending.pop() {
+        while addr >= TXT_OFFSET && !code.contains_key(&addr) {
+            let inst = match Instruction::decode_at(&memory, addr) {
+                Ok(inst) if inst.available_in(mode) => inst,
+                _ => break,
+            };
+            code.insert(addr, inst);
+            let next = addr + inst.size();
+
+            match inst {
+                Instruction::Jp(nnn) => {
+                    label(&mut labels, nnn, LabelKind::Jump);
+                    pending.push(nnn as usize);
+                    break;
+                }
+                Instruction::JpV0(nnn) => {
+                    // usually a jump table, the offset is unknown
+                    label(&mut labels, nnn, LabelKind::Jump);
+                    pending.push(nnn as usize);
+                    break;
+                }
+                Instruction::Call(nnn) => {
+                    label(&mut labels, nnn, LabelKind::Subroutine);
+                    pending.push(nnn as usize);
+                }
+                Instruction::LdI(nnn) => label(&mut labels, nnn, LabelKind::Data),
+                Instruction::LdILong(nnnn) => label(&mut labels, nnnn, LabelKind::Data),
+                Instruction::Ret | Instruction::Exit => break,
+                Instruction::SeVxByte { .. }
+                | Instruction::SneVxByte { .. }
+                | Instruction::SeVxVy { .. }
+                | Instruction::SneVxVy { .. }
+                | Instruction::Skp { .. }
+                | Instruction::Sknp { .. } => {
+                    // both the next instruction and the one after it
+                    let skipped = match Instruction::decode_at(&memory, next) {
+                        Ok(Instruction::LdILong(_)) if mode == Mode::XoChip => 4,
+                        _ => 2,
+                    };
+                    pending.push(next + skipped);
+                }
+                _ => {}
+            }
+            addr = next;
+        }
+    }
+
+    // walk the ROM in order, grouping unreached bytes into data
+    let mut entries = Vec::new();
+    let mut addr = TXT_OFFSET;
+    while addr < end {
+        if let Some(inst) = code.get(&addr) {
+            entries.push(Entry::Code { addr, inst: *inst });
+            addr += inst.size();
+            continue;
+        }
+
+        let start = addr;
+        addr += 1;
+        while addr < end
+            && addr - start < DATA_PER_LINE
+            && !code.contains_key(&addr)
+            && !labels.contains_key(&addr)
+        {
+            addr += 1;
+        }
+        entries.push(Entry::Data {
+            addr: start,
+            bytes: memory[start..addr].to_vec(),
+        });
+    }
+
+    // only keep labels that land on the start of a line
+    let starts: BTreeSet<usize> = entries.iter().map(Entry::addr).collect();
+    labels.retain(|addr, _| starts.contains(addr));
+
+    Disassembly { entries, labels }
+}
+
+impl Disassembly {
+    /**
+     * The name of the label at addr, if there is one
+     */
+    pub fn label(&self, addr: usize) -> Option<String> {
+        let prefix = match self.labels.get(&addr)? {
+            LabelKind::Data => "data",
+            LabelKind::Jump => "label",
+            LabelKind::Subroutine => "sub",
+        };
+        Some(format!("{}_{:03x}", prefix, addr))
+    }
+
+    /**
+     * Render an instruction, replacing known addresses with labels
+     */
+    pub fn render(&self, inst: &Instruction) -> String {
+        let name = |addr: u16| {
+            self.label(addr as usize)
+                .unwrap_or_else(|| format!("0x{:03X}", addr))
+        };
+        match *inst {
+            Instruction::Jp(nnn) => format!("JP {}", name(nnn)),
+            Instruction::Call(nnn) => format!("CALL {}", name(nnn)),
+            Instruction::LdI(nnn) => format!("LD I, {}", name(nnn)),
+            Instruction::JpV0(nnn) => format!("JP V0, {}", name(nnn)),
+            Instruction::LdILong(nnnn) => match self.label(nnnn as usize) {
+                Some(label) => format!("LD I, LONG {}", label),
+                None => inst.to_string(),
+            },
+            _ => inst.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.entries {
+            if let Some(label) = self.label(entry.addr()) {
+                writeln!(f, "{}:", label)?;
+            }
+
+            match entry {
+                Entry::Code { addr, inst } => {
+                    let raw: Vec<String> = inst
+                        .to_bytes()
+                        .chunks(2)
+                        .map(|w| format!("{:02X}{:02X}", w[0], w[1]))
+                        .collect();
+                    let text = self.render(inst);
+                    writeln!(f, "    {:<32}; {:03X}: {}", text, addr, raw.join(" "))?;
+                }
+                Entry::Data { addr, bytes } => {
+                    let hex: Vec<String> = bytes.iter().map(|b| format!("0x{:02X}", b)).collect();
+                    let text = format!("db {}", hex.join(", "));
+                    writeln!(f, "    {:<32}; {:03X}", text, addr)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
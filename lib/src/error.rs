@@ -0,0 +1,86 @@
+use std::fmt;
+
+/**
+ * Faults raised while loading or executing a program
+ */
+#[derive(Debug)]
+pub enum Chip8Error {
+    /// RET executed with no return address on the stack
+    StackUnderflow { pc: usize },
+    /// CALL executed with the stack already full
+    StackOverflow { pc: usize },
+    /// An instruction accessed memory past the end of the address space
+    MemoryOutOfBounds { addr: usize, pc: usize },
+    /// The opcode is not part of the instruction set being decoded
+    InvalidOpcode { opcode: u16, pc: usize },
+    /// There is no font sprite for the requested digit
+    UnsupportedFontDigit { digit: u8, pc: usize },
+    /// A keymap binding targets a key past 0xF
+    InvalidKey { key: u8 },
+    /// The ROM does not fit in memory after TXT_OFFSET
+    RomTooLarge { size: usize, max: usize },
+    /// A save state is corrupt or from an incompatible version
+    InvalidState(&'static str),
+    /// A movie file is corrupt or from an incompatible version
+    InvalidMovie(&'static str),
+    /// The ROM could not be read
+    Io(std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Chip8Error>;
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chip8Error::StackUnderflow { pc } => {
+                write!(
+                    f,
+                    "stack underflow at 0x{:03x}, no address to return to",
+                    pc
+                )
+            }
+            Chip8Error::StackOverflow { pc } => write!(f, "stack overflow at 0x{:03x}", pc),
+            Chip8Error::MemoryOutOfBounds { addr, pc } => {
+                write!(
+                    f,
+                    "memory access out of bounds at 0x{:x} (pc 0x{:03x})",
+                    addr, pc
+                )
+            }
+            Chip8Error::InvalidOpcode { opcode, pc } => {
+                write!(f, "invalid opcode 0x{:04x} at 0x{:03x}", opcode, pc)
+            }
+            Chip8Error::UnsupportedFontDigit { digit, pc } => {
+                write!(
+                    f,
+                    "no font sprite for digit 0x{:x} (pc 0x{:03x})",
+                    digit, pc
+                )
+            }
+            Chip8Error::InvalidKey { key } => {
+                write!(f, "no CHIP-8 key 0x{:x}, keys are 0-F", key)
+            }
+            Chip8Error::RomTooLarge { size, max } => {
+                write!(f, "ROM too large to load, {} bytes (max {})", size, max)
+            }
+            Chip8Error::InvalidState(reason) => write!(f, "invalid save state: {}", reason),
+            Chip8Error::InvalidMovie(reason) => write!(f, "invalid movie: {}", reason),
+            Chip8Error::Io(e) => write!(f, "failed to read ROM: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Chip8Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Chip8Error {
+    fn from(e: std::io::Error) -> Self {
+        Chip8Error::Io(e)
+    }
+}
@@ -0,0 +1,242 @@
+/**
+ * The packed rows of one plane. Pixel x of a row is
+ * bit (width - 1 - x), so the leftmost pixel is the most
+ * significant bit and sprite rows can be shifted into place.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Rows {
+    /// up to 64 pixels per row, the CHIP-8 low resolution
+    Low(Vec<u64>),
+    /// up to 128 pixels per row, the SCHIP high resolution
+    High(Vec<u128>),
+}
+
+/**
+ * The display, one bit per pixel for each XO-CHIP plane
+ *
+ * Sprites are XORed a whole row at a time with collisions
+ * detected on the same masks. Front-ends that want one
+ * value per pixel use get, iter or pixels, which yield
+ * the bitmask of the planes a pixel is lit on.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    planes: [Rows; 2],
+}
+
+impl Framebuffer {
+    /**
+     * A blank display, at most 128 pixels wide
+     */
+    pub fn new(width: usize, height: usize) -> Self {
+        assert!(width > 0 && width <= 128, "unsupported display width");
+        let rows = || {
+            if width <= 64 {
+                Rows::Low(vec![0; height])
+            } else {
+                Rows::High(vec![0; height])
+            }
+        };
+        Framebuffer {
+            width,
+            height,
+            planes: [rows(), rows()],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /**
+     * The number of pixels
+     */
+    pub fn len(&self) -> usize {
+        self.width * self.height
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /**
+     * The bits a row of this display can use
+     */
+    fn mask(&self) -> u128 {
+        u128::MAX >> (128 - self.width)
+    }
+
+    /**
+     * Row y of plane 0 or 1, see Rows for the bit order
+     */
+    pub fn row(&self, plane: usize, y: usize) -> u128 {
+        match &self.planes[plane] {
+            Rows::Low(rows) => rows[y] as u128,
+            Rows::High(rows) => rows[y],
+        }
+    }
+
+    /**
+     * Replace row y of plane 0 or 1, bits past
+     * the width of the display are dropped
+     */
+    pub fn set_row(&mut self, plane: usize, y: usize, bits: u128) {
+        let bits = bits & self.mask();
+        match &mut self.planes[plane] {
+            Rows::Low(rows) => rows[y] = bits as u64,
+            Rows::High(rows) => rows[y] = bits,
+        }
+    }
+
+    /**
+     * Toggle the pixels of mask in row y of plane 0 or 1,
+     * returning the ones that were lit before
+     */
+    pub fn xor_row(&mut self, plane: usize, y: usize, mask: u128) -> u128 {
+        let old = self.row(plane, y);
+        self.set_row(plane, y, old ^ mask);
+        old & mask
+    }
+
+    /**
+     * The bitmask of the planes pixel (x, y) is lit on
+     */
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        let bit = self.width - 1 - x;
+        (0..2).fold(0, |acc, plane| {
+            acc | (((self.row(plane, y) >> bit) & 1) as u8) << plane
+        })
+    }
+
+    /**
+     * Light pixel (x, y) on exactly the planes in the bitmask
+     */
+    pub fn set(&mut self, x: usize, y: usize, planes: u8) {
+        let bit = 1u128 << (self.width - 1 - x);
+        for plane in 0..2 {
+            let row = self.row(plane, y);
+            if planes & (1 << plane) != 0 {
+                self.set_row(plane, y, row | bit);
+            } else {
+                self.set_row(plane, y, row & !bit);
+            }
+        }
+    }
+
+    /**
+     * The pixel at row major index i, see get
+     */
+    pub fn pixel(&self, i: usize) -> u8 {
+        self.get(i % self.width, i / self.width)
+    }
+
+    /**
+     * Every pixel in row major order, see get
+     */
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..self.height).flat_map(move |y| (0..self.width).map(move |x| self.get(x, y)))
+    }
+
+    /**
+     * One byte per pixel, row major, as the display
+     * was stored before it was packed
+     */
+    pub fn pixels(&self) -> Vec<u8> {
+        self.iter().collect()
+    }
+
+    /**
+     * Clear the planes in the bitmask
+     */
+    pub fn clear(&mut self, planes: u8) {
+        for plane in (0..2).filter(|p| planes & (1 << p) != 0) {
+            match &mut self.planes[plane] {
+                Rows::Low(rows) => rows.fill(0),
+                Rows::High(rows) => rows.fill(0),
+            }
+        }
+    }
+
+    /**
+     * Move the planes in the bitmask by (dx, dy) pixels,
+     * pixels scrolled in from the edges are cleared
+     */
+    pub fn scroll(&mut self, planes: u8, dx: isize, dy: isize) {
+        for plane in (0..2).filter(|p| planes & (1 << p) != 0) {
+            let src: Vec<u128> = (0..self.height).map(|y| self.row(plane, y)).collect();
+            for y in 0..self.height {
+                let sy = y as isize - dy;
+                let bits = if sy < 0 || sy >= self.height as isize {
+                    0
+                } else {
+                    src[sy as usize]
+                };
+                // moving right means towards the low bits
+                let moved = if dx >= 0 { bits >> dx } else { bits << -dx };
+                self.set_row(plane, y, moved);
+            }
+        }
+    }
+
+    /**
+     * The row mask for cols pixels of sprite data, with the
+     * leftmost pixel in the most significant bit, drawn from
+     * column x. Pixels past the right edge are clipped or
+     * wrapped around to column 0.
+     */
+    pub(crate) fn sprite_row(&self, x: usize, bits: u128, cols: usize, wraps: bool) -> u128 {
+        let end = x + cols;
+        if end <= self.width {
+            bits << (self.width - end)
+        } else {
+            let overflow = end - self.width;
+            let clipped = bits >> overflow;
+            if wraps {
+                clipped | ((bits << (self.width - overflow)) & self.mask())
+            } else {
+                clipped
+            }
+        }
+    }
+
+    /**
+     * The packed rows of both planes, width / 8 big endian
+     * bytes per row, as stored in save states
+     */
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let row_bytes = self.width.div_ceil(8);
+        let mut out = Vec::with_capacity(2 * self.height * row_bytes);
+        for plane in 0..2 {
+            for y in 0..self.height {
+                out.extend_from_slice(&self.row(plane, y).to_be_bytes()[16 - row_bytes..]);
+            }
+        }
+        out
+    }
+
+    /**
+     * The length of to_bytes
+     */
+    pub(crate) fn packed_len(&self) -> usize {
+        2 * self.height * self.width.div_ceil(8)
+    }
+
+    /**
+     * Restore rows written by to_bytes, bytes
+     * must be exactly packed_len long
+     */
+    pub(crate) fn load_bytes(&mut self, bytes: &[u8]) {
+        let row_bytes = self.width.div_ceil(8);
+        for (i, row) in bytes.chunks_exact(row_bytes).enumerate() {
+            let mut be = [0u8; 16];
+            be[16 - row_bytes..].copy_from_slice(row);
+            self.set_row(i / self.height, i % self.height, u128::from_be_bytes(be));
+        }
+    }
+}
@@ -0,0 +1,543 @@
+use crate::cpu::{Cpu, STACK_SIZE};
+use crate::debugger::{Access, Breakpoint, Debugger, StopReason, Watchpoint};
+use crate::error::Chip8Error;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+/**
+ * Register layout for GDB's `target.xml`: V0-VF, then I and PC
+ * (16 bit, little endian), then SP (stack depth), DT and ST
+ */
+pub const TARGET_XML: &str = include_str!("gdb-target.xml");
+
+// largest packet accepted, advertised in qSupported
+const PACKET_SIZE: usize = 0x1000;
+
+// instructions run between checks for a ^C from the client
+const POLL_CYCLES: u64 = 10_000;
+
+// register numbers of I and PC, the only 16 bit registers
+const REG_I: usize = 16;
+const REG_PC: usize = 17;
+const REG_COUNT: usize = 21;
+
+// signals reported in stop replies
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+/**
+ * What to do after handling a packet
+ */
+enum Command {
+    Reply(String),
+    Step(Option<usize>),
+    Continue(Option<usize>),
+    /// Reply OK, then stop sending and expecting acks
+    NoAck,
+    /// Reply if there is one, then close the connection
+    Close(Option<String>),
+}
+
+/**
+ * A packet or a ^C read from the client
+ */
+enum Incoming {
+    Packet { data: Vec<u8>, valid: bool },
+    Interrupt,
+}
+
+/**
+ * Serves the GDB remote serial protocol for a Cpu,
+ * so standard debugger front-ends can drive it:
+ *
+ * ```text
+ * (gdb) target remote localhost:9000
+ * ```
+ */
+pub struct GdbServer {
+    pub debugger: Debugger,
+    no_ack: bool,
+    last_stop: String,
+    // bytes read while polling for ^C, the packet
+    // reader consumes them before the socket
+    pushback: VecDeque<u8>,
+}
+
+impl GdbServer {
+    pub fn new(cpu: Cpu) -> Self {
+        GdbServer {
+            debugger: Debugger::new(cpu),
+            no_ack: false,
+            last_stop: format!("S{:02x}", SIGTRAP),
+            pushback: VecDeque::new(),
+        }
+    }
+
+    /**
+     * Accept a single client and serve it until it
+     * detaches, kills the target or disconnects
+     */
+    pub fn serve(&mut self, listener: &TcpListener) -> io::Result<()> {
+        let (stream, _) = listener.accept()?;
+        self.serve_connection(stream)
+    }
+
+    pub fn serve_connection(&mut self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+        self.no_ack = false;
+        self.pushback.clear();
+
+        loop {
+            let data = match self.read_packet(&mut stream)? {
+                None => return Ok(()),
+                Some(Incoming::Interrupt) => continue,
+                Some(Incoming::Packet { valid: false, .. }) if !self.no_ack => {
+                    stream.write_all(b"-")?;
+                    continue;
+                }
+                Some(Incoming::Packet { data, .. }) => data,
+            };
+            if !self.no_ack {
+                stream.write_all(b"+")?;
+            }
+
+            let reply = match self.handle(&String::from_utf8_lossy(&data)) {
+                Command::Reply(reply) => reply,
+                Command::Step(addr) => {
+                    self.jump(addr);
+                    let stop = self.debugger.step_into();
+                    self.stop_reply(&stop)
+                }
+                Command::Continue(addr) => {
+                    self.jump(addr);
+                    self.resume(&mut stream)?
+                }
+                Command::NoAck => {
+                    self.send(&mut stream, "OK")?;
+                    self.no_ack = true;
+                    continue;
+                }
+                Command::Close(reply) => {
+                    if let Some(reply) = reply {
+                        self.send(&mut stream, &reply)?;
+                    }
+                    return Ok(());
+                }
+            };
+            self.send(&mut stream, &reply)?;
+        }
+    }
+
+    fn handle(&mut self, packet: &str) -> Command {
+        if packet == "QStartNoAckMode" {
+            return Command::NoAck;
+        }
+        let (kind, args) = match packet.chars().next() {
+            Some(c) => packet.split_at(c.len_utf8()),
+            None => return Command::Reply(String::new()),
+        };
+
+        let reply = match kind {
+            "?" => self.last_stop.clone(),
+            "g" => self.read_registers(),
+            "G" => ok_or_error(self.write_registers(args)),
+            "p" => hex_usize(args)
+                .and_then(|n| self.read_register(n))
+                .unwrap_or_else(|| "E01".into()),
+            "P" => ok_or_error(args.split_once('=').is_some_and(|(n, value)| {
+                hex_usize(n).is_some_and(|n| self.write_register(n, value))
+            })),
+            "m" => self.read_memory(args).unwrap_or_else(|| "E01".into()),
+            "M" => ok_or_error(self.write_memory(args)),
+            "s" => return Command::Step(hex_usize(args)),
+            "c" => return Command::Continue(hex_usize(args)),
+            "Z" => ok_or_error(self.insert_point(args)),
+            "z" => ok_or_error(self.remove_point(args)),
+            "H" | "T" => "OK".into(),
+            "D" => return Command::Close(Some("OK".into())),
+            "k" => return Command::Close(None),
+            _ => return Command::Reply(self.query(packet)),
+        };
+        Command::Reply(reply)
+    }
+
+    /**
+     * General queries, empty for anything unsupported
+     */
+    fn query(&mut self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return format!(
+                "PacketSize={:x};qXfer:features:read+;QStartNoAckMode+",
+                PACKET_SIZE
+            );
+        }
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let (offset, length) = match range.split_once(',') {
+                Some((offset, length)) => (hex_usize(offset), hex_usize(length)),
+                None => (None, None),
+            };
+            return match (offset, length) {
+                (Some(offset), Some(length)) => {
+                    let xml = TARGET_XML.as_bytes();
+                    let start = offset.min(xml.len());
+                    let end = start.saturating_add(length).min(xml.len());
+                    let marker = if end == xml.len() { 'l' } else { 'm' };
+                    format!("{}{}", marker, String::from_utf8_lossy(&xml[start..end]))
+                }
+                _ => "E01".into(),
+            };
+        }
+        match packet {
+            "qAttached" => "1".into(),
+            "qC" => "QC1".into(),
+            "qfThreadInfo" => "m1".into(),
+            "qsThreadInfo" => "l".into(),
+            _ => String::new(),
+        }
+    }
+
+    fn jump(&mut self, addr: Option<usize>) {
+        if let Some(addr) = addr {
+            self.debugger.cpu.program_counter = addr;
+        }
+    }
+
+    /**
+     * Continue until the debugger stops or the client sends ^C
+     */
+    fn resume(&mut self, stream: &mut TcpStream) -> io::Result<String> {
+        loop {
+            if let Some(stop) = self.debugger.run(POLL_CYCLES) {
+                return Ok(self.stop_reply(&stop));
+            }
+            if self.interrupted(stream)? {
+                self.last_stop = format!("S{:02x}", SIGINT);
+                return Ok(self.last_stop.clone());
+            }
+        }
+    }
+
+    fn stop_reply(&mut self, stop: &StopReason) -> String {
+        self.last_stop = match stop {
+            StopReason::Exited => "W00".into(),
+            StopReason::Watchpoint { index, addr, .. } => {
+                let kind = match self.debugger.watchpoints[*index].access {
+                    Access::Write => "watch",
+                    Access::Read => "rwatch",
+                    Access::ReadWrite => "awatch",
+                };
+                format!("T{:02x}{}:{:x};", SIGTRAP, kind, addr)
+            }
+            StopReason::Fault(Chip8Error::InvalidOpcode { .. }) => format!("S{:02x}", SIGILL),
+            StopReason::Fault(_) => format!("S{:02x}", SIGSEGV),
+            _ => format!("S{:02x}", SIGTRAP),
+        };
+        self.last_stop.clone()
+    }
+
+    fn send(&mut self, stream: &mut TcpStream, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum(data.as_bytes()));
+        loop {
+            stream.write_all(packet.as_bytes())?;
+            if self.no_ack {
+                return Ok(());
+            }
+            // the ack follows the packet, so it is read from the
+            // socket past any pushback. Resend on a nack, anything
+            // else counts as an ack and is kept for read_packet.
+            let mut byte = [0u8];
+            match stream.read(&mut byte)? {
+                0 => return Ok(()),
+                _ if byte[0] == b'-' => {}
+                _ if byte[0] == b'+' => return Ok(()),
+                _ => {
+                    self.pushback.push_back(byte[0]);
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /**
+     * The value of register n, hex encoded in target byte order
+     */
+    fn read_register(&self, n: usize) -> Option<String> {
+        let cpu = &self.debugger.cpu;
+        let value = match n {
+            0..=15 => cpu.registers[n] as u16,
+            REG_I => cpu.i_register,
+            REG_PC => cpu.program_counter as u16,
+            18 => cpu.stack.len() as u16,
+            19 => cpu.delay_timer as u16,
+            20 => cpu.sound_timer as u16,
+            _ => return None,
+        };
+        let bytes = value.to_le_bytes();
+        Some(to_hex(&bytes[..register_size(n)]))
+    }
+
+    fn write_register(&mut self, n: usize, hex: &str) -> bool {
+        let value = match from_hex(hex) {
+            Some(bytes) if n < REG_COUNT && bytes.len() == register_size(n) => {
+                bytes.iter().rev().fold(0u16, |acc, b| acc << 8 | *b as u16)
+            }
+            _ => return false,
+        };
+        let cpu = &mut self.debugger.cpu;
+        match n {
+            0..=15 => cpu.registers[n] = value as u8,
+            REG_I => cpu.i_register = value,
+            REG_PC => cpu.program_counter = value as usize,
+            18 => cpu.stack.resize((value as usize).min(STACK_SIZE), 0),
+            19 => cpu.delay_timer = value as u8,
+            _ => cpu.sound_timer = value as u8,
+        }
+        true
+    }
+
+    fn read_registers(&self) -> String {
+        (0..REG_COUNT)
+            .filter_map(|n| self.read_register(n))
+            .collect()
+    }
+
+    /**
+     * All registers at once, nothing is written
+     * unless the whole payload is valid
+     */
+    fn write_registers(&mut self, hex: &str) -> bool {
+        let digits: usize = (0..REG_COUNT).map(|n| register_size(n) * 2).sum();
+        if hex.len() != digits || from_hex(hex).is_none() {
+            return false;
+        }
+        let mut rest = hex;
+        for n in 0..REG_COUNT {
+            let (value, tail) = rest.split_at(register_size(n) * 2);
+            self.write_register(n, value);
+            rest = tail;
+        }
+        true
+    }
+
+    /**
+     * `addr,length`
+     */
+    fn read_memory(&self, args: &str) -> Option<String> {
+        let (addr, length) = args.split_once(',')?;
+        let range = self.memory_range(addr, length)?;
+        Some(to_hex(&self.debugger.cpu.memory[range]))
+    }
+
+    /**
+     * `addr,length:XX...`
+     */
+    fn write_memory(&mut self, args: &str) -> bool {
+        let parsed = args.split_once(':').and_then(|(target, data)| {
+            let (addr, length) = target.split_once(',')?;
+            Some((self.memory_range(addr, length)?, from_hex(data)?))
+        });
+        match parsed {
+            Some((range, bytes)) if range.len() == bytes.len() => {
+                self.debugger.cpu.memory[range].copy_from_slice(&bytes);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn memory_range(&self, addr: &str, length: &str) -> Option<std::ops::Range<usize>> {
+        let addr = hex_usize(addr)?;
+        let end = addr.checked_add(hex_usize(length)?)?;
+        if end <= self.debugger.cpu.memory.len() {
+            Some(addr..end)
+        } else {
+            None
+        }
+    }
+
+    /**
+     * `type,addr,kind`, software and hardware breakpoints
+     * are both PC breakpoints, 2-4 are watchpoints
+     */
+    fn insert_point(&mut self, args: &str) -> bool {
+        match parse_point(args) {
+            Some((0, addr, _)) | Some((1, addr, _)) => {
+                let breakpoint = Breakpoint::address(addr);
+                if !self.debugger.breakpoints.contains(&breakpoint) {
+                    self.debugger.breakpoints.push(breakpoint);
+                }
+                true
+            }
+            Some((kind, addr, length)) => match watch_access(kind) {
+                Some(access) => {
+                    self.debugger.watchpoints.push(Watchpoint {
+                        range: addr..addr.saturating_add(length.max(1)),
+                        access,
+                    });
+                    true
+                }
+                None => false,
+            },
+            None => false,
+        }
+    }
+
+    fn remove_point(&mut self, args: &str) -> bool {
+        match parse_point(args) {
+            Some((0, addr, _)) | Some((1, addr, _)) => {
+                let breakpoint = Breakpoint::address(addr);
+                self.debugger.breakpoints.retain(|b| *b != breakpoint);
+                true
+            }
+            Some((kind, addr, length)) => match watch_access(kind) {
+                Some(access) => {
+                    let range = addr..addr.saturating_add(length.max(1));
+                    self.debugger
+                        .watchpoints
+                        .retain(|w| w.range != range || w.access != access);
+                    true
+                }
+                None => false,
+            },
+            None => false,
+        }
+    }
+
+    /**
+     * The next byte, taken from the pushback before the socket
+     */
+    fn read_byte(&mut self, stream: &mut TcpStream) -> io::Result<Option<u8>> {
+        if let Some(byte) = self.pushback.pop_front() {
+            return Ok(Some(byte));
+        }
+        let mut byte = [0u8];
+        match stream.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    /**
+     * The next packet or ^C, skipping acks, None once the client disconnects
+     */
+    fn read_packet(&mut self, stream: &mut TcpStream) -> io::Result<Option<Incoming>> {
+        loop {
+            match self.read_byte(stream)? {
+                None => return Ok(None),
+                Some(0x03) => return Ok(Some(Incoming::Interrupt)),
+                Some(b'$') => break,
+                Some(_) => {}
+            }
+        }
+
+        let mut data = Vec::new();
+        loop {
+            match self.read_byte(stream)? {
+                None => return Ok(None),
+                Some(b'#') => break,
+                Some(_) if data.len() >= PACKET_SIZE => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "packet too large",
+                    ))
+                }
+                Some(b) => data.push(b),
+            }
+        }
+
+        let mut digits = [0u8; 2];
+        for digit in &mut digits {
+            *digit = match self.read_byte(stream)? {
+                Some(b) => b,
+                None => return Ok(None),
+            };
+        }
+        let valid = std::str::from_utf8(&digits)
+            .ok()
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+            == Some(checksum(&data));
+        Ok(Some(Incoming::Packet { data, valid }))
+    }
+
+    /**
+     * Check for a ^C without blocking, a disconnect also
+     * interrupts so the next read sees it. Anything else,
+     * e.g. a packet sent while running, is kept for
+     * read_packet.
+     */
+    fn interrupted(&mut self, stream: &mut TcpStream) -> io::Result<bool> {
+        stream.set_nonblocking(true)?;
+        let mut buf = [0u8; 64];
+        let result = stream.read(&mut buf);
+        stream.set_nonblocking(false)?;
+        match result {
+            Ok(0) => Ok(true),
+            Ok(len) => {
+                self.pushback.extend(&buf[..len]);
+                match self.pushback.iter().position(|b| *b == 0x03) {
+                    Some(pos) => {
+                        self.pushback.remove(pos);
+                        Ok(true)
+                    }
+                    None => Ok(false),
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn register_size(n: usize) -> usize {
+    match n {
+        REG_I | REG_PC => 2,
+        _ => 1,
+    }
+}
+
+fn parse_point(args: &str) -> Option<(u8, usize, usize)> {
+    let mut fields = args.split(',');
+    let kind = fields.next()?.parse().ok()?;
+    let addr = hex_usize(fields.next()?)?;
+    // the kind or length may be followed by ;conditions, which are ignored
+    let length = hex_usize(fields.next()?.split(';').next()?)?;
+    Some((kind, addr, length))
+}
+
+fn watch_access(kind: u8) -> Option<Access> {
+    match kind {
+        2 => Some(Access::Write),
+        3 => Some(Access::Read),
+        4 => Some(Access::ReadWrite),
+        _ => None,
+    }
+}
+
+fn ok_or_error(ok: bool) -> String {
+    if ok { "OK" } else { "E01" }.into()
+}
+
+fn hex_usize(s: &str) -> Option<usize> {
+    usize::from_str_radix(s, 16).ok()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) || !s.is_ascii() {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
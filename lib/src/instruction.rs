@@ -0,0 +1,353 @@
+use crate::cpu::Mode;
+use crate::error::{Chip8Error, Result};
+use std::fmt;
+
+/**
+ * A decoded instruction
+ *
+ * Covers the CHIP-8, SUPER-CHIP and XO-CHIP instruction sets,
+ * use available_in to check an instruction against a Mode.
+ * Register operands (x, y) are indexes into V0-VF.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Sys(u16),                    // 0nnn
+    Cls,                         // 00E0
+    Ret,                         // 00EE
+    ScrollDown(u8),              // 00Cn (SCHIP)
+    ScrollUp(u8),                // 00Dn (XO-CHIP)
+    ScrollRight,                 // 00FB (SCHIP)
+    ScrollLeft,                  // 00FC (SCHIP)
+    Exit,                        // 00FD (SCHIP)
+    Low,                         // 00FE (SCHIP)
+    High,                        // 00FF (SCHIP)
+    Jp(u16),                     // 1nnn
+    Call(u16),                   // 2nnn
+    SeVxByte { x: u8, kk: u8 },  // 3xkk
+    SneVxByte { x: u8, kk: u8 }, // 4xkk
+    SeVxVy { x: u8, y: u8 },     // 5xy0
+    SaveRange { x: u8, y: u8 },  // 5xy2 (XO-CHIP)
+    LoadRange { x: u8, y: u8 },  // 5xy3 (XO-CHIP)
+    LdVxByte { x: u8, kk: u8 },  // 6xkk
+    AddVxByte { x: u8, kk: u8 }, // 7xkk
+    LdVxVy { x: u8, y: u8 },     // 8xy0
+    OrVxVy { x: u8, y: u8 },     // 8xy1
+    AndVxVy { x: u8, y: u8 },    // 8xy2
+    XorVxVy { x: u8, y: u8 },    // 8xy3
+    AddVxVy { x: u8, y: u8 },    // 8xy4
+    SubVxVy { x: u8, y: u8 },    // 8xy5
+    ShrVxVy { x: u8, y: u8 },    // 8xy6
+    SubnVxVy { x: u8, y: u8 },   // 8xy7
+    ShlVxVy { x: u8, y: u8 },    // 8xyE
+    SneVxVy { x: u8, y: u8 },    // 9xy0
+    LdI(u16),                    // Annn
+    JpV0(u16),                   // Bnnn
+    Rnd { x: u8, kk: u8 },       // Cxkk
+    Drw { x: u8, y: u8, n: u8 }, // Dxyn
+    Skp { x: u8 },               // Ex9E
+    Sknp { x: u8 },              // ExA1
+    LdILong(u16),                // F000 nnnn (XO-CHIP)
+    Plane(u8),                   // Fn01 (XO-CHIP)
+    Audio,                       // F002 (XO-CHIP)
+    LdVxDt { x: u8 },            // Fx07
+    LdVxK { x: u8 },             // Fx0A
+    LdDtVx { x: u8 },            // Fx15
+    LdStVx { x: u8 },            // Fx18
+    AddIVx { x: u8 },            // Fx1E
+    LdFVx { x: u8 },             // Fx29
+    LdHfVx { x: u8 },            // Fx30 (SCHIP)
+    LdBVx { x: u8 },             // Fx33
+    Pitch { x: u8 },             // Fx3A (XO-CHIP)
+    LdIVx { x: u8 },             // Fx55
+    LdVxI { x: u8 },             // Fx65
+    LdRVx { x: u8 },             // Fx75 (SCHIP)
+    LdVxR { x: u8 },             // Fx85 (SCHIP)
+}
+
+impl Instruction {
+    /**
+     * Decode a 16 bit opcode
+     *
+     * F000 is followed by a second word holding the address,
+     * it decodes to LdILong(0), use decode_at to read both words.
+     * Errors are reported as InvalidOpcode with a pc of 0, the
+     * caller knows where the opcode was fetched from.
+     */
+    pub fn decode(opcode: u16) -> Result<Instruction> {
+        use Instruction::*;
+
+        let nums = [
+            (opcode >> 12) & 0xF,
+            (opcode >> 8) & 0xF,
+            (opcode >> 4) & 0xF,
+            opcode & 0xF,
+        ];
+        let x = nums[1] as u8;
+        let y = nums[2] as u8;
+        let n = nums[3] as u8;
+        let kk = (opcode & 0x00FF) as u8;
+        let nnn = opcode & 0x0FFF;
+
+        let inst = match nums {
+            [0, 0, 0xE, 0] => Cls,
+            [0, 0, 0xE, 0xE] => Ret,
+            [0, 0, 0xC, _] => ScrollDown(n),
+            [0, 0, 0xD, _] => ScrollUp(n),
+            [0, 0, 0xF, 0xB] => ScrollRight,
+            [0, 0, 0xF, 0xC] => ScrollLeft,
+            [0, 0, 0xF, 0xD] => Exit,
+            [0, 0, 0xF, 0xE] => Low,
+            [0, 0, 0xF, 0xF] => High,
+            [0, _, _, _] => Sys(nnn),
+            [1, _, _, _] => Jp(nnn),
+            [2, _, _, _] => Call(nnn),
+            [3, _, _, _] => SeVxByte { x, kk },
+            [4, _, _, _] => SneVxByte { x, kk },
+            [5, _, _, 0] => SeVxVy { x, y },
+            [5, _, _, 2] => SaveRange { x, y },
+            [5, _, _, 3] => LoadRange { x, y },
+            [6, _, _, _] => LdVxByte { x, kk },
+            [7, _, _, _] => AddVxByte { x, kk },
+            [8, _, _, 0] => LdVxVy { x, y },
+            [8, _, _, 1] => OrVxVy { x, y },
+            [8, _, _, 2] => AndVxVy { x, y },
+            [8, _, _, 3] => XorVxVy { x, y },
+            [8, _, _, 4] => AddVxVy { x, y },
+            [8, _, _, 5] => SubVxVy { x, y },
+            [8, _, _, 6] => ShrVxVy { x, y },
+            [8, _, _, 7] => SubnVxVy { x, y },
+            [8, _, _, 0xE] => ShlVxVy { x, y },
+            [9, _, _, 0] => SneVxVy { x, y },
+            [0xA, _, _, _] => LdI(nnn),
+            [0xB, _, _, _] => JpV0(nnn),
+            [0xC, _, _, _] => Rnd { x, kk },
+            [0xD, _, _, _] => Drw { x, y, n },
+            [0xE, _, 9, 0xE] => Skp { x },
+            [0xE, _, 0xA, 1] => Sknp { x },
+            [0xF, 0, 0, 0] => LdILong(0),
+            [0xF, _, 0, 1] => Plane(x),
+            [0xF, 0, 0, 2] => Audio,
+            [0xF, _, 0, 7] => LdVxDt { x },
+            [0xF, _, 0, 0xA] => LdVxK { x },
+            [0xF, _, 1, 5] => LdDtVx { x },
+            [0xF, _, 1, 8] => LdStVx { x },
+            [0xF, _, 1, 0xE] => AddIVx { x },
+            [0xF, _, 2, 9] => LdFVx { x },
+            [0xF, _, 3, 0] => LdHfVx { x },
+            [0xF, _, 3, 3] => LdBVx { x },
+            [0xF, _, 3, 0xA] => Pitch { x },
+            [0xF, _, 5, 5] => LdIVx { x },
+            [0xF, _, 6, 5] => LdVxI { x },
+            [0xF, _, 7, 5] => LdRVx { x },
+            [0xF, _, 8, 5] => LdVxR { x },
+            [_, _, _, _] => return Err(Chip8Error::InvalidOpcode { opcode, pc: 0 }),
+        };
+        Ok(inst)
+    }
+
+    /**
+     * Decode the instruction at addr, including the address
+     * word following F000
+     */
+    pub fn decode_at(memory: &[u8], addr: usize) -> Result<Instruction> {
+        let word = |addr: usize| match memory.get(addr..addr + 2) {
+            Some(bytes) => Ok(u16::from_be_bytes([bytes[0], bytes[1]])),
+            None => Err(Chip8Error::MemoryOutOfBounds {
+                addr: addr.max(memory.len()),
+                pc: addr,
+            }),
+        };
+
+        let opcode = word(addr)?;
+        match Instruction::decode(opcode) {
+            Ok(Instruction::LdILong(_)) => Ok(Instruction::LdILong(word(addr + 2)?)),
+            Ok(inst) => Ok(inst),
+            Err(_) => Err(Chip8Error::InvalidOpcode { opcode, pc: addr }),
+        }
+    }
+
+    /**
+     * Encode the instruction as a 16 bit opcode, for LdILong
+     * this is the F000 prefix, see to_bytes
+     */
+    pub fn encode(&self) -> u16 {
+        use Instruction::*;
+
+        let xy = |op: u16, x: u8, y: u8, n: u16| op | (x as u16) << 8 | (y as u16) << 4 | n;
+        let xkk = |op: u16, x: u8, kk: u8| op | (x as u16) << 8 | kk as u16;
+        let fx = |x: u8, low: u16| 0xF000 | (x as u16) << 8 | low;
+
+        match *self {
+            Sys(nnn) => nnn & 0x0FFF,
+            Cls => 0x00E0,
+            Ret => 0x00EE,
+            ScrollDown(n) => 0x00C0 | (n as u16 & 0xF),
+            ScrollUp(n) => 0x00D0 | (n as u16 & 0xF),
+            ScrollRight => 0x00FB,
+            ScrollLeft => 0x00FC,
+            Exit => 0x00FD,
+            Low => 0x00FE,
+            High => 0x00FF,
+            Jp(nnn) => 0x1000 | (nnn & 0x0FFF),
+            Call(nnn) => 0x2000 | (nnn & 0x0FFF),
+            SeVxByte { x, kk } => xkk(0x3000, x, kk),
+            SneVxByte { x, kk } => xkk(0x4000, x, kk),
+            SeVxVy { x, y } => xy(0x5000, x, y, 0),
+            SaveRange { x, y } => xy(0x5000, x, y, 2),
+            LoadRange { x, y } => xy(0x5000, x, y, 3),
+            LdVxByte { x, kk } => xkk(0x6000, x, kk),
+            AddVxByte { x, kk } => xkk(0x7000, x, kk),
+            LdVxVy { x, y } => xy(0x8000, x, y, 0),
+            OrVxVy { x, y } => xy(0x8000, x, y, 1),
+            AndVxVy { x, y } => xy(0x8000, x, y, 2),
+            XorVxVy { x, y } => xy(0x8000, x, y, 3),
+            AddVxVy { x, y } => xy(0x8000, x, y, 4),
+            SubVxVy { x, y } => xy(0x8000, x, y, 5),
+            ShrVxVy { x, y } => xy(0x8000, x, y, 6),
+            SubnVxVy { x, y } => xy(0x8000, x, y, 7),
+            ShlVxVy { x, y } => xy(0x8000, x, y, 0xE),
+            SneVxVy { x, y } => xy(0x9000, x, y, 0),
+            LdI(nnn) => 0xA000 | (nnn & 0x0FFF),
+            JpV0(nnn) => 0xB000 | (nnn & 0x0FFF),
+            Rnd { x, kk } => xkk(0xC000, x, kk),
+            Drw { x, y, n } => xy(0xD000, x, y, n as u16 & 0xF),
+            Skp { x } => xkk(0xE000, x, 0x9E),
+            Sknp { x } => xkk(0xE000, x, 0xA1),
+            LdILong(_) => 0xF000,
+            Plane(n) => fx(n, 0x01),
+            Audio => 0xF002,
+            LdVxDt { x } => fx(x, 0x07),
+            LdVxK { x } => fx(x, 0x0A),
+            LdDtVx { x } => fx(x, 0x15),
+            LdStVx { x } => fx(x, 0x18),
+            AddIVx { x } => fx(x, 0x1E),
+            LdFVx { x } => fx(x, 0x29),
+            LdHfVx { x } => fx(x, 0x30),
+            LdBVx { x } => fx(x, 0x33),
+            Pitch { x } => fx(x, 0x3A),
+            LdIVx { x } => fx(x, 0x55),
+            LdVxI { x } => fx(x, 0x65),
+            LdRVx { x } => fx(x, 0x75),
+            LdVxR { x } => fx(x, 0x85),
+        }
+    }
+
+    /**
+     * The big endian bytes of the instruction as stored in memory
+     */
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.encode().to_be_bytes().to_vec();
+        if let Instruction::LdILong(addr) = self {
+            bytes.extend_from_slice(&addr.to_be_bytes());
+        }
+        bytes
+    }
+
+    /**
+     * Size of the instruction in bytes
+     */
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::LdILong(_) => 4,
+            _ => 2,
+        }
+    }
+
+    /**
+     * The earliest platform that supports this instruction
+     */
+    pub fn platform(&self) -> Mode {
+        use Instruction::*;
+        match self {
+            ScrollDown(_) | ScrollRight | ScrollLeft | Exit | Low | High => Mode::SuperChip,
+            LdHfVx { .. } | LdRVx { .. } | LdVxR { .. } => Mode::SuperChip,
+            ScrollUp(_) | SaveRange { .. } | LoadRange { .. } => Mode::XoChip,
+            LdILong(_) | Plane(_) | Audio | Pitch { .. } => Mode::XoChip,
+            _ => Mode::Chip8,
+        }
+    }
+
+    /**
+     * True if the instruction is part of the given instruction set
+     */
+    pub fn available_in(&self, mode: Mode) -> bool {
+        match (self.platform(), mode) {
+            (Mode::Chip8, _) => true,
+            (Mode::SuperChip, m) => m != Mode::Chip8,
+            (Mode::XoChip, m) => m == Mode::XoChip,
+        }
+    }
+
+    /**
+     * True if the instruction can change what is on screen
+     */
+    pub fn modifies_display(&self) -> bool {
+        use Instruction::*;
+        matches!(
+            self,
+            Cls | Drw { .. } | ScrollDown(_) | ScrollUp(_) | ScrollRight | ScrollLeft | Low | High
+        )
+    }
+}
+
+/**
+ * Standard mnemonic syntax, e.g. "LD V1, 0x05"
+ */
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Instruction::*;
+        match *self {
+            Sys(nnn) => write!(f, "SYS 0x{:03X}", nnn),
+            Cls => write!(f, "CLS"),
+            Ret => write!(f, "RET"),
+            ScrollDown(n) => write!(f, "SCD {}", n),
+            ScrollUp(n) => write!(f, "SCU {}", n),
+            ScrollRight => write!(f, "SCR"),
+            ScrollLeft => write!(f, "SCL"),
+            Exit => write!(f, "EXIT"),
+            Low => write!(f, "LOW"),
+            High => write!(f, "HIGH"),
+            Jp(nnn) => write!(f, "JP 0x{:03X}", nnn),
+            Call(nnn) => write!(f, "CALL 0x{:03X}", nnn),
+            SeVxByte { x, kk } => write!(f, "SE V{:X}, 0x{:02X}", x, kk),
+            SneVxByte { x, kk } => write!(f, "SNE V{:X}, 0x{:02X}", x, kk),
+            SeVxVy { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            SaveRange { x, y } => write!(f, "LD [I], V{:X} - V{:X}", x, y),
+            LoadRange { x, y } => write!(f, "LD V{:X} - V{:X}, [I]", x, y),
+            LdVxByte { x, kk } => write!(f, "LD V{:X}, 0x{:02X}", x, kk),
+            AddVxByte { x, kk } => write!(f, "ADD V{:X}, 0x{:02X}", x, kk),
+            LdVxVy { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            OrVxVy { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            AndVxVy { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            XorVxVy { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            AddVxVy { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            SubVxVy { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            ShrVxVy { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            SubnVxVy { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            ShlVxVy { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            SneVxVy { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            LdI(nnn) => write!(f, "LD I, 0x{:03X}", nnn),
+            JpV0(nnn) => write!(f, "JP V0, 0x{:03X}", nnn),
+            Rnd { x, kk } => write!(f, "RND V{:X}, 0x{:02X}", x, kk),
+            Drw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Skp { x } => write!(f, "SKP V{:X}", x),
+            Sknp { x } => write!(f, "SKNP V{:X}", x),
+            LdILong(nnnn) => write!(f, "LD I, LONG 0x{:04X}", nnnn),
+            Plane(n) => write!(f, "PLANE {}", n),
+            Audio => write!(f, "AUDIO"),
+            LdVxDt { x } => write!(f, "LD V{:X}, DT", x),
+            LdVxK { x } => write!(f, "LD V{:X}, K", x),
+            LdDtVx { x } => write!(f, "LD DT, V{:X}", x),
+            LdStVx { x } => write!(f, "LD ST, V{:X}", x),
+            AddIVx { x } => write!(f, "ADD I, V{:X}", x),
+            LdFVx { x } => write!(f, "LD F, V{:X}", x),
+            LdHfVx { x } => write!(f, "LD HF, V{:X}", x),
+            LdBVx { x } => write!(f, "LD B, V{:X}", x),
+            Pitch { x } => write!(f, "PITCH V{:X}", x),
+            LdIVx { x } => write!(f, "LD [I], V{:X}", x),
+            LdVxI { x } => write!(f, "LD V{:X}, [I]", x),
+            LdRVx { x } => write!(f, "LD R, V{:X}", x),
+            LdVxR { x } => write!(f, "LD V{:X}, R", x),
+        }
+    }
+}
@@ -1,7 +1,10 @@
 pub(crate) mod inst {
 
-    use crate::cpu::Cpu;
-    use crate::cpu::{DISP_HEIGHT, DISP_WIDTH, FLAG_REGISTER, FONT_SET};
+    use crate::cpu::{Cpu, Rect, STACK_SIZE};
+    use crate::cpu::{BIG_FONT_OFFSET, BIG_FONT_SET, FLAG_REGISTER, FONT_SET};
+    use crate::cpu::{DISP_HEIGHT, DISP_WIDTH, HIRES_HEIGHT, HIRES_WIDTH};
+    use crate::error::{Chip8Error, Result};
+    use crate::quirks::IndexIncrement;
 
     /**  
      *  0nnn - SYS addr
@@ -10,19 +13,85 @@ pub(crate) mod inst {
      *  originally implemented.  It is ignored bymodern interpreters.  
      *  This will not be implemented
      */
-    pub fn sys() {}
+    pub fn sys() -> Result<()> {
+        Ok(())
+    }
 
     /**
      *  00E0 - CLS
      *  Clear the display.
+     *
+     *  Only the selected XO-CHIP planes are cleared
      */
-    pub fn cls(cpu: &mut Cpu) {
-        if let Some((last, elems)) = cpu.display.split_last_mut() {
-            for el in elems {
-                *el = 0;
-            }
-            *last = 0
-        }
+    pub fn cls(cpu: &mut Cpu) -> Result<()> {
+        cpu.display.clear(cpu.planes);
+        let (width, height) = cpu.resolution();
+        cpu.mark_dirty(Rect::new(0, 0, width, height));
+        Ok(())
+    }
+
+    /**
+     *  Move the selected planes of the display by (dx, dy)
+     *  pixels, pixels scrolled in from the edges are cleared
+     */
+    fn scroll(cpu: &mut Cpu, dx: isize, dy: isize) {
+        let (width, height) = cpu.resolution();
+        cpu.display.scroll(cpu.planes, dx, dy);
+        cpu.mark_dirty(Rect::new(0, 0, width, height));
+    }
+
+    /**
+     *  00Cn - SCD nibble (SCHIP)
+     *  Scroll the display down by n pixels.
+     */
+    pub fn scd_n(cpu: &mut Cpu, n: u8) -> Result<()> {
+        scroll(cpu, 0, n as isize);
+        Ok(())
+    }
+
+    /**
+     *  00Dn - SCU nibble (XO-CHIP)
+     *  Scroll the display up by n pixels.
+     */
+    pub fn scu_n(cpu: &mut Cpu, n: u8) -> Result<()> {
+        scroll(cpu, 0, -(n as isize));
+        Ok(())
+    }
+
+    /**
+     *  00FB - SCR (SCHIP)
+     *  Scroll the display right by 4 pixels.
+     */
+    pub fn scr(cpu: &mut Cpu) -> Result<()> {
+        scroll(cpu, 4, 0);
+        Ok(())
+    }
+
+    /**
+     *  00FC - SCL (SCHIP)
+     *  Scroll the display left by 4 pixels.
+     */
+    pub fn scl(cpu: &mut Cpu) -> Result<()> {
+        scroll(cpu, -4, 0);
+        Ok(())
+    }
+
+    /**
+     *  00FE - LOW (SCHIP)
+     *  Switch to the 64x32 low resolution mode.
+     */
+    pub fn low(cpu: &mut Cpu) -> Result<()> {
+        cpu.set_resolution(DISP_WIDTH, DISP_HEIGHT);
+        Ok(())
+    }
+
+    /**
+     *  00FF - HIGH (SCHIP)
+     *  Switch to the 128x64 high resolution mode.
+     */
+    pub fn high(cpu: &mut Cpu) -> Result<()> {
+        cpu.set_resolution(HIRES_WIDTH, HIRES_HEIGHT);
+        Ok(())
     }
 
     /**  
@@ -31,22 +100,26 @@ pub(crate) mod inst {
      *  counter to theaddress at the top of the stack, then
      *  subtracts 1 from the stack pointer.
      */
-    pub fn ret(cpu: &mut Cpu) {
+    pub fn ret(cpu: &mut Cpu) -> Result<()> {
         cpu.program_counter = match cpu.stack.pop() {
-            Some(addr) => (addr - 2) as usize,
+            Some(addr) => (addr as usize).wrapping_sub(2),
             None => {
-                panic!("[*] segfault! No address on the stack to jump to.");
+                return Err(Chip8Error::StackUnderflow {
+                    pc: cpu.program_counter,
+                });
             }
-        }
+        };
+        Ok(())
     }
 
     /**  
      *  1nnn - JP addr
      *  Jump to address nnn
      */
-    pub fn jmp_nnn(cpu: &mut Cpu, opcode: u16) {
-        let addr = (opcode & 0x0FFF) as usize;
-        cpu.program_counter = addr - 2;
+    pub fn jmp_nnn(cpu: &mut Cpu, addr: u16) -> Result<()> {
+        let addr = addr as usize;
+        cpu.program_counter = addr.wrapping_sub(2);
+        Ok(())
     }
 
     /**  
@@ -55,10 +128,16 @@ pub(crate) mod inst {
      *  before jumping, save the next instruction address
      *  on the stack
      */
-    pub fn call(cpu: &mut Cpu, opcode: u16) {
-        let addr = (opcode & 0x0FFF) as usize;
+    pub fn call(cpu: &mut Cpu, addr: u16) -> Result<()> {
+        let addr = addr as usize;
+        if cpu.stack.len() >= STACK_SIZE {
+            return Err(Chip8Error::StackOverflow {
+                pc: cpu.program_counter,
+            });
+        }
         cpu.stack.push((cpu.program_counter + 2) as u16);
-        cpu.program_counter = addr - 2;
+        cpu.program_counter = addr.wrapping_sub(2);
+        Ok(())
     }
 
     /**  
@@ -67,11 +146,11 @@ pub(crate) mod inst {
      *  compares register Vx tokk, and if they are equal,
      *  increments the program counter by 2.
      */
-    pub fn se_vx_kk(cpu: &mut Cpu, reg: u16, opcode: u16) {
-        let value = (opcode & 0x00FF) as u8;
+    pub fn se_vx_kk(cpu: &mut Cpu, reg: u8, value: u8) -> Result<()> {
         if cpu.registers[reg as usize] == value {
-            cpu.program_counter += 2;
+            cpu.skip_next_instruction();
         }
+        Ok(())
     }
 
     /**  
@@ -80,11 +159,11 @@ pub(crate) mod inst {
      *  compares register Vx tokk, and if they are not equal,
      *  increments the program counter by 2.
      */
-    pub fn sne_vx_kk(cpu: &mut Cpu, reg: u16, opcode: u16) {
-        let value = (opcode & 0x00FF) as u8;
+    pub fn sne_vx_kk(cpu: &mut Cpu, reg: u8, value: u8) -> Result<()> {
         if cpu.registers[reg as usize] != value {
-            cpu.program_counter += 2;
+            cpu.skip_next_instruction();
         }
+        Ok(())
     }
 
     /**  
@@ -93,19 +172,20 @@ pub(crate) mod inst {
      *  register Vx toregister Vy, and if they are equal, increments
      *  the program counter by 2
      */
-    pub fn se_vx_vy(cpu: &mut Cpu, regx: u16, regy: u16) {
+    pub fn se_vx_vy(cpu: &mut Cpu, regx: u8, regy: u8) -> Result<()> {
         if cpu.registers[regx as usize] == cpu.registers[regy as usize] {
-            cpu.program_counter += 2;
+            cpu.skip_next_instruction();
         }
+        Ok(())
     }
 
     /**  
      *  6xkk - LD Vx, byte
      *  Loads the value kk into register Vx.
      */
-    pub fn ld_vx(cpu: &mut Cpu, reg: u16, opcode: u16) {
-        let value = (opcode & 0x00FF) as u8;
+    pub fn ld_vx(cpu: &mut Cpu, reg: u8, value: u8) -> Result<()> {
         cpu.registers[reg as usize] = value;
+        Ok(())
     }
 
     /**  
@@ -113,17 +193,18 @@ pub(crate) mod inst {
      *  Adds the value kk to the value of register Vx,
      *  then stores the result in Vx.
      */
-    pub fn add_vx_kk(cpu: &mut Cpu, reg: u16, opcode: u16) {
-        let value = opcode & 0x00FF;
-        cpu.registers[reg as usize] = cpu.registers[reg as usize].wrapping_add(value as u8);
+    pub fn add_vx_kk(cpu: &mut Cpu, reg: u8, value: u8) -> Result<()> {
+        cpu.registers[reg as usize] = cpu.registers[reg as usize].wrapping_add(value);
+        Ok(())
     }
 
     /**  
      *  8xy0 - LD Vx, Vy
      *  Stores the value of register Vy in register Vx.
      */
-    pub fn ld_vx_vy(cpu: &mut Cpu, regx: u16, regy: u16) {
+    pub fn ld_vx_vy(cpu: &mut Cpu, regx: u8, regy: u8) -> Result<()> {
         cpu.registers[regx as usize] = cpu.registers[regy as usize];
+        Ok(())
     }
 
     /**  
@@ -133,9 +214,15 @@ pub(crate) mod inst {
      *  the corrseponding bits from two values, and if either
      *  bit is 1, then the same bit in the result is also 1.
      *  Otherwise, it is 0.
+     *
+     *  The VIP interpreter also resets VF (quirks.logic_resets_vf)
      */
-    pub fn or_vx_vy(cpu: &mut Cpu, regx: u16, regy: u16) {
+    pub fn or_vx_vy(cpu: &mut Cpu, regx: u8, regy: u8) -> Result<()> {
         cpu.registers[regx as usize] |= cpu.registers[regy as usize];
+        if cpu.quirks.logic_resets_vf {
+            cpu.registers[FLAG_REGISTER] = 0;
+        }
+        Ok(())
     }
 
     /**  
@@ -145,9 +232,15 @@ pub(crate) mod inst {
      *  the corrseponding bits from two values, and if both
      *  bits are 1, then the same bit in the result is also 1.
      *  Otherwise, it is 0.
+     *
+     *  The VIP interpreter also resets VF (quirks.logic_resets_vf)
      */
-    pub fn and_vx_vy(cpu: &mut Cpu, regx: u16, regy: u16) {
+    pub fn and_vx_vy(cpu: &mut Cpu, regx: u8, regy: u8) -> Result<()> {
         cpu.registers[regx as usize] &= cpu.registers[regy as usize];
+        if cpu.quirks.logic_resets_vf {
+            cpu.registers[FLAG_REGISTER] = 0;
+        }
+        Ok(())
     }
 
     /**  
@@ -157,9 +250,15 @@ pub(crate) mod inst {
      *  corrseponding bits from two values, and if the bits are not
      *  both the same, then the corresponding bit in the result is
      *  set to 1. Otherwise, it is 0.
+     *
+     *  The VIP interpreter also resets VF (quirks.logic_resets_vf)
      */
-    pub fn xor_vx_vy(cpu: &mut Cpu, regx: u16, regy: u16) {
+    pub fn xor_vx_vy(cpu: &mut Cpu, regx: u8, regy: u8) -> Result<()> {
         cpu.registers[regx as usize] ^= cpu.registers[regy as usize];
+        if cpu.quirks.logic_resets_vf {
+            cpu.registers[FLAG_REGISTER] = 0;
+        }
+        Ok(())
     }
 
     /**  
@@ -168,8 +267,8 @@ pub(crate) mod inst {
      *  greater than 8 bits (i.e., > 255,) VF is set to 1, otherwise 0.
      *  Only the lowest 8 bits of the result are kept, and stored in Vx.
      */
-    pub fn add_vx_vy(cpu: &mut Cpu, regx: u16, regy: u16) {
-        match cpu.registers[regx as usize].overflowing_add(cpu.registers[regy as usize] as u8) {
+    pub fn add_vx_vy(cpu: &mut Cpu, regx: u8, regy: u8) -> Result<()> {
+        match cpu.registers[regx as usize].overflowing_add(cpu.registers[regy as usize]) {
             (v, true) => {
                 cpu.registers[regx as usize] = v;
                 cpu.registers[FLAG_REGISTER] = 1u8;
@@ -179,6 +278,7 @@ pub(crate) mod inst {
                 cpu.registers[FLAG_REGISTER] = 0u8;
             }
         }
+        Ok(())
     }
 
     /**  
@@ -186,8 +286,8 @@ pub(crate) mod inst {
      *  Subtract the value of register VY from register VX
      *  If Vx > Vy, then VF is set to 1, otherwise 0.
      */
-    pub fn sub_vx_vy(cpu: &mut Cpu, regx: u16, regy: u16) {
-        match cpu.registers[regx as usize].overflowing_sub(cpu.registers[regy as usize] as u8) {
+    pub fn sub_vx_vy(cpu: &mut Cpu, regx: u8, regy: u8) -> Result<()> {
+        match cpu.registers[regx as usize].overflowing_sub(cpu.registers[regy as usize]) {
             (v, true) => {
                 cpu.registers[regx as usize] = v;
                 cpu.registers[FLAG_REGISTER] = 0u8; // 0 if underflow occured
@@ -197,16 +297,23 @@ pub(crate) mod inst {
                 cpu.registers[FLAG_REGISTER] = 1u8; // 1 if underflow didn't occur
             }
         }
+        Ok(())
     }
 
     /**  
      *  8xy6 - SHR Vx{, Vy}
      *  Store the value of register VY shifted right one bit in register VX
      *  Set register VF to the least significant bit prior to the shift
+     *
+     *  Later interpreters shift VX in place and ignore VY
+     *  unless quirks.shift_uses_vy is set
      */
-    pub fn shr_vx_vy(cpu: &mut Cpu, regx: u16, _regy: u16) {
-        cpu.registers[FLAG_REGISTER] = cpu.registers[regx as usize] & 1; 
-        cpu.registers[regx as usize] = cpu.registers[regx as usize].wrapping_shr(1);
+    pub fn shr_vx_vy(cpu: &mut Cpu, regx: u8, regy: u8) -> Result<()> {
+        let src = if cpu.quirks.shift_uses_vy { regy } else { regx };
+        let value = cpu.registers[src as usize];
+        cpu.registers[regx as usize] = value.wrapping_shr(1);
+        cpu.registers[FLAG_REGISTER] = value & 1;
+        Ok(())
     }
 
     /**  
@@ -215,8 +322,8 @@ pub(crate) mod inst {
      *  Set VF to 00 if a borrow occurs
      *  Set VF to 01 if a borrow does not occur
      */
-    pub fn subn_vx_vy(cpu: &mut Cpu, regx: u16, regy: u16) {
-        match cpu.registers[regy as usize].overflowing_sub(cpu.registers[regx as usize] as u8) {
+    pub fn subn_vx_vy(cpu: &mut Cpu, regx: u8, regy: u8) -> Result<()> {
+        match cpu.registers[regy as usize].overflowing_sub(cpu.registers[regx as usize]) {
             (v, true) => {
                 cpu.registers[regx as usize] = v;
                 cpu.registers[FLAG_REGISTER] = 0u8; // 0 if underflow occured
@@ -226,16 +333,23 @@ pub(crate) mod inst {
                 cpu.registers[FLAG_REGISTER] = 1u8; // 1 if underflow didn't occur
             }
         }
+        Ok(())
     }
 
     /**  
      *  8xyE - SHL Vx{, Vy}
      *  Store the value of register VY shifted left one bit in register VX
      *  Set register VF to the most significant bit prior to the shift
+     *
+     *  Later interpreters shift VX in place and ignore VY
+     *  unless quirks.shift_uses_vy is set
      */
-    pub fn shl_vx_vy(cpu: &mut Cpu, regx: u16, _regy: u16) {
-        cpu.registers[FLAG_REGISTER] = cpu.registers[regx as usize] >> 7; 
-        cpu.registers[regx as usize] = cpu.registers[regx as usize].wrapping_shl(1);
+    pub fn shl_vx_vy(cpu: &mut Cpu, regx: u8, regy: u8) -> Result<()> {
+        let src = if cpu.quirks.shift_uses_vy { regy } else { regx };
+        let value = cpu.registers[src as usize];
+        cpu.registers[regx as usize] = value.wrapping_shl(1);
+        cpu.registers[FLAG_REGISTER] = value >> 7;
+        Ok(())
     }
 
     /**
@@ -243,29 +357,38 @@ pub(crate) mod inst {
      *  The values of Vx and Vy are compared, and if they are not
      *  equal, the program counter is increased by 2.
      */
-    pub fn sne_vx_vy(cpu: &mut Cpu, regx: u16, regy: u16) {
+    pub fn sne_vx_vy(cpu: &mut Cpu, regx: u8, regy: u8) -> Result<()> {
         if cpu.registers[regx as usize] != cpu.registers[regy as usize] {
-            cpu.program_counter += 2;
+            cpu.skip_next_instruction();
         }
+        Ok(())
     }
 
     /**  
      *  Annn - LD I, addr
      *  Set I = nnn.  The value of register I is set to nnn.
      */
-    pub fn ld_i_nnn(cpu: &mut Cpu, opcode: u16) {
-        let addr = (opcode & 0x0FFF) as u16;
+    pub fn ld_i_nnn(cpu: &mut Cpu, addr: u16) -> Result<()> {
         cpu.i_register = addr;
+        Ok(())
     }
 
     /**
      *  Bnnn - JP V0, addr
      *  Jump  to  location  nnn  +  V0.   The  program  counter
      *  is  set  to  nnn  plus  thevalue of V0.
+     *
+     *  CHIP-48 and SCHIP treat this as Bxnn and jump to
+     *  xnn + Vx instead (quirks.jump_uses_vx)
      */
-    pub fn jmp_v0_nnn(cpu: &mut Cpu, opcode: u16) {
-        let addr = (opcode & 0x0FFF) as u16;
-        cpu.program_counter = ((cpu.registers[0] as u16) + addr - 2) as usize;
+    pub fn jmp_v0_nnn(cpu: &mut Cpu, addr: u16) -> Result<()> {
+        let reg = if cpu.quirks.jump_uses_vx {
+            ((addr >> 8) & 0xF) as usize
+        } else {
+            0
+        };
+        cpu.program_counter = (cpu.registers[reg] as usize + addr as usize).wrapping_sub(2);
+        Ok(())
     }
 
     /**
@@ -274,43 +397,102 @@ pub(crate) mod inst {
      *  numberfrom 0 to 255, which is then ANDed with the value kk.  
      *  The results are storedin Vx.  
      */
-    pub fn rnd_vx_kk(cpu: &mut Cpu, reg: u16, opcode: u16) {
-        use rand::Rng;
-        let mut rng = rand::thread_rng();
-        let value = (opcode & 0x00FF) as u8;
-        cpu.registers[reg as usize] = rng.gen::<u8>() & value;
+    pub fn rnd_vx_kk(cpu: &mut Cpu, reg: u8, value: u8) -> Result<()> {
+        cpu.registers[reg as usize] = cpu.rng.next_byte() & value;
+        Ok(())
     }
 
     /**
      * Dxyn - DRW Vx, Vy, nibble
      * Display n-byte sprite starting at memory location I at
      * (Vx, Vy), set VF = collision.
+     *
+     * The starting coordinates always wrap, pixels that
+     * run off the edge of the screen are clipped unless
+     * quirks.draw_wraps is set
+     *
+     * Dxy0 draws a 16x16 sprite in SCHIP mode
+     *
+     * In XO-CHIP mode the sprite is drawn on every selected
+     * plane, with the data for each plane following the last
      */
-    pub fn drw_vx_vy_n(cpu: &mut Cpu, regx: u16, regy: u16, n: u16) {
-        let x = ((cpu.registers[regx as usize] as usize) % DISP_WIDTH) as u16;
-        let y = ((cpu.registers[regy as usize] as usize) % DISP_HEIGHT) as u16;
-        let start_pos = (x + (y * (DISP_WIDTH as u16))) as usize;
+    pub fn drw_vx_vy_n(cpu: &mut Cpu, regx: u8, regy: u8, n: u8) -> Result<()> {
+        let (width, height) = cpu.resolution();
+        let x = (cpu.registers[regx as usize] as usize) % width;
+        let y = (cpu.registers[regy as usize] as usize) % height;
+
+        // sprite dimensions in pixels
+        let (cols, rows) = cpu.sprite_size(n);
+        let row_bytes = cols / 8;
+
+        // make sure the sprite data for every selected plane is in memory
+        let len = rows * row_bytes * cpu.planes.count_ones() as usize;
+        let mut sprite = cpu.memory_range(cpu.i_register as usize, len)?.start;
+
+        let wraps = cpu.quirks.draw_wraps;
         cpu.registers[FLAG_REGISTER] = 0;
-        for row in 0..(n as usize) {
-            for col in 0..8 {
-                let disp_pos = start_pos + col + (row * DISP_WIDTH);
-                let mem_pos = (cpu.i_register as usize) + row;
+        let mut drawn = false;
+        for plane in 0..2 {
+            if cpu.planes & (1 << plane) == 0 {
+                continue;
+            }
+            for row in 0..rows {
+                let mut py = y + row;
 
                 // check if boundary has been reached
-                if disp_pos >= cpu.display.len() {
-                    continue;
+                if py >= height {
+                    if !wraps {
+                        break;
+                    }
+                    py %= height;
                 }
 
-                // each byte in memory contains 8 pixels for our display
-                // so we must get the individual bit value for this row,col
-                let mem_val = cpu.memory[mem_pos] >> (7 - col) & 0x01;
+                // each byte in memory contains 8 pixels of the row,
+                // leftmost pixel first, which are XORed in at once
+                let data = &cpu.memory[sprite + row * row_bytes..][..row_bytes];
+                let bits = data.iter().fold(0u128, |acc, b| acc << 8 | *b as u128);
+                let mask = cpu.display.sprite_row(x, bits, cols, wraps);
+                if mask == 0 {
+                    continue;
+                }
 
-                if cpu.display[disp_pos] == 1 && mem_val == 1 {
+                let mut collided = cpu.display.xor_row(plane, py, mask);
+                if collided != 0 {
                     cpu.registers[FLAG_REGISTER] = 1;
-                    cpu.phosphor_glow[disp_pos] = 2; // ticks until clear
                 }
-                cpu.display[disp_pos] ^= mem_val;
+                while collided != 0 {
+                    let px = width - 1 - collided.trailing_zeros() as usize;
+                    cpu.phosphor_glow[px + py * width] = cpu.phosphor_decay;
+                    collided &= collided - 1;
+                }
+                drawn = true;
             }
+            sprite += rows * row_bytes;
+        }
+
+        // a wrapped sprite is split in up to four pieces
+        if drawn {
+            for (sx, sw) in spans(x, cols, width, wraps) {
+                for (sy, sh) in spans(y, rows, height, wraps) {
+                    cpu.mark_dirty(Rect::new(sx, sy, sw, sh));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * The (start, length) runs covered by len pixels from
+     * start on an axis of size pixels, either clipped or
+     * wrapped around to 0
+     */
+    fn spans(start: usize, len: usize, size: usize, wraps: bool) -> Vec<(usize, usize)> {
+        if start + len <= size {
+            vec![(start, len)]
+        } else if wraps {
+            vec![(start, size - start), (0, start + len - size)]
+        } else {
+            vec![(start, size - start)]
         }
     }
 
@@ -318,22 +500,24 @@ pub(crate) mod inst {
      * Ex9E - SKP Vx
      * Skip next instruction if key with the value of Vx is pressed.
      */
-    pub(crate) fn skp_vx(cpu: &mut Cpu, reg: u16) {
-        let key = cpu.registers[reg as usize] as usize;
-        if cpu.keyboard.get(key) == Some(&true) {
-            cpu.program_counter += 2;
+    pub(crate) fn skp_vx(cpu: &mut Cpu, reg: u8) -> Result<()> {
+        let key = cpu.registers[reg as usize];
+        if cpu.is_key_pressed(key) {
+            cpu.skip_next_instruction();
         }
+        Ok(())
     }
 
     /**
      * ExA1 - SKNP Vx
      * Skip next instruction if key with the value of Vx is not pressed.
      */
-    pub(crate) fn sknp_vx(cpu: &mut Cpu, reg: u16) {
-        let key = cpu.registers[reg as usize] as usize;
-        if cpu.keyboard.get(key) == Some(&false) {
-            cpu.program_counter += 2;
+    pub(crate) fn sknp_vx(cpu: &mut Cpu, reg: u8) -> Result<()> {
+        let key = cpu.registers[reg as usize];
+        if key < 16 && !cpu.is_key_pressed(key) {
+            cpu.skip_next_instruction();
         }
+        Ok(())
     }
 
     /**
@@ -341,8 +525,9 @@ pub(crate) mod inst {
      *
      * Set Vx = delay timer value.
      */
-    pub(crate) fn ld_vx_dt(cpu: &mut Cpu, reg: u16) {
+    pub(crate) fn ld_vx_dt(cpu: &mut Cpu, reg: u8) -> Result<()> {
         cpu.registers[reg as usize] = cpu.delay_timer;
+        Ok(())
     }
 
     /**
@@ -350,8 +535,9 @@ pub(crate) mod inst {
      *
      * Set delay timer = Vx.
      */
-    pub(crate) fn ld_dt_vx(cpu: &mut Cpu, reg: u16) {
+    pub(crate) fn ld_dt_vx(cpu: &mut Cpu, reg: u8) -> Result<()> {
         cpu.delay_timer = cpu.registers[reg as usize];
+        Ok(())
     }
 
     /**
@@ -359,18 +545,20 @@ pub(crate) mod inst {
      *
      * Set sound timer = Vx.
      */
-    pub(crate) fn ld_st_vx(cpu: &mut Cpu, reg: u16) {
-        cpu.sound_timer = cpu.registers[reg as usize];
+    pub(crate) fn ld_st_vx(cpu: &mut Cpu, reg: u8) -> Result<()> {
+        cpu.set_sound_timer(cpu.registers[reg as usize]);
+        Ok(())
     }
 
     /**
      * Fx1E - ADD I, Vx
      * Set I = I + Vx.
      */
-    pub(crate) fn add_i_vx(cpu: &mut Cpu, reg: u16) {
+    pub(crate) fn add_i_vx(cpu: &mut Cpu, reg: u8) -> Result<()> {
         cpu.i_register = cpu
             .i_register
             .wrapping_add(cpu.registers[reg as usize].into());
+        Ok(())
     }
 
     /**
@@ -379,12 +567,34 @@ pub(crate) mod inst {
      * The value of I is set to the location for the hexadecimal sprite
      * corresponding to the value of Vx in the font set
      */
-    pub(crate) fn ld_f_vx(cpu: &mut Cpu, reg: u16) {
-        let addr = cpu.registers[reg as usize] * 5;
-        if addr as usize > FONT_SET.len() {
-            panic!("No fontset for {:?}", addr);
+    pub(crate) fn ld_f_vx(cpu: &mut Cpu, reg: u8) -> Result<()> {
+        let digit = cpu.registers[reg as usize];
+        let addr = digit as usize * 5;
+        if addr >= FONT_SET.len() {
+            return Err(Chip8Error::UnsupportedFontDigit {
+                digit,
+                pc: cpu.program_counter,
+            });
         }
-        cpu.i_register = addr.into();
+        cpu.i_register = addr as u16;
+        Ok(())
+    }
+
+    /**
+     * Fx30 - LD HF, Vx (SCHIP)
+     * Set I = location of the large 8x10 sprite for digit Vx.
+     */
+    pub(crate) fn ld_hf_vx(cpu: &mut Cpu, reg: u8) -> Result<()> {
+        let digit = cpu.registers[reg as usize];
+        let addr = BIG_FONT_OFFSET + digit as usize * 10;
+        if addr >= BIG_FONT_OFFSET + BIG_FONT_SET.len() {
+            return Err(Chip8Error::UnsupportedFontDigit {
+                digit,
+                pc: cpu.program_counter,
+            });
+        }
+        cpu.i_register = addr as u16;
+        Ok(())
     }
 
     /**
@@ -395,30 +605,141 @@ pub(crate) mod inst {
      * I+1 = the tens digit of Vx
      * I+2 = the ones digit of Vx
      */
-    pub(crate) fn ld_b_vx(cpu: &mut Cpu, reg: u16) {
-        let addr = cpu.i_register as usize;
+    pub(crate) fn ld_b_vx(cpu: &mut Cpu, reg: u8) -> Result<()> {
+        let addr = cpu.memory_range(cpu.i_register as usize, 3)?.start;
         cpu.memory[addr] = (cpu.registers[reg as usize] / 100) % 10;
         cpu.memory[addr + 1] = (cpu.registers[reg as usize] / 10) % 10;
         cpu.memory[addr + 2] = cpu.registers[reg as usize] % 10;
+        Ok(())
+    }
+
+    /**
+     * Apply quirks.index_increment to I after Fx55/Fx65
+     */
+    fn increment_i(cpu: &mut Cpu, reg: u8) {
+        cpu.i_register = match cpu.quirks.index_increment {
+            IndexIncrement::None => cpu.i_register,
+            IndexIncrement::ByX => cpu.i_register.wrapping_add(reg as u16),
+            IndexIncrement::ByXPlusOne => cpu.i_register.wrapping_add(reg as u16 + 1),
+        };
     }
 
     /**
      * Fx55 - LD [I], Vx
      * Store registers V0 through Vx in memory starting at location I.
      */
-    pub(crate) fn ld_i_vx(cpu: &mut Cpu, reg: u16) {
-        let addr = cpu.i_register as usize;
+    pub(crate) fn ld_i_vx(cpu: &mut Cpu, reg: u8) -> Result<()> {
         let n = reg as usize;
-        cpu.memory[addr..addr + n+1].copy_from_slice(&cpu.registers[0..n+1]);
+        let range = cpu.memory_range(cpu.i_register as usize, n + 1)?;
+        cpu.memory[range].copy_from_slice(&cpu.registers[0..n + 1]);
+        increment_i(cpu, reg);
+        Ok(())
     }
 
-    /*
+    /**
      * Fx65 - LD Vx, [I]
      * Read registers V0 through Vx from memory starting at location I.
      */
-    pub(crate) fn ld_vx_i(cpu: &mut Cpu, reg: u16) {
-        let addr = cpu.i_register as usize;
+    pub(crate) fn ld_vx_i(cpu: &mut Cpu, reg: u8) -> Result<()> {
         let n = reg as usize;
-        cpu.registers[0..n + 1].copy_from_slice(&cpu.memory[addr..addr + n + 1]);
+        let range = cpu.memory_range(cpu.i_register as usize, n + 1)?;
+        cpu.registers[0..n + 1].copy_from_slice(&cpu.memory[range]);
+        increment_i(cpu, reg);
+        Ok(())
+    }
+
+    /**
+     * Fx75 - LD R, Vx (SCHIP)
+     * Store registers V0 through Vx in the RPL user flags.
+     */
+    pub(crate) fn ld_r_vx(cpu: &mut Cpu, reg: u8) -> Result<()> {
+        let n = reg as usize;
+        cpu.rpl_flags[0..n + 1].copy_from_slice(&cpu.registers[0..n + 1]);
+        Ok(())
+    }
+
+    /**
+     * Fx85 - LD Vx, R (SCHIP)
+     * Read registers V0 through Vx from the RPL user flags.
+     */
+    pub(crate) fn ld_vx_r(cpu: &mut Cpu, reg: u8) -> Result<()> {
+        let n = reg as usize;
+        cpu.registers[0..n + 1].copy_from_slice(&cpu.rpl_flags[0..n + 1]);
+        Ok(())
+    }
+
+    /**
+     * 5xy2 - LD [I], Vx - Vy (XO-CHIP)
+     * Store registers Vx through Vy in memory starting at
+     * location I, in reverse order if x > y. I is not modified.
+     */
+    pub(crate) fn ld_i_vx_vy(cpu: &mut Cpu, regx: u8, regy: u8) -> Result<()> {
+        let (x, y) = (regx as usize, regy as usize);
+        let len = (x as isize - y as isize).unsigned_abs() + 1;
+        let addr = cpu.memory_range(cpu.i_register as usize, len)?.start;
+        for offset in 0..len {
+            let reg = if x <= y { x + offset } else { x - offset };
+            cpu.memory[addr + offset] = cpu.registers[reg];
+        }
+        Ok(())
+    }
+
+    /**
+     * 5xy3 - LD Vx - Vy, [I] (XO-CHIP)
+     * Read registers Vx through Vy from memory starting at
+     * location I, in reverse order if x > y. I is not modified.
+     */
+    pub(crate) fn ld_vx_vy_i(cpu: &mut Cpu, regx: u8, regy: u8) -> Result<()> {
+        let (x, y) = (regx as usize, regy as usize);
+        let len = (x as isize - y as isize).unsigned_abs() + 1;
+        let addr = cpu.memory_range(cpu.i_register as usize, len)?.start;
+        for offset in 0..len {
+            let reg = if x <= y { x + offset } else { x - offset };
+            cpu.registers[reg] = cpu.memory[addr + offset];
+        }
+        Ok(())
+    }
+
+    /**
+     * F000 NNNN - LD I, long addr (XO-CHIP)
+     * Set I to the 16 bit address following the instruction,
+     * the program counter skips over it.
+     */
+    pub(crate) fn ld_i_nnnn(cpu: &mut Cpu, addr: u16) -> Result<()> {
+        cpu.i_register = addr;
+        cpu.program_counter += 2;
+        Ok(())
+    }
+
+    /**
+     * Fn01 - PLANE n (XO-CHIP)
+     * Select the drawing planes affected by CLS, DRW and
+     * the scroll instructions.
+     */
+    pub(crate) fn plane_n(cpu: &mut Cpu, n: u8) -> Result<()> {
+        cpu.planes = n & 0x3;
+        Ok(())
+    }
+
+    /**
+     * F002 - AUDIO (XO-CHIP)
+     * Load the 16 byte audio pattern buffer from memory
+     * starting at location I.
+     */
+    pub(crate) fn audio(cpu: &mut Cpu) -> Result<()> {
+        let range = cpu.memory_range(cpu.i_register as usize, cpu.audio_pattern.len())?;
+        cpu.audio_pattern.copy_from_slice(&cpu.memory[range]);
+        cpu.buzzer.set_pattern(&cpu.audio_pattern, cpu.pitch);
+        Ok(())
+    }
+
+    /**
+     * Fx3A - PITCH Vx (XO-CHIP)
+     * Set the audio pattern playback pitch to Vx.
+     */
+    pub(crate) fn pitch_vx(cpu: &mut Cpu, reg: u8) -> Result<()> {
+        cpu.pitch = cpu.registers[reg as usize];
+        cpu.buzzer.set_pattern(&cpu.audio_pattern, cpu.pitch);
+        Ok(())
     }
 }
@@ -0,0 +1,193 @@
+use crate::error::{Chip8Error, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/**
+ * Host key codes for each CHIP-8 key 0-F on the QWERTY
+ * layout, using the 4x4 block starting at the 1 key:
+ *
+ *   1 2 3 4        1 2 3 C
+ *   Q W E R   ->   4 5 6 D
+ *   A S D F        7 8 9 E
+ *   Z X C V        A 0 B F
+ */
+const QWERTY: [usize; 16] = [
+    88, 49, 50, 51, 81, 87, 69, 65, 83, 68, 90, 67, 52, 82, 70, 86,
+];
+
+// the same physical keys on AZERTY: A Z E R / Q S D F / W X C V
+const AZERTY: [usize; 16] = [
+    88, 49, 50, 51, 65, 90, 69, 81, 83, 68, 87, 67, 52, 82, 70, 86,
+];
+
+// the same physical keys on Dvorak: ' , . P / A O E U / ; Q J K
+const DVORAK: [usize; 16] = [
+    81, 49, 50, 51, 222, 188, 190, 65, 79, 69, 186, 74, 52, 80, 85, 75,
+];
+
+/**
+ * Maps host key codes (as reported by JS `keyCode`)
+ * to CHIP-8 keys 0-F
+ *
+ * Keymaps can be parsed from a TOML style list of
+ * `host = chip8` bindings, one per line:
+ *
+ * ```text
+ * # host key = CHIP-8 key
+ * Q = 0x4
+ * 1 = 0x1
+ * 188 = 0x5   # numbers longer than one digit are raw key codes
+ * ```
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    keys: BTreeMap<usize, u8>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::qwerty()
+    }
+}
+
+impl Keymap {
+    /**
+     * A keymap with no bindings
+     */
+    pub fn empty() -> Self {
+        Keymap {
+            keys: BTreeMap::new(),
+        }
+    }
+
+    fn from_layout(layout: &[usize; 16]) -> Self {
+        let mut keymap = Self::empty();
+        for (key, code) in layout.iter().enumerate() {
+            keymap.bind(*code, key as u8).unwrap();
+        }
+        keymap
+    }
+
+    pub fn qwerty() -> Self {
+        Self::from_layout(&QWERTY)
+    }
+
+    pub fn azerty() -> Self {
+        Self::from_layout(&AZERTY)
+    }
+
+    pub fn dvorak() -> Self {
+        Self::from_layout(&DVORAK)
+    }
+
+    /**
+     * A preset by name: qwerty, azerty or dvorak
+     */
+    pub fn preset(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "qwerty" => Some(Self::qwerty()),
+            "azerty" => Some(Self::azerty()),
+            "dvorak" => Some(Self::dvorak()),
+            _ => None,
+        }
+    }
+
+    /**
+     * Map a host key code to CHIP-8 key 0-F, replacing
+     * any previous binding for that code
+     */
+    pub fn bind(&mut self, code: usize, key: u8) -> Result<()> {
+        if key >= 16 {
+            return Err(Chip8Error::InvalidKey { key });
+        }
+        self.keys.insert(code, key);
+        Ok(())
+    }
+
+    /**
+     * The CHIP-8 key for a host key code, if it is mapped
+     */
+    pub fn translate(&self, code: usize) -> Option<u8> {
+        self.keys.get(&code).copied()
+    }
+}
+
+/**
+ * A host key, either a single letter or digit,
+ * or a raw key code
+ */
+fn parse_code(s: &str) -> Option<usize> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphanumeric() => Some(c.to_ascii_uppercase() as usize),
+        _ => s.parse().ok(),
+    }
+}
+
+/**
+ * A CHIP-8 key, a hex digit with an optional 0x prefix
+ */
+fn parse_key(s: &str) -> Option<u8> {
+    let digits = s.strip_prefix("0x").unwrap_or(s);
+    u8::from_str_radix(digits, 16).ok().filter(|key| *key < 16)
+}
+
+fn unquote(s: &str) -> &str {
+    let s = s.trim();
+    if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+        &s[1..s.len() - 1]
+    } else {
+        s
+    }
+}
+
+impl std::str::FromStr for Keymap {
+    type Err = String;
+
+    /**
+     * Parse `host = chip8` bindings, ignoring blank
+     * lines, `#` comments and `[section]` headers
+     */
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut keymap = Keymap::empty();
+        for (index, line) in s.lines().enumerate() {
+            let line = match line.find('#') {
+                Some(comment) => &line[..comment],
+                None => line,
+            }
+            .trim();
+            if line.is_empty() || line.starts_with('[') {
+                continue;
+            }
+
+            let (host, key) = match line.find('=') {
+                Some(eq) => (unquote(&line[..eq]), unquote(&line[eq + 1..])),
+                None => return Err(format!("line {}: expected host = key", index + 1)),
+            };
+            let code = parse_code(host)
+                .ok_or_else(|| format!("line {}: invalid host key '{}'", index + 1, host))?;
+            let key = parse_key(key)
+                .ok_or_else(|| format!("line {}: invalid CHIP-8 key '{}'", index + 1, key))?;
+            // parse_key only yields keys 0-F
+            keymap.bind(code, key).unwrap();
+        }
+        Ok(keymap)
+    }
+}
+
+/**
+ * Writes the bindings in the format accepted by FromStr
+ */
+impl fmt::Display for Keymap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (code, key) in &self.keys {
+            let host = match *code as u8 as char {
+                c if *code < 128 && c.is_ascii_alphanumeric() => c.to_string(),
+                // at least two digits, so it isn't read back as a character
+                _ => format!("{:02}", code),
+            };
+            writeln!(f, "{} = 0x{:X}", host, key)?;
+        }
+        Ok(())
+    }
+}
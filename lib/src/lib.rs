@@ -1,9 +1,60 @@
 /**
  * Exported
  */
+pub mod asm;
+pub mod audio;
 pub mod cpu;
+pub mod debugger;
+pub mod disasm;
+pub mod error;
+pub mod framebuffer;
+pub mod gdb;
+pub mod instruction;
+pub mod keymap;
+pub mod machine;
+pub mod movie;
+pub mod palette;
+pub mod peripherals;
+pub mod quirks;
+pub mod rewind;
+pub mod rng;
+pub mod trace;
 
 mod instructions;
+mod snapshot;
 
 #[cfg(test)]
+mod test_asm;
+#[cfg(test)]
+mod test_audio;
+#[cfg(test)]
+mod test_debugger;
+#[cfg(test)]
+mod test_dirty;
+#[cfg(test)]
+mod test_disasm;
+#[cfg(test)]
+mod test_framebuffer;
+#[cfg(test)]
+mod test_gdb;
+#[cfg(test)]
+mod test_instruction;
+#[cfg(test)]
+#[allow(clippy::module_inception, clippy::mixed_case_hex_literals)]
 mod test_instructions;
+#[cfg(test)]
+mod test_keymap;
+#[cfg(test)]
+mod test_machine;
+#[cfg(test)]
+mod test_movie;
+#[cfg(test)]
+mod test_palette;
+#[cfg(test)]
+mod test_peripherals;
+#[cfg(test)]
+mod test_rewind;
+#[cfg(test)]
+mod test_state;
+#[cfg(test)]
+mod test_trace;
@@ -0,0 +1,222 @@
+use crate::cpu::Cpu;
+use crate::error::{Chip8Error, Result};
+use crate::rewind::Rewind;
+use std::time::Duration;
+
+// the delay and sound timers count down at 60Hz
+pub const FRAME_DURATION: Duration = Duration::from_micros(16_667);
+
+// instructions per frame, roughly 600Hz
+pub const DEFAULT_CYCLES_PER_FRAME: usize = 10;
+
+// frames run_for will catch up on at most, so a
+// long pause doesn't fast forward the program
+const MAX_FRAMES_PER_CALL: u32 = 6;
+
+/**
+ * What happened while running one or more frames
+ */
+#[derive(Debug, Default)]
+pub struct FrameEvents {
+    /// Frames completed
+    pub frames: u32,
+    /// Instructions executed
+    pub cycles: usize,
+    /// The framebuffer differs from the start of the frame,
+    /// it was drawn to, cleared, scrolled or resized
+    pub display_dirty: bool,
+    /// The sound timer became non-zero
+    pub sound_started: bool,
+    /// The sound timer reached zero
+    pub sound_stopped: bool,
+    /// The sound timer is running at the end of the frame
+    pub sound_on: bool,
+    /// Fx0A is blocking until a key is pressed
+    pub waiting_for_key: bool,
+    /// The program executed 00FD - EXIT
+    pub exited: bool,
+    /// The instruction that stopped the frame early
+    pub fault: Option<Chip8Error>,
+}
+
+impl FrameEvents {
+    pub(crate) fn merge(&mut self, frame: FrameEvents) {
+        self.frames += frame.frames;
+        self.cycles += frame.cycles;
+        self.display_dirty |= frame.display_dirty;
+        self.sound_started |= frame.sound_started;
+        self.sound_stopped |= frame.sound_stopped;
+        self.sound_on = frame.sound_on;
+        self.waiting_for_key = frame.waiting_for_key;
+        self.exited = frame.exited;
+        self.fault = frame.fault;
+    }
+}
+
+/**
+ * A Cpu driven at a fixed number of instructions per
+ * 60Hz timer tick, so every front-end gets the same timing
+ */
+pub struct Machine {
+    pub cpu: Cpu,
+    cycles_per_frame: usize,
+    // frames run since the machine was created
+    frame: u64,
+    // time accumulated by run_for that didn't fill a frame
+    pending: Duration,
+    // per-frame snapshots, captured when enabled
+    rewind: Option<Rewind>,
+}
+
+impl Machine {
+    pub fn new(cpu: Cpu) -> Self {
+        Machine {
+            cpu,
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+            frame: 0,
+            pending: Duration::from_secs(0),
+            rewind: None,
+        }
+    }
+
+    pub fn cycles_per_frame(&self) -> usize {
+        self.cycles_per_frame
+    }
+
+    /**
+     * Number of frames run so far, movies use it to
+     * timestamp input
+     */
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /**
+     * Set the number of instructions executed per frame
+     */
+    pub fn set_cycles_per_frame(&mut self, cycles: usize) {
+        self.cycles_per_frame = cycles;
+    }
+
+    /**
+     * Execute one frame worth of instructions, then tick the
+     * timers once. The frame ends early on exit or while
+     * waiting for a key, in which case the timers still tick,
+     * and on a fault, in which case they don't.
+     */
+    pub fn run_frame(&mut self) -> FrameEvents {
+        let mut events = FrameEvents {
+            frames: 1,
+            ..Default::default()
+        };
+        let sound_before = self.cpu.sound_timer > 0;
+        let display_before = self.cpu.display.clone();
+        self.frame += 1;
+        self.cpu.poll_key_wait();
+
+        for _ in 0..self.cycles_per_frame {
+            if self.cpu.has_exited() || self.cpu.is_waiting_for_key() {
+                break;
+            }
+
+            let result = self
+                .cpu
+                .fetch_instruction()
+                .and_then(|opcode| self.cpu.execute_instruction(opcode));
+            match result {
+                Ok(()) => events.cycles += 1,
+                Err(e) => {
+                    events.fault = Some(e);
+                    break;
+                }
+            }
+        }
+
+        events.display_dirty = self.cpu.display != display_before;
+
+        // a sound started this frame is
+        // reported before the timer ticks
+        let sound_during = self.cpu.sound_timer > 0;
+        if events.fault.is_none() {
+            self.cpu.decrement_timers();
+        }
+        events.sound_on = self.cpu.sound_timer > 0;
+        events.sound_started = !sound_before && sound_during;
+        events.sound_stopped = (sound_before || sound_during) && !events.sound_on;
+        events.waiting_for_key = self.cpu.is_waiting_for_key();
+        events.exited = self.cpu.has_exited();
+
+        if let (Some(rewind), None) = (self.rewind.as_mut(), &events.fault) {
+            rewind.capture(&self.cpu);
+        }
+        events
+    }
+
+    /**
+     * Capture a snapshot after every frame, keeping as
+     * many frames as fit in budget bytes
+     */
+    pub fn enable_rewind(&mut self, budget: usize) {
+        let mut rewind = Rewind::new(budget);
+        rewind.capture(&self.cpu);
+        self.rewind = Some(rewind);
+    }
+
+    pub fn disable_rewind(&mut self) {
+        self.rewind = None;
+    }
+
+    /**
+     * Number of frames rewind can currently go back
+     */
+    pub fn rewind_frames(&self) -> usize {
+        self.rewind.as_ref().map_or(0, |rewind| rewind.frames())
+    }
+
+    /**
+     * Go back up to frames frames, returning how many were
+     * rewound. The frame counter goes back too, so a movie
+     * being recorded can drop the inputs that were undone.
+     */
+    pub fn rewind(&mut self, frames: usize) -> Result<usize> {
+        let rewound = match self.rewind.as_mut() {
+            Some(rewind) => rewind.rewind(&mut self.cpu, frames)?,
+            None => return Ok(0),
+        };
+        self.frame = self.frame.saturating_sub(rewound as u64);
+        Ok(rewound)
+    }
+
+    /**
+     * Add elapsed to the time carried over from earlier calls
+     * and take out the whole frames it covers, dropping the
+     * backlog after a long pause instead of fast forwarding
+     */
+    pub fn frames_due(&mut self, elapsed: Duration) -> u32 {
+        self.pending += elapsed;
+
+        let frames = (self.pending.as_micros() / FRAME_DURATION.as_micros()) as u32;
+        if frames > MAX_FRAMES_PER_CALL {
+            self.pending = Duration::from_secs(0);
+        } else {
+            self.pending -= FRAME_DURATION * frames;
+        }
+        frames.min(MAX_FRAMES_PER_CALL)
+    }
+
+    /**
+     * Run as many whole frames as fit in elapsed, carrying
+     * the remainder over to the next call. Stops at the
+     * first fault.
+     */
+    pub fn run_for(&mut self, elapsed: Duration) -> FrameEvents {
+        let mut events = FrameEvents::default();
+        for _ in 0..self.frames_due(elapsed) {
+            events.merge(self.run_frame());
+            if events.fault.is_some() {
+                break;
+            }
+        }
+        events
+    }
+}
@@ -0,0 +1,260 @@
+use crate::cpu::{Cpu, Mode};
+use crate::error::{Chip8Error, Result};
+use crate::machine::{FrameEvents, Machine, DEFAULT_CYCLES_PER_FRAME};
+use crate::quirks::Quirks;
+use crate::rng::SplitMix64;
+use crate::snapshot::{Reader, Writer};
+use std::time::Duration;
+
+// movie file header
+const MOVIE_MAGIC: &[u8; 4] = b"RC8M";
+const MOVIE_VERSION: u16 = 1;
+
+/**
+ * A key press or release, applied before the frame runs
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Input {
+    pub frame: u64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+/**
+ * Everything needed to replay a session exactly: the ROM,
+ * the machine configuration, the RNG seed and every key
+ * event with the frame it happened on
+ *
+ * Recording always starts from a freshly booted machine,
+ * so a movie does not depend on the state of the emulator
+ * it was recorded on.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub mode: Mode,
+    pub quirks: Quirks,
+    pub cycles_per_frame: usize,
+    pub seed: u64,
+    pub rom: Vec<u8>,
+    pub inputs: Vec<Input>,
+    /// Frames recorded, the replay runs at least this long
+    pub frames: u64,
+}
+
+impl Movie {
+    pub fn new(rom: &[u8], mode: Mode, seed: u64) -> Self {
+        Movie {
+            mode,
+            quirks: mode.default_quirks(),
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+            seed,
+            rom: rom.to_vec(),
+            inputs: Vec::new(),
+            frames: 0,
+        }
+    }
+
+    /**
+     * A fresh machine with the ROM loaded and the RNG
+     * seeded, ready to record or replay this movie
+     */
+    pub fn boot(&self) -> Result<Machine> {
+        let mut cpu = Cpu::with_mode(self.mode);
+        cpu.quirks = self.quirks;
+        cpu.set_rng(SplitMix64::new(self.seed));
+        cpu.load_from_bytes(&self.rom)?;
+
+        let mut machine = Machine::new(cpu);
+        machine.set_cycles_per_frame(self.cycles_per_frame);
+        Ok(machine)
+    }
+
+    /**
+     * Serialize to the movie file format, inputs are
+     * stored as the number of frames since the previous
+     * one followed by the key and a press/release bit
+     */
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.bytes(MOVIE_MAGIC);
+        w.u16(MOVIE_VERSION);
+        w.mode_and_quirks(self.mode, &self.quirks);
+        w.u32(self.cycles_per_frame as u32);
+        w.u64(self.seed);
+        w.buffer(&self.rom);
+        w.u64(self.frames);
+
+        w.u32(self.inputs.len() as u32);
+        let mut last = 0;
+        for input in &self.inputs {
+            w.u32((input.frame - last) as u32);
+            w.u8(input.key | (input.pressed as u8) << 7);
+            last = input.frame;
+        }
+        w.finish()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        Self::read(bytes).map_err(|e| match e {
+            Chip8Error::InvalidState(reason) => Chip8Error::InvalidMovie(reason),
+            e => e,
+        })
+    }
+
+    fn read(bytes: &[u8]) -> Result<Self> {
+        let mut r = Reader::new(bytes);
+        if r.bytes(MOVIE_MAGIC.len())? != MOVIE_MAGIC {
+            return Err(Chip8Error::InvalidMovie("not a movie"));
+        }
+        if r.u16()? != MOVIE_VERSION {
+            return Err(Chip8Error::InvalidMovie("unsupported version"));
+        }
+
+        let (mode, quirks) = r.mode_and_quirks()?;
+        let cycles_per_frame = r.u32()? as usize;
+        let seed = r.u64()?;
+        let rom = r.buffer()?.to_vec();
+        let frames = r.u64()?;
+
+        let count = r.u32()? as usize;
+        let mut inputs = Vec::with_capacity(count.min(bytes.len()));
+        let mut frame = 0;
+        for _ in 0..count {
+            frame += r.u32()? as u64;
+            let packed = r.u8()?;
+            if packed & 0x70 != 0 {
+                return Err(Chip8Error::InvalidMovie("invalid key"));
+            }
+            inputs.push(Input {
+                frame,
+                key: packed & 0xF,
+                pressed: packed & 0x80 != 0,
+            });
+        }
+        r.finish()?;
+
+        Ok(Movie {
+            mode,
+            quirks,
+            cycles_per_frame,
+            seed,
+            rom,
+            inputs,
+            frames,
+        })
+    }
+}
+
+/**
+ * Logs key events against the frame counter of a machine
+ * booted with Movie::boot
+ */
+#[derive(Debug)]
+pub struct Recorder {
+    movie: Movie,
+}
+
+impl Recorder {
+    pub fn new(movie: Movie) -> Self {
+        Recorder { movie }
+    }
+
+    /**
+     * Record a key event and forward it to the CPU, it
+     * takes effect at the start of the next frame. Keys
+     * outside 0-F are ignored.
+     */
+    pub fn key(&mut self, machine: &mut Machine, key: u8, pressed: bool) {
+        if key >= 16 {
+            return;
+        }
+        self.movie.inputs.push(Input {
+            frame: machine.frame(),
+            key,
+            pressed,
+        });
+        if pressed {
+            machine.cpu.key_down_chip8(key);
+        } else {
+            machine.cpu.key_up_chip8(key);
+        }
+    }
+
+    /**
+     * Forget the inputs undone by Machine::rewind, so
+     * recording carries on from the rewound frame
+     */
+    pub fn rewind(&mut self, machine: &Machine) {
+        let frame = machine.frame();
+        self.movie.inputs.retain(|input| input.frame < frame);
+    }
+
+    /**
+     * Stop recording, the movie ends at the current frame
+     */
+    pub fn finish(mut self, machine: &Machine) -> Movie {
+        self.movie.frames = machine.frame();
+        self.movie
+    }
+}
+
+/**
+ * Feeds the inputs of a movie back into the machine
+ * returned by Movie::boot
+ */
+#[derive(Debug)]
+pub struct Player {
+    movie: Movie,
+    next: usize,
+}
+
+impl Player {
+    pub fn new(movie: Movie) -> Self {
+        Player { movie, next: 0 }
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    /**
+     * Apply the inputs recorded for this frame, then run it
+     */
+    pub fn run_frame(&mut self, machine: &mut Machine) -> FrameEvents {
+        let frame = machine.frame();
+        while let Some(input) = self.movie.inputs.get(self.next) {
+            if input.frame > frame {
+                break;
+            }
+            if input.pressed {
+                machine.cpu.key_down_chip8(input.key);
+            } else {
+                machine.cpu.key_up_chip8(input.key);
+            }
+            self.next += 1;
+        }
+        machine.run_frame()
+    }
+
+    /**
+     * Machine::run_for with the recorded inputs applied
+     * at the start of each frame
+     */
+    pub fn run_for(&mut self, machine: &mut Machine, elapsed: Duration) -> FrameEvents {
+        let mut events = FrameEvents::default();
+        for _ in 0..machine.frames_due(elapsed) {
+            events.merge(self.run_frame(machine));
+            if events.fault.is_some() {
+                break;
+            }
+        }
+        events
+    }
+
+    /**
+     * True once every recorded frame has been replayed
+     */
+    pub fn is_finished(&self, machine: &Machine) -> bool {
+        machine.frame() >= self.movie.frames && self.next == self.movie.inputs.len()
+    }
+}
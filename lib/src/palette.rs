@@ -0,0 +1,157 @@
+use crate::cpu::{Cpu, Rect};
+
+/**
+ * An RGBA color, one byte per channel
+ */
+pub type Rgba = [u8; 4];
+
+/**
+ * Opaque color from a 0xRRGGBB value
+ */
+pub fn rgb(value: u32) -> Rgba {
+    let [_, r, g, b] = value.to_be_bytes();
+    [r, g, b, 0xFF]
+}
+
+/**
+ * The colors used to turn the display into RGBA pixels
+ *
+ * Each display pixel holds the bitmask of the XO-CHIP planes
+ * it is lit on, which indexes colors: 0 is the background,
+ * 1 the foreground (plane 1), 2 plane 2 and 3 both planes.
+ * CHIP-8 and SCHIP programs only ever draw on plane 1.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub colors: [Rgba; 4],
+    /// Shown while a pixel erased by DRW is still glowing
+    pub glow: Rgba,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            colors: [rgb(0x000000), rgb(0x33FF66), rgb(0xFF9933), rgb(0xFFFFFF)],
+            glow: rgb(0x33FF99),
+        }
+    }
+}
+
+impl Palette {
+    /**
+     * A two color palette, plane 2 and overlapping
+     * pixels use the foreground as well
+     */
+    pub fn new(foreground: Rgba, background: Rgba) -> Self {
+        Palette {
+            colors: [background, foreground, foreground, foreground],
+            ..Palette::default()
+        }
+    }
+
+    pub fn with_glow(mut self, glow: Rgba) -> Self {
+        self.glow = glow;
+        self
+    }
+
+    /**
+     * Replace all four plane colors, see Palette
+     */
+    pub fn with_planes(mut self, colors: [Rgba; 4]) -> Self {
+        self.colors = colors;
+        self
+    }
+
+    /**
+     * The color of a pixel holding planes that
+     * still has glow frames left
+     */
+    pub fn color(&self, planes: u8, glow: u8) -> Rgba {
+        if glow > 0 {
+            self.glow
+        } else {
+            self.colors[(planes & 0x3) as usize]
+        }
+    }
+
+    /**
+     * Draw the display into out as RGBA, resizing it to
+     * width * height * 4 bytes, and count the phosphor
+     * glow down by one frame
+     *
+     * The area that drew glowing pixels is marked dirty,
+     * as in render_regions, so it keeps fading when later
+     * frames only redraw the dirty regions.
+     */
+    pub fn render(&self, cpu: &mut Cpu, out: &mut Vec<u8>) {
+        let (display, glow, (width, _)) = cpu.get_display();
+        out.resize(display.len() * 4, 0);
+
+        let mut glowing: Option<Rect> = None;
+        let pixels = display.iter().zip(glow.iter_mut());
+        for (i, ((planes, glow), rgba)) in pixels.zip(out.chunks_exact_mut(4)).enumerate() {
+            rgba.copy_from_slice(&self.color(planes, *glow));
+            if *glow > 0 {
+                let pixel = Rect::new(i % width, i / width, 1, 1);
+                glowing = Some(glowing.map_or(pixel, |rect| rect.union(&pixel)));
+            }
+            *glow = glow.saturating_sub(1);
+        }
+
+        if let Some(rect) = glowing {
+            cpu.mark_dirty(rect);
+        }
+    }
+
+    /**
+     * Redraw only the regions that changed, typically from
+     * Cpu::take_dirty_regions, into out as last filled by
+     * render at the current resolution. Overlapping regions
+     * are merged first and the merged regions are returned.
+     *
+     * Regions that drew glowing pixels are marked dirty
+     * again, so they keep fading until they go dark.
+     */
+    pub fn render_regions(&self, cpu: &mut Cpu, out: &mut [u8], regions: &[Rect]) -> Vec<Rect> {
+        let regions = disjoint(regions);
+        let (width, _) = cpu.resolution();
+        let (display, glow, _) = cpu.get_display();
+
+        let mut glowing = Vec::new();
+        for rect in &regions {
+            let mut lit = false;
+            for y in rect.y..rect.y + rect.height {
+                for x in rect.x..rect.x + rect.width {
+                    let i = x + y * width;
+                    out[i * 4..i * 4 + 4].copy_from_slice(&self.color(display.get(x, y), glow[i]));
+                    lit |= glow[i] > 0;
+                    glow[i] = glow[i].saturating_sub(1);
+                }
+            }
+            if lit {
+                glowing.push(*rect);
+            }
+        }
+
+        for rect in glowing {
+            cpu.mark_dirty(rect);
+        }
+        regions
+    }
+}
+
+/**
+ * Merge overlapping rectangles until none overlap, so
+ * no pixel is drawn (and its glow faded) twice
+ */
+fn disjoint(regions: &[Rect]) -> Vec<Rect> {
+    let mut merged: Vec<Rect> = Vec::with_capacity(regions.len());
+    for rect in regions {
+        let mut rect = *rect;
+        while let Some(pos) = merged.iter().position(|r| r.intersects(&rect)) {
+            rect = rect.union(&merged.swap_remove(pos));
+        }
+        merged.push(rect);
+    }
+    merged
+}
@@ -0,0 +1,88 @@
+use bitvec::prelude::*;
+
+pub use crate::framebuffer::Framebuffer;
+pub use crate::rng::{RandomSource, ScriptedSource, SplitMix64};
+
+/**
+ * Receives the framebuffer whenever an instruction changes it
+ *
+ * The CPU keeps its own framebuffer for collision detection,
+ * so implementations only need to present it, e.g. on an LED
+ * matrix or by recording frames in a test.
+ */
+pub trait Display: Send {
+    /// Framebuffer::get yields the bitmask of the
+    /// planes a pixel is lit on
+    fn update(&mut self, display: &Framebuffer);
+}
+
+/**
+ * The state of the 16 key hexadecimal keypad
+ */
+pub trait Keypad: Send {
+    /// True if key 0-F is held down
+    fn is_pressed(&self, key: u8) -> bool;
+
+    /// Called by Cpu::key_down and Cpu::key_up, sources that
+    /// read from their own hardware or a recording can ignore it
+    fn set_pressed(&mut self, _key: u8, _pressed: bool) {}
+}
+
+/**
+ * The tone played while the sound timer is non-zero
+ */
+pub trait Buzzer: Send {
+    /// The sound timer became non-zero (true) or reached zero (false)
+    fn set_active(&mut self, active: bool);
+
+    /// XO-CHIP loaded a new audio pattern or pitch
+    fn set_pattern(&mut self, _pattern: &[u8; 16], _pitch: u8) {}
+}
+
+/**
+ * Does nothing, front-ends read Cpu::display instead
+ */
+#[derive(Debug, Default, Clone)]
+pub struct NullDisplay;
+
+impl Display for NullDisplay {
+    fn update(&mut self, _display: &Framebuffer) {}
+}
+
+/**
+ * Key state set through Cpu::key_down and Cpu::key_up
+ */
+#[derive(Debug, Clone)]
+pub struct Keyboard {
+    keys: BitVec<LocalBits, usize>,
+}
+
+impl Default for Keyboard {
+    fn default() -> Self {
+        Keyboard {
+            keys: bitvec![0; 16],
+        }
+    }
+}
+
+impl Keypad for Keyboard {
+    fn is_pressed(&self, key: u8) -> bool {
+        self.keys.get(key as usize) == Some(&true)
+    }
+
+    fn set_pressed(&mut self, key: u8, pressed: bool) {
+        if (key as usize) < self.keys.len() {
+            self.keys.set(key as usize, pressed);
+        }
+    }
+}
+
+/**
+ * Silent, front-ends read Cpu::sound_timer instead
+ */
+#[derive(Debug, Default, Clone)]
+pub struct NullBuzzer;
+
+impl Buzzer for NullBuzzer {
+    fn set_active(&mut self, _active: bool) {}
+}
@@ -0,0 +1,116 @@
+/**
+ * How Fx55/Fx65 modify the I register after
+ * storing or loading V0 through Vx
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexIncrement {
+    /// I is left unchanged (SCHIP)
+    None,
+    /// I is incremented by x (CHIP-48)
+    ByX,
+    /// I is incremented by x + 1 (COSMAC VIP)
+    ByXPlusOne,
+}
+
+/**
+ * Behavior of the instructions that were implemented
+ * differently by the various CHIP-8 interpreters.
+ *
+ * The default profile matches the historical behavior
+ * of this crate. The presets below cover the common
+ * platforms ROMs are written for.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8xy6/8xyE shift Vy into Vx instead of shifting Vx in place
+    pub shift_uses_vy: bool,
+
+    /// How Fx55/Fx65 modify I
+    pub index_increment: IndexIncrement,
+
+    /// Bnnn jumps to nnn + Vx (x being the high nibble of nnn)
+    /// instead of nnn + V0
+    pub jump_uses_vx: bool,
+
+    /// Dxyn wraps pixels around the edges of the screen
+    /// instead of clipping them
+    pub draw_wraps: bool,
+
+    /// 8xy1/8xy2/8xy3 reset VF to 0
+    pub logic_resets_vf: bool,
+
+    /// Fx0A completes when the key is released
+    /// instead of as soon as it is pressed
+    pub wait_for_release: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            index_increment: IndexIncrement::None,
+            jump_uses_vx: false,
+            draw_wraps: false,
+            logic_resets_vf: false,
+            wait_for_release: false,
+        }
+    }
+}
+
+impl Quirks {
+    /**
+     * The original interpreter for the RCA COSMAC VIP
+     */
+    pub fn cosmac_vip() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            index_increment: IndexIncrement::ByXPlusOne,
+            jump_uses_vx: false,
+            draw_wraps: false,
+            logic_resets_vf: true,
+            wait_for_release: true,
+        }
+    }
+
+    /**
+     * CHIP-48 for the HP-48 graphing calculators
+     */
+    pub fn chip48() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            index_increment: IndexIncrement::ByX,
+            jump_uses_vx: true,
+            draw_wraps: false,
+            logic_resets_vf: false,
+            wait_for_release: false,
+        }
+    }
+
+    /**
+     * SUPER-CHIP 1.1
+     */
+    pub fn schip() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            index_increment: IndexIncrement::None,
+            jump_uses_vx: true,
+            draw_wraps: false,
+            logic_resets_vf: false,
+            wait_for_release: false,
+        }
+    }
+
+    /**
+     * XO-CHIP as implemented by Octo
+     */
+    pub fn xo_chip() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            index_increment: IndexIncrement::ByXPlusOne,
+            jump_uses_vx: false,
+            draw_wraps: true,
+            logic_resets_vf: false,
+            wait_for_release: false,
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use crate::cpu::Cpu;
+use crate::error::Result;
+use std::collections::VecDeque;
+
+// enough for several minutes of a typical game
+pub const DEFAULT_REWIND_BUDGET: usize = 2 * 1024 * 1024;
+
+/**
+ * Rebuilds the previous snapshot from the one after it
+ */
+enum Delta {
+    /// Runs of (unchanged bytes, changed bytes) XORed
+    /// against the newer snapshot
+    Xor(Vec<u8>),
+    /// The snapshots differ in size, e.g. after a
+    /// resolution change, so the older one is kept whole
+    Full(Vec<u8>),
+}
+
+impl Delta {
+    /**
+     * Bytes charged against the budget, including the entry
+     * itself so frames that change nothing aren't free
+     */
+    fn cost(&self) -> usize {
+        let data = match self {
+            Delta::Xor(runs) => runs.capacity(),
+            Delta::Full(state) => state.capacity(),
+        };
+        std::mem::size_of::<Delta>() + data
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(buf: &[u8], pos: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = buf[*pos];
+        *pos += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+/**
+ * The delta that turns newer back into older
+ */
+fn diff(older: Vec<u8>, newer: &[u8]) -> Delta {
+    if older.len() != newer.len() {
+        return Delta::Full(older);
+    }
+
+    let mut runs = Vec::new();
+    let mut pos = 0;
+    while pos < older.len() {
+        let start = pos;
+        while pos < older.len() && older[pos] == newer[pos] {
+            pos += 1;
+        }
+        let changed = pos;
+        while pos < older.len() && older[pos] != newer[pos] {
+            pos += 1;
+        }
+        if changed == pos {
+            break;
+        }
+        write_varint(&mut runs, changed - start);
+        write_varint(&mut runs, pos - changed);
+        runs.extend(
+            older[changed..pos]
+                .iter()
+                .zip(&newer[changed..pos])
+                .map(|(a, b)| a ^ b),
+        );
+    }
+    Delta::Xor(runs)
+}
+
+/**
+ * Undo a delta in place, turning the newer snapshot
+ * back into the older one
+ */
+fn apply(state: &mut Vec<u8>, delta: Delta) {
+    let runs = match delta {
+        Delta::Full(older) => {
+            *state = older;
+            return;
+        }
+        Delta::Xor(runs) => runs,
+    };
+
+    let (mut pos, mut offset) = (0, 0);
+    while pos < runs.len() {
+        offset += read_varint(&runs, &mut pos);
+        let len = read_varint(&runs, &mut pos);
+        for byte in &mut state[offset..offset + len] {
+            *byte ^= runs[pos];
+            pos += 1;
+        }
+        offset += len;
+    }
+}
+
+/**
+ * Ring buffer of per-frame CPU snapshots for rewinding
+ *
+ * Only the newest snapshot is kept whole, every older
+ * frame is stored as the delta from the frame after it.
+ * The oldest frames are dropped once the deltas and the
+ * newest snapshot exceed the memory budget.
+ */
+pub struct Rewind {
+    newest: Option<Vec<u8>>,
+    // oldest first
+    deltas: VecDeque<Delta>,
+    budget: usize,
+    // the cost of all deltas
+    used: usize,
+}
+
+impl Default for Rewind {
+    fn default() -> Self {
+        Self::new(DEFAULT_REWIND_BUDGET)
+    }
+}
+
+impl Rewind {
+    /**
+     * A buffer using at most budget bytes for the
+     * deltas and the newest snapshot
+     */
+    pub fn new(budget: usize) -> Self {
+        Rewind {
+            newest: None,
+            deltas: VecDeque::new(),
+            budget,
+            used: 0,
+        }
+    }
+
+    /**
+     * Snapshot the CPU, should be called once per frame
+     */
+    pub fn capture(&mut self, cpu: &Cpu) {
+        let state = cpu.save_state();
+        if let Some(older) = self.newest.take() {
+            let delta = diff(older, &state);
+            self.used += delta.cost();
+            self.deltas.push_back(delta);
+        }
+        self.newest = Some(state);
+
+        while self.memory_used() > self.budget {
+            match self.deltas.pop_front() {
+                Some(delta) => self.used -= delta.cost(),
+                None => break,
+            }
+        }
+    }
+
+    /**
+     * Number of frames that can be rewound
+     */
+    pub fn frames(&self) -> usize {
+        self.deltas.len()
+    }
+
+    /**
+     * Bytes used by the deltas and the newest snapshot
+     */
+    pub fn memory_used(&self) -> usize {
+        self.used + self.newest.as_ref().map_or(0, |state| state.capacity())
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+        self.used = 0;
+    }
+
+    /**
+     * Restore the CPU to the state captured frames ago, or
+     * as far back as the buffer goes. Returns the number of
+     * frames actually rewound, rewinding 0 frames restores
+     * the last capture.
+     */
+    pub fn rewind(&mut self, cpu: &mut Cpu, frames: usize) -> Result<usize> {
+        let state = match self.newest.as_mut() {
+            Some(state) => state,
+            None => return Ok(0),
+        };
+
+        let frames = frames.min(self.deltas.len());
+        for _ in 0..frames {
+            if let Some(delta) = self.deltas.pop_back() {
+                self.used -= delta.cost();
+                apply(state, delta);
+            }
+        }
+        cpu.load_state(state)?;
+        Ok(frames)
+    }
+}
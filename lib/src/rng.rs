@@ -0,0 +1,97 @@
+/**
+ * A source of random bytes for Cxkk - RND Vx, kk
+ *
+ * Sources that can report and restore their state are
+ * included in save states, so a restored CPU draws the
+ * same sequence as the one it was saved from.
+ */
+pub trait RandomSource: Send {
+    /// The next random byte
+    fn next_byte(&mut self) -> u8;
+
+    /// The current position in the sequence, if it can be saved
+    fn state(&self) -> Option<u64> {
+        None
+    }
+
+    /// Continue the sequence from a position returned by state
+    fn restore(&mut self, _state: u64) {}
+}
+
+/**
+ * The default seedable generator, SplitMix64
+ */
+#[derive(Debug, Clone)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        SplitMix64 { state: seed }
+    }
+
+    /**
+     * Seeded from the operating system, for
+     * when reproducibility does not matter
+     */
+    pub fn from_entropy() -> Self {
+        Self::new(rand::random())
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+impl RandomSource for SplitMix64 {
+    fn next_byte(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+
+    fn state(&self) -> Option<u64> {
+        Some(self.state)
+    }
+
+    fn restore(&mut self, state: u64) {
+        self.state = state;
+    }
+}
+
+/**
+ * Replays a fixed sequence of bytes, starting over
+ * once the end is reached. Useful for asserting the
+ * exact outcome of Cxkk in tests.
+ */
+#[derive(Debug, Clone)]
+pub struct ScriptedSource {
+    bytes: Vec<u8>,
+    position: usize,
+}
+
+impl ScriptedSource {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        assert!(!bytes.is_empty(), "scripted source needs at least one byte");
+        ScriptedSource { bytes, position: 0 }
+    }
+}
+
+impl RandomSource for ScriptedSource {
+    fn next_byte(&mut self) -> u8 {
+        let byte = self.bytes[self.position];
+        self.position = (self.position + 1) % self.bytes.len();
+        byte
+    }
+
+    fn state(&self) -> Option<u64> {
+        Some(self.position as u64)
+    }
+
+    fn restore(&mut self, state: u64) {
+        self.position = state as usize % self.bytes.len();
+    }
+}
@@ -0,0 +1,175 @@
+use crate::cpu::Mode;
+use crate::error::{Chip8Error, Result};
+use crate::quirks::{IndexIncrement, Quirks};
+use byteorder::{BigEndian, ByteOrder};
+
+/**
+ * Appends big endian fields to a save state
+ */
+#[derive(Default)]
+pub(crate) struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    pub fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.buf.push(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        let mut word = [0u8; 2];
+        BigEndian::write_u16(&mut word, value);
+        self.buf.extend_from_slice(&word);
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        let mut dword = [0u8; 4];
+        BigEndian::write_u32(&mut dword, value);
+        self.buf.extend_from_slice(&dword);
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        let mut qword = [0u8; 8];
+        BigEndian::write_u64(&mut qword, value);
+        self.buf.extend_from_slice(&qword);
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /**
+     * A length prefixed byte buffer
+     */
+    pub fn buffer(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.bytes(bytes);
+    }
+
+    /**
+     * The instruction set and quirks profile, shared
+     * by save states and movies
+     */
+    pub fn mode_and_quirks(&mut self, mode: Mode, quirks: &Quirks) {
+        self.u8(match mode {
+            Mode::Chip8 => 0,
+            Mode::SuperChip => 1,
+            Mode::XoChip => 2,
+        });
+        self.bool(quirks.shift_uses_vy);
+        self.u8(match quirks.index_increment {
+            IndexIncrement::None => 0,
+            IndexIncrement::ByX => 1,
+            IndexIncrement::ByXPlusOne => 2,
+        });
+        self.bool(quirks.jump_uses_vx);
+        self.bool(quirks.draw_wraps);
+        self.bool(quirks.logic_resets_vf);
+        self.bool(quirks.wait_for_release);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/**
+ * Reads the fields written by Writer, failing with
+ * InvalidState if the data is truncated
+ */
+pub(crate) struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Reader { buf }
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.buf.len() < len {
+            return Err(Chip8Error::InvalidState("save state is truncated"));
+        }
+        let (head, tail) = self.buf.split_at(len);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(Chip8Error::InvalidState("invalid boolean")),
+        }
+    }
+
+    pub fn u16(&mut self) -> Result<u16> {
+        Ok(BigEndian::read_u16(self.bytes(2)?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        Ok(BigEndian::read_u32(self.bytes(4)?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        Ok(BigEndian::read_u64(self.bytes(8)?))
+    }
+
+    /**
+     * A length prefixed byte buffer
+     */
+    pub fn buffer(&mut self) -> Result<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.bytes(len)
+    }
+
+    /**
+     * Fixed size array
+     */
+    pub fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut out = [0u8; N];
+        out.copy_from_slice(self.bytes(N)?);
+        Ok(out)
+    }
+
+    pub fn mode_and_quirks(&mut self) -> Result<(Mode, Quirks)> {
+        let mode = match self.u8()? {
+            0 => Mode::Chip8,
+            1 => Mode::SuperChip,
+            2 => Mode::XoChip,
+            _ => return Err(Chip8Error::InvalidState("unknown mode")),
+        };
+        let mut quirks = mode.default_quirks();
+        quirks.shift_uses_vy = self.bool()?;
+        quirks.index_increment = match self.u8()? {
+            0 => IndexIncrement::None,
+            1 => IndexIncrement::ByX,
+            2 => IndexIncrement::ByXPlusOne,
+            _ => return Err(Chip8Error::InvalidState("unknown index increment quirk")),
+        };
+        quirks.jump_uses_vx = self.bool()?;
+        quirks.draw_wraps = self.bool()?;
+        quirks.logic_resets_vf = self.bool()?;
+        quirks.wait_for_release = self.bool()?;
+        Ok((mode, quirks))
+    }
+
+    /**
+     * Fail if anything is left over
+     */
+    pub fn finish(self) -> Result<()> {
+        if self.buf.is_empty() {
+            Ok(())
+        } else {
+            Err(Chip8Error::InvalidState("trailing data after save state"))
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use crate::asm::assemble;
+use crate::cpu::Mode;
+use crate::disasm::disassemble;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_labels_and_data() {
+        let source = "
+start:
+    CALL draw       ; forward reference
+    JP start
+draw: LD I, sprite
+    DRW V0, V1, 2
+    RET
+sprite:
+    db 0xF0, 0b10010000
+    dw 0x1234
+";
+        let rom = assemble(source).unwrap();
+        assert_eq!(
+            rom,
+            vec![
+                0x22, 0x04, 0x12, 0x00, 0xA2, 0x0A, 0xD0, 0x12, 0x00, 0xEE, 0xF0, 0x90, 0x12, 0x34
+            ]
+        );
+    }
+
+    #[test]
+    fn test_define_and_expressions() {
+        let source = "
+define SPEED 3
+define NEXT here + 2
+here:
+    LD V0, SPEED + 1
+    JP NEXT
+    LD I, LONG here
+    LD [I], V2 - V4
+    SHR V1
+";
+        let rom = assemble(source).unwrap();
+        assert_eq!(
+            rom,
+            vec![0x60, 0x04, 0x12, 0x02, 0xF0, 0x00, 0x02, 0x00, 0x52, 0x42, 0x81, 0x16]
+        );
+    }
+
+    #[test]
+    fn test_error_location() {
+        let err = assemble("CLS\n    LD V0, missing\n").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 12));
+        assert_eq!(err.to_string(), "<input>:2:12: undefined symbol 'missing'");
+
+        let err = assemble("  ADD V0, 0x100").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 11));
+
+        let err = assemble("FOO V0").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 1));
+
+        let err = assemble("a:\na: CLS").unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn test_reserved_names() {
+        for source in ["B: CLS", "define dt 3", "CLS\nv1: RET", "define I 0x300"] {
+            let err = assemble(source).unwrap_err();
+            assert!(err.message.contains("reserved"), "{}", err);
+        }
+        let err = assemble("CLS\n  hf:").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 3));
+    }
+
+    #[test]
+    fn test_negative_values() {
+        let source = "
+define STEP -2
+    ADD V0, -1
+    LD V1, STEP
+    db -128, 5 - 6
+    dw -1
+";
+        let rom = assemble(source).unwrap();
+        assert_eq!(rom, vec![0x70, 0xFF, 0x61, 0xFE, 0x80, 0xFF, 0xFF, 0xFF]);
+
+        let err = assemble("ADD V0, -129").unwrap_err();
+        assert_eq!(err.message, "value -129 out of range (min -128, max 0xFF)");
+        // addresses are never negative
+        assert!(assemble("JP -2").is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let roms: [(&[u8], Mode); 3] = [
+            (include_bytes!("../../wasm/roms/PONG"), Mode::Chip8),
+            (
+                include_bytes!("../../wasm/roms/test_opcode.ch8"),
+                Mode::Chip8,
+            ),
+            (include_bytes!("../../wasm/roms/TETRIS"), Mode::Chip8),
+        ];
+        for (rom, mode) in roms.iter() {
+            let listing = disassemble(rom, *mode).to_string();
+            assert_eq!(&assemble(&listing).unwrap()[..], *rom);
+        }
+    }
+}
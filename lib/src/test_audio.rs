@@ -0,0 +1,127 @@
+use crate::audio::{write_wav, Synth, Waveform};
+use crate::cpu::{Cpu, Mode};
+use crate::machine::Machine;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_silent_when_inactive() {
+        let mut synth = Synth::new(48000);
+        let mut out = [1.0; 64];
+        synth.fill(false, &mut out);
+        assert!(out.iter().all(|s| *s == 0.0));
+    }
+
+    #[test]
+    fn test_square_wave() {
+        // 1000Hz at 8000Hz is 4 samples high, 4 samples low
+        let mut synth = Synth::new(8000);
+        synth.frequency = 1000.0;
+        synth.volume = 0.5;
+
+        let mut out = [0.0; 16];
+        synth.fill(true, &mut out);
+        for (i, sample) in out.iter().enumerate() {
+            let expected = if i % 8 < 4 { 0.5 } else { -0.5 };
+            assert_eq!(*sample, expected, "sample {}", i);
+        }
+    }
+
+    #[test]
+    fn test_phase_continuity() {
+        let mut whole = Synth::new(8000);
+        whole.frequency = 300.0;
+        whole.waveform = Waveform::Sine;
+        let mut split = whole.clone();
+
+        let mut expected = [0.0; 100];
+        whole.fill(true, &mut expected);
+
+        let mut out = [0.0; 100];
+        let (first, second) = out.split_at_mut(37);
+        split.fill(true, first);
+        split.fill(true, second);
+        for (a, b) in out.iter().zip(expected.iter()) {
+            assert!((a - b).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_waveform_ranges() {
+        for waveform in &["triangle", "sawtooth", "sine"] {
+            let mut synth = Synth::new(8000);
+            synth.waveform = waveform.parse().unwrap();
+            synth.volume = 1.0;
+            let mut out = [0.0; 400];
+            synth.fill(true, &mut out);
+            assert!(out.iter().all(|s| (-1.0..=1.0).contains(s)));
+            assert!(out.iter().any(|s| *s > 0.9));
+            assert!(out.iter().any(|s| *s < -0.9));
+        }
+        assert!("noise".parse::<Waveform>().is_err());
+    }
+
+    #[test]
+    fn test_pattern() {
+        let mut cpu = Cpu::with_mode(Mode::XoChip);
+        cpu.audio_pattern = [0xFF; 16];
+        cpu.audio_pattern[8..].copy_from_slice(&[0; 8]);
+
+        // the default pitch plays 4000 bits per second, so at
+        // 128000Hz each bit lasts 32 samples
+        let mut synth = Synth::new(128000);
+        synth.waveform = Waveform::Pattern;
+        synth.volume = 1.0;
+        synth.sync(&cpu);
+
+        let mut out = vec![0.0; 128 * 32];
+        synth.fill(true, &mut out);
+        assert!(out[..64 * 32].iter().all(|s| *s == 1.0));
+        assert!(out[64 * 32..].iter().all(|s| *s == -1.0));
+    }
+
+    #[test]
+    fn test_follows_timer_ticks() {
+        let mut cpu = Cpu::new();
+        // LD V0, 0x02 ; LD ST, V0 ; JP 0x204
+        cpu.load_from_bytes(&[0x60, 0x02, 0xF0, 0x18, 0x12, 0x04])
+            .unwrap();
+        let mut machine = Machine::new(cpu);
+        let mut synth = Synth::new(6000);
+
+        let frames: Vec<Vec<f32>> = (0..4)
+            .map(|_| {
+                let events = machine.run_frame();
+                synth.render_frame(&machine.cpu, &events)
+            })
+            .collect();
+
+        assert!(frames.iter().all(|f| f.len() == 100));
+        assert!(frames[0].iter().any(|s| *s != 0.0));
+        assert!(frames[1].iter().any(|s| *s != 0.0));
+        assert!(frames[2].iter().all(|s| *s == 0.0));
+        assert!(frames[3].iter().all(|s| *s == 0.0));
+    }
+
+    #[test]
+    fn test_write_wav() {
+        let mut out = Vec::new();
+        write_wav(&mut out, 44100, &[0.0, 1.0, -1.0, 2.0]).unwrap();
+
+        assert_eq!(out.len(), 44 + 8);
+        assert_eq!(&out[..4], b"RIFF");
+        assert_eq!(&out[4..8], &44u32.to_le_bytes());
+        assert_eq!(&out[8..16], b"WAVEfmt ");
+        assert_eq!(&out[24..28], &44100u32.to_le_bytes());
+        assert_eq!(&out[36..40], b"data");
+        assert_eq!(&out[40..44], &8u32.to_le_bytes());
+
+        let samples: Vec<i16> = out[44..]
+            .chunks(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        assert_eq!(samples, vec![0, i16::MAX, -i16::MAX, i16::MAX]);
+    }
+}
@@ -0,0 +1,238 @@
+use crate::cpu::Cpu;
+use crate::debugger::{
+    Access, Breakpoint, Compare, Condition, Debugger, Register, StepTarget, StopReason, Watchpoint,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROM: [u8; 16] = [
+        0x60, 0x05, // LD V0, 0x05
+        0x22, 0x08, // CALL 0x208
+        0x70, 0x01, // ADD V0, 0x01
+        0x12, 0x06, // JP 0x206
+        0xA3, 0x00, // LD I, 0x300
+        0xF0, 0x33, // LD B, V0
+        0xD0, 0x11, // DRW V0, V1, 1
+        0x00, 0xEE, // RET
+    ];
+
+    fn debugger(rom: &[u8]) -> Debugger {
+        let mut cpu = Cpu::new();
+        cpu.load_from_bytes(rom).unwrap();
+        Debugger::new(cpu)
+    }
+
+    #[test]
+    fn test_stepping() {
+        let mut dbg = debugger(&ROM);
+        assert!(matches!(dbg.step_into(), StopReason::Step));
+        assert_eq!(dbg.cpu.program_counter, 0x202);
+
+        // step over runs the whole subroutine
+        assert!(matches!(dbg.step_over(), StopReason::Step));
+        assert_eq!(dbg.cpu.program_counter, 0x204);
+        assert!(dbg.cpu.stack.is_empty());
+        assert_eq!(dbg.cycles(), 6);
+
+        // step over anything else is a single step
+        assert!(matches!(dbg.step_over(), StopReason::Step));
+        assert_eq!(dbg.cpu.program_counter, 0x206);
+        assert!(matches!(dbg.step_into(), StopReason::Stalled));
+    }
+
+    #[test]
+    fn test_step_out() {
+        let mut dbg = debugger(&ROM);
+        assert!(dbg.step_out().is_none());
+        dbg.step_into();
+        dbg.step_into();
+        dbg.step_into();
+        assert_eq!(dbg.cpu.program_counter, 0x20A);
+
+        assert!(matches!(dbg.step_out(), Some(StopReason::Step)));
+        assert_eq!(dbg.cpu.program_counter, 0x204);
+        assert!(dbg.cpu.stack.is_empty());
+    }
+
+    #[test]
+    fn test_chunked_steps() {
+        let mut dbg = debugger(&ROM);
+        assert_eq!(dbg.over_target(), None);
+        assert_eq!(dbg.out_target(), None);
+        dbg.step_into();
+
+        let target = dbg.over_target().unwrap();
+        assert_eq!(
+            target,
+            StepTarget::Return {
+                pc: 0x204,
+                depth: 0
+            }
+        );
+        assert!(dbg.run_to(target, 2).is_none());
+        assert_eq!(dbg.cpu.program_counter, 0x20A);
+        assert_eq!(dbg.out_target(), Some(StepTarget::Out { depth: 1 }));
+        assert!(dbg.run_to(target, 2).is_none());
+        assert!(matches!(dbg.run_to(target, 2), Some(StopReason::Step)));
+        assert_eq!(dbg.cpu.program_counter, 0x204);
+
+        // a target that is never reached gives control back
+        let target = StepTarget::Out { depth: 0 };
+        assert!(dbg.run_to(target, 1).is_none());
+        assert!(matches!(dbg.run_to(target, 10), Some(StopReason::Stalled)));
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut dbg = debugger(&ROM);
+        dbg.breakpoints.push(Breakpoint::address(0x20C));
+        assert!(matches!(dbg.resume(), StopReason::Breakpoint(0)));
+        assert_eq!(dbg.cpu.program_counter, 0x20C);
+
+        let mut dbg = debugger(&ROM);
+        dbg.breakpoints.push(Breakpoint::opcode("Dxy?").unwrap());
+        assert!(matches!(dbg.resume(), StopReason::Breakpoint(0)));
+        assert_eq!(dbg.cpu.program_counter, 0x20C);
+
+        // a breakpoint stops a step over early
+        let mut dbg = debugger(&ROM);
+        dbg.breakpoints.push(Breakpoint::address(0x20A));
+        dbg.step_into();
+        assert!(matches!(dbg.step_over(), StopReason::Breakpoint(0)));
+        assert_eq!(dbg.cpu.program_counter, 0x20A);
+    }
+
+    #[test]
+    fn test_conditional_breakpoints() {
+        let mut dbg = debugger(&ROM);
+        dbg.breakpoints.push(Breakpoint::Address {
+            addr: 0x204,
+            condition: Some("V0 == 6".parse().unwrap()),
+        });
+        assert!(matches!(dbg.resume(), StopReason::Stalled));
+
+        let mut dbg = debugger(&ROM);
+        dbg.breakpoints.push(Breakpoint::Address {
+            addr: 0x204,
+            condition: Some("V0 == 5".parse().unwrap()),
+        });
+        assert!(matches!(dbg.resume(), StopReason::Breakpoint(0)));
+        assert_eq!(dbg.cpu.program_counter, 0x204);
+
+        // stops where the condition becomes true
+        let mut dbg = debugger(&ROM);
+        dbg.breakpoints
+            .push(Breakpoint::Condition("I == 0x300".parse().unwrap()));
+        dbg.breakpoints
+            .push(Breakpoint::Condition("V0 > 5".parse().unwrap()));
+        assert!(matches!(dbg.resume(), StopReason::Breakpoint(0)));
+        assert_eq!(dbg.cpu.program_counter, 0x20A);
+        assert!(matches!(dbg.resume(), StopReason::Breakpoint(1)));
+        assert_eq!(dbg.cpu.program_counter, 0x206);
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let mut dbg = debugger(&ROM);
+        dbg.watchpoints.push(Watchpoint {
+            range: 0x301..0x302,
+            access: Access::Write,
+        });
+        match dbg.resume() {
+            StopReason::Watchpoint {
+                index,
+                addr,
+                write,
+                pc,
+            } => assert_eq!((index, addr, write, pc), (0, 0x301, true, 0x20A)),
+            stop => panic!("unexpected stop: {}", stop),
+        }
+        assert_eq!(dbg.cpu.program_counter, 0x20C);
+
+        // the draw reads the sprite, LD B only wrote it
+        let mut dbg = debugger(&ROM);
+        dbg.watchpoints.push(Watchpoint {
+            range: 0x300..0x303,
+            access: Access::Read,
+        });
+        match dbg.resume() {
+            StopReason::Watchpoint {
+                addr, write, pc, ..
+            } => assert_eq!((addr, write, pc), (0x300, false, 0x20C)),
+            stop => panic!("unexpected stop: {}", stop),
+        }
+    }
+
+    #[test]
+    fn test_watch_register_dumps() {
+        // LD I, 0x300 ; LD [I], V1 ; LD V1, [I] ; JP 0x206
+        let mut dbg = debugger(&[0xA3, 0x00, 0xF1, 0x55, 0xF1, 0x65, 0x12, 0x06]);
+        dbg.watchpoints.push(Watchpoint {
+            range: 0x301..0x302,
+            access: Access::ReadWrite,
+        });
+        assert!(matches!(
+            dbg.resume(),
+            StopReason::Watchpoint {
+                write: true,
+                pc: 0x202,
+                ..
+            }
+        ));
+        assert!(matches!(
+            dbg.resume(),
+            StopReason::Watchpoint {
+                write: false,
+                pc: 0x204,
+                ..
+            }
+        ));
+        assert!(matches!(dbg.resume(), StopReason::Stalled));
+    }
+
+    #[test]
+    fn test_run_budget() {
+        let mut dbg = debugger(&ROM);
+        assert!(dbg.run(3).is_none());
+        assert_eq!(dbg.cycles(), 3);
+        assert_eq!(dbg.cpu.program_counter, 0x20A);
+
+        // F00A
+        let mut dbg = debugger(&[0xF0, 0x0A]);
+        assert!(matches!(dbg.run(100), Some(StopReason::WaitingForKey)));
+    }
+
+    #[test]
+    fn test_parsing() {
+        let condition: Condition = "vA <= 0x1F".parse().unwrap();
+        assert_eq!(
+            condition,
+            Condition {
+                register: Register::V(0xA),
+                compare: Compare::Le,
+                value: 0x1F,
+            }
+        );
+        assert_eq!(condition.to_string(), "VA <= 0x1F");
+        assert_eq!("DT!=0".parse::<Condition>().unwrap().compare, Compare::Ne);
+        assert!("VG == 1".parse::<Condition>().is_err());
+        assert!("V1 5".parse::<Condition>().is_err());
+
+        assert_eq!(
+            Breakpoint::opcode("Dxy?").unwrap().to_string(),
+            "opcode D???"
+        );
+        assert!(Breakpoint::opcode("D0").is_none());
+        assert_eq!(
+            Breakpoint::Address {
+                addr: 0x204,
+                condition: Some(condition),
+            }
+            .to_string(),
+            "address 0x204 if VA <= 0x1F"
+        );
+        assert_eq!("rw".parse::<Access>(), Ok(Access::ReadWrite));
+    }
+}
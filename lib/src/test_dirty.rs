@@ -0,0 +1,101 @@
+use crate::cpu::{Cpu, Mode, Rect};
+use crate::quirks::Quirks;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /**
+     * Draw the 8x5 font sprite for 0 at (x, y)
+     */
+    fn draw_zero(cpu: &mut Cpu, x: u8, y: u8) {
+        cpu.i_register = 0;
+        cpu.registers[0] = x;
+        cpu.registers[1] = y;
+        cpu.execute_instruction(0xD015).unwrap();
+    }
+
+    fn clean_cpu(quirks: Quirks) -> Cpu {
+        let mut cpu = Cpu::with_quirks(quirks);
+        assert_eq!(cpu.take_dirty_regions(), vec![Rect::new(0, 0, 64, 32)]);
+        assert!(cpu.take_dirty_regions().is_empty());
+        cpu
+    }
+
+    #[test]
+    fn test_drw() {
+        let mut cpu = clean_cpu(Quirks::default());
+        draw_zero(&mut cpu, 10, 5);
+        // drawing over the same area again adds nothing
+        draw_zero(&mut cpu, 10, 5);
+        assert_eq!(cpu.take_dirty_regions(), vec![Rect::new(10, 5, 8, 5)]);
+
+        // a blank sprite changes nothing
+        cpu.i_register = 0x300;
+        cpu.execute_instruction(0xD015).unwrap();
+        assert!(cpu.take_dirty_regions().is_empty());
+
+        // overlapping draws are both kept
+        draw_zero(&mut cpu, 0, 0);
+        draw_zero(&mut cpu, 4, 2);
+        assert_eq!(
+            cpu.take_dirty_regions(),
+            vec![Rect::new(0, 0, 8, 5), Rect::new(4, 2, 8, 5)]
+        );
+    }
+
+    #[test]
+    fn test_drw_edges() {
+        // clipped at the bottom right corner
+        let mut cpu = clean_cpu(Quirks::default());
+        draw_zero(&mut cpu, 60, 30);
+        assert_eq!(cpu.take_dirty_regions(), vec![Rect::new(60, 30, 4, 2)]);
+
+        // wrapped into all four corners
+        let mut cpu = clean_cpu(Quirks::xo_chip());
+        draw_zero(&mut cpu, 60, 30);
+        assert_eq!(
+            cpu.take_dirty_regions(),
+            vec![
+                Rect::new(60, 30, 4, 2),
+                Rect::new(60, 0, 4, 3),
+                Rect::new(0, 30, 4, 2),
+                Rect::new(0, 0, 4, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_full_screen() {
+        let mut cpu = clean_cpu(Quirks::default());
+        draw_zero(&mut cpu, 10, 5);
+        cpu.execute_instruction(0x00E0).unwrap();
+        assert_eq!(cpu.take_dirty_regions(), vec![Rect::new(0, 0, 64, 32)]);
+
+        let mut cpu = Cpu::with_mode(Mode::SuperChip);
+        cpu.take_dirty_regions();
+        // HIGH
+        cpu.execute_instruction(0x00FF).unwrap();
+        assert_eq!(cpu.take_dirty_regions(), vec![Rect::new(0, 0, 128, 64)]);
+        // SCD 1
+        cpu.execute_instruction(0x00C1).unwrap();
+        assert_eq!(cpu.take_dirty_regions(), vec![Rect::new(0, 0, 128, 64)]);
+
+        // loading a state redraws everything
+        let state = cpu.save_state();
+        cpu.load_state(&state).unwrap();
+        assert_eq!(cpu.take_dirty_regions(), vec![Rect::new(0, 0, 128, 64)]);
+    }
+
+    #[test]
+    fn test_merges_many_regions() {
+        let mut cpu = clean_cpu(Quirks::default());
+        for i in 0..20 {
+            draw_zero(&mut cpu, i * 2, i);
+        }
+        let regions = cpu.take_dirty_regions();
+        assert!(regions.len() <= 16);
+        let bounds = regions.iter().fold(regions[0], |acc, r| acc.union(r));
+        assert_eq!(bounds, Rect::new(0, 0, 46, 24));
+    }
+}
@@ -0,0 +1,68 @@
+use crate::cpu::Mode;
+use crate::disasm::{disassemble, Entry, LabelKind};
+use crate::instruction::Instruction;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // CALL sub, LD I, sprite, DRW, JP to itself,
+    // then the subroutine and a sprite
+    const ROM: [u8; 14] = [
+        0x22, 0x08, // 200: CALL 0x208
+        0xA2, 0x0A, // 202: LD I, 0x20A
+        0xD0, 0x11, // 204: DRW V0, V1, 1
+        0x12, 0x06, // 206: JP 0x206
+        0x00, 0xEE, // 208: RET
+        0xF0, 0x90, 0x90, 0xF0, // 20A: sprite
+    ];
+
+    #[test]
+    fn test_code_and_data() {
+        let dis = disassemble(&ROM, Mode::Chip8);
+        assert_eq!(
+            dis.entries[0],
+            Entry::Code {
+                addr: 0x200,
+                inst: Instruction::Call(0x208)
+            }
+        );
+        assert_eq!(
+            dis.entries.last().unwrap(),
+            &Entry::Data {
+                addr: 0x20A,
+                bytes: vec![0xF0, 0x90, 0x90, 0xF0]
+            }
+        );
+        assert_eq!(dis.labels.get(&0x208), Some(&LabelKind::Subroutine));
+        assert_eq!(dis.labels.get(&0x20A), Some(&LabelKind::Data));
+        assert_eq!(dis.labels.get(&0x206), Some(&LabelKind::Jump));
+    }
+
+    #[test]
+    fn test_skip_follows_both_paths() {
+        let rom = [
+            0x30, 0x01, // 200: SE V0, 0x01
+            0x12, 0x08, // 202: JP 0x208
+            0x00, 0xE0, // 204: CLS
+            0x12, 0x04, // 206: JP 0x204
+            0x12, 0x08, // 208: JP 0x208
+        ];
+        let dis = disassemble(&rom, Mode::Chip8);
+        assert!(dis
+            .entries
+            .iter()
+            .all(|entry| matches!(entry, Entry::Code { .. })));
+    }
+
+    #[test]
+    fn test_listing() {
+        let listing = disassemble(&ROM, Mode::Chip8).to_string();
+        let lines: Vec<&str> = listing.lines().map(str::trim_end).collect();
+        assert_eq!(lines[0], "label_200:");
+        assert!(lines[1].starts_with("    CALL sub_208 "));
+        assert!(lines[1].ends_with("; 200: 2208"));
+        assert!(listing.contains("    LD I, data_20a "));
+        assert!(listing.contains("data_20a:\n    db 0xF0, 0x90, 0x90, 0xF0 "));
+    }
+}
@@ -0,0 +1,119 @@
+use crate::cpu::{Cpu, Mode, FLAG_REGISTER};
+use crate::framebuffer::Framebuffer;
+use crate::quirks::Quirks;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pixels() {
+        let mut display = Framebuffer::new(64, 32);
+        assert_eq!(display.len(), 64 * 32);
+        display.set(0, 0, 1);
+        display.set(63, 1, 2);
+        display.set(5, 2, 3);
+
+        // the leftmost pixel is the most significant bit
+        assert_eq!(display.row(0, 0), 1 << 63);
+        assert_eq!(display.row(1, 1), 1);
+        assert_eq!(display.get(5, 2), 3);
+        assert_eq!(display.pixel(64 + 63), 2);
+
+        let pixels = display.pixels();
+        assert_eq!(pixels.len(), 64 * 32);
+        assert_eq!(pixels.iter().filter(|p| **p != 0).count(), 3);
+        assert_eq!(pixels[2 * 64 + 5], 3);
+
+        display.set(5, 2, 0);
+        assert_eq!(display.get(5, 2), 0);
+        display.clear(1);
+        assert_eq!(display.get(0, 0), 0);
+        assert_eq!(display.get(63, 1), 2);
+    }
+
+    #[test]
+    fn test_xor_row() {
+        let mut display = Framebuffer::new(128, 64);
+        assert_eq!(display.xor_row(0, 3, 0b1100), 0);
+        assert_eq!(display.xor_row(0, 3, 0b0110), 0b0100);
+        assert_eq!(display.row(0, 3), 0b1010);
+        assert_eq!(display.row(1, 3), 0);
+
+        // the full 128 bit row is usable in high resolution
+        display.xor_row(1, 0, u128::MAX);
+        assert!(display.iter().take(128).all(|p| p == 2));
+    }
+
+    #[test]
+    fn test_sprite_row() {
+        let display = Framebuffer::new(64, 32);
+        assert_eq!(display.sprite_row(0, 0xF0, 8, false), 0xF0 << 56);
+        assert_eq!(display.sprite_row(56, 0xF0, 8, false), 0xF0);
+
+        // pixels past the right edge are clipped or wrapped
+        assert_eq!(display.sprite_row(60, 0xFF, 8, false), 0x0F);
+        assert_eq!(display.sprite_row(60, 0xFF, 8, true), 0xF << 60 | 0x0F);
+        let hires = Framebuffer::new(128, 64);
+        assert_eq!(hires.sprite_row(120, 0xFFFF, 16, true), 0xFF << 120 | 0xFF);
+    }
+
+    #[test]
+    fn test_scroll() {
+        let mut display = Framebuffer::new(64, 32);
+        display.set(0, 0, 3);
+        display.set(63, 31, 1);
+
+        display.scroll(1, 4, 1);
+        assert_eq!(display.get(4, 1), 1);
+        assert_eq!(display.get(0, 0), 2);
+        // scrolled off the bottom right
+        assert!(display.iter().filter(|p| *p == 1).count() == 1);
+
+        display.scroll(3, -8, -1);
+        assert_eq!(display.get(4, 1), 0);
+        assert!(display.iter().all(|p| p == 0));
+    }
+
+    #[test]
+    fn test_drw_wraps_hires() {
+        let mut quirks = Quirks::xo_chip();
+        quirks.draw_wraps = true;
+        let mut cpu = Cpu::with_mode(Mode::XoChip);
+        cpu.quirks = quirks;
+        cpu.execute_instruction(0x00FF).unwrap(); // HIGH
+
+        // 16x16 sprite of solid rows from 0x300
+        cpu.memory[0x300..0x320].fill(0xFF);
+        cpu.i_register = 0x300;
+        cpu.registers[0] = 120;
+        cpu.registers[1] = 60;
+        cpu.execute_instruction(0xD010).unwrap();
+        assert_eq!(cpu.registers[FLAG_REGISTER], 0);
+        assert_eq!(cpu.display.iter().filter(|p| *p == 1).count(), 256);
+        assert_eq!(cpu.display.get(127, 63), 1);
+        assert_eq!(cpu.display.get(0, 0), 1);
+        assert_eq!(cpu.display.get(7, 11), 1);
+        assert_eq!(cpu.display.get(8, 0), 0);
+
+        // drawing it again erases it and sets the glow
+        cpu.execute_instruction(0xD010).unwrap();
+        assert_eq!(cpu.registers[FLAG_REGISTER], 1);
+        assert!(cpu.display.iter().all(|p| p == 0));
+        assert_eq!(cpu.phosphor_glow[7 + 11 * 128], cpu.phosphor_decay);
+    }
+
+    #[test]
+    fn test_packed_state() {
+        let mut cpu = Cpu::new();
+        cpu.display.set(3, 4, 1);
+        cpu.display.set(60, 31, 2);
+        let state = cpu.save_state();
+
+        let mut restored = Cpu::new();
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.display, cpu.display);
+        // one bit per pixel and plane instead of a byte per pixel
+        assert_eq!(cpu.display.packed_len(), 64 * 32 * 2 / 8);
+    }
+}
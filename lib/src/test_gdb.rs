@@ -0,0 +1,230 @@
+use crate::cpu::{Cpu, Mode};
+use crate::gdb::{GdbServer, TARGET_XML};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROM: [u8; 12] = [
+        0x60, 0x05, // LD V0, 0x05
+        0x22, 0x08, // CALL 0x208
+        0x70, 0x01, // ADD V0, 0x01
+        0x12, 0x06, // JP 0x206
+        0xA3, 0x00, // LD I, 0x300
+        0x00, 0xEE, // RET
+    ];
+
+    /**
+     * A scripted GDB talking to a server on another thread
+     */
+    struct Client {
+        stream: TcpStream,
+        acks: bool,
+    }
+
+    impl Client {
+        fn send(&mut self, data: &str) {
+            let sum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+            write!(self.stream, "${}#{:02x}", data, sum).unwrap();
+            if self.acks {
+                assert_eq!(self.byte(), b'+');
+            }
+        }
+
+        fn reply(&mut self) -> String {
+            while self.byte() != b'$' {}
+            let mut data = Vec::new();
+            loop {
+                match self.byte() {
+                    b'#' => break,
+                    b => data.push(b),
+                }
+            }
+            let sum = data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+            let digits = [self.byte(), self.byte()];
+            assert_eq!(
+                std::str::from_utf8(&digits).unwrap(),
+                format!("{:02x}", sum)
+            );
+            if self.acks {
+                self.stream.write_all(b"+").unwrap();
+            }
+            String::from_utf8(data).unwrap()
+        }
+
+        fn request(&mut self, data: &str) -> String {
+            self.send(data);
+            self.reply()
+        }
+
+        fn byte(&mut self) -> u8 {
+            let mut byte = [0u8];
+            self.stream.read_exact(&mut byte).unwrap();
+            byte[0]
+        }
+    }
+
+    fn connect(cpu: Cpu) -> (Client, JoinHandle<GdbServer>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut server = GdbServer::new(cpu);
+            server.serve(&listener).unwrap();
+            server
+        });
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_nodelay(true).unwrap();
+        (Client { stream, acks: true }, server)
+    }
+
+    fn cpu(rom: &[u8]) -> Cpu {
+        let mut cpu = Cpu::new();
+        cpu.load_from_bytes(rom).unwrap();
+        cpu
+    }
+
+    #[test]
+    fn test_registers() {
+        let (mut gdb, server) = connect(cpu(&ROM));
+        assert_eq!(gdb.request("?"), "S05");
+        // V0-VF, I, PC, SP, DT, ST
+        assert_eq!(
+            gdb.request("g"),
+            format!("{}00000002000000", "00".repeat(16))
+        );
+
+        let registers = format!("{}00030a02010203", "11".repeat(16));
+        assert_eq!(gdb.request(&format!("G{}", registers)), "OK");
+        assert_eq!(gdb.request("g"), registers);
+        assert_eq!(gdb.request("p11"), "0a02");
+        assert_eq!(gdb.request("Pf=7f"), "OK");
+        assert_eq!(gdb.request("pf"), "7f");
+        assert_eq!(gdb.request("p15"), "E01");
+        assert_eq!(gdb.request("G00"), "E01");
+        // a bad digit anywhere leaves every register untouched
+        let before = gdb.request("g");
+        let malformed = format!("G{}00030a020102zz", "22".repeat(16));
+        assert_eq!(gdb.request(&malformed), "E01");
+        assert_eq!(gdb.request("g"), before);
+        assert_eq!(gdb.request("D"), "OK");
+
+        let cpu = server.join().unwrap().debugger.cpu;
+        assert_eq!(cpu.registers[0xF], 0x7F);
+        assert_eq!(cpu.i_register, 0x300);
+        assert_eq!(cpu.program_counter, 0x20A);
+        assert_eq!(cpu.stack.len(), 1);
+        assert_eq!((cpu.delay_timer, cpu.sound_timer), (2, 3));
+    }
+
+    #[test]
+    fn test_memory() {
+        let (mut gdb, server) = connect(cpu(&ROM));
+        assert_eq!(gdb.request("m200,4"), "60052208");
+        assert_eq!(gdb.request("M300,3:abcdef"), "OK");
+        assert_eq!(gdb.request("m300,3"), "abcdef");
+        assert_eq!(gdb.request("mfff,2"), "E01");
+        assert_eq!(gdb.request("M300,2:ab"), "E01");
+        gdb.send("k");
+        let cpu = server.join().unwrap().debugger.cpu;
+        assert_eq!(&cpu.memory[0x300..0x303], &[0xAB, 0xCD, 0xEF]);
+    }
+
+    #[test]
+    fn test_breakpoints_and_stepping() {
+        let (mut gdb, server) = connect(cpu(&ROM));
+        assert_eq!(gdb.request("Z0,208,2"), "OK");
+        assert_eq!(gdb.request("c"), "S05");
+        assert_eq!(gdb.request("p11"), "0802");
+        assert_eq!(gdb.request("p12"), "01");
+
+        assert_eq!(gdb.request("z0,208,2"), "OK");
+        assert_eq!(gdb.request("s"), "S05");
+        assert_eq!(gdb.request("p11"), "0a02");
+        assert_eq!(gdb.request("p10"), "0003");
+
+        // step from an address
+        assert_eq!(gdb.request("s204"), "S05");
+        assert_eq!(gdb.request("p0"), "06");
+        // JP to itself never makes progress
+        assert_eq!(gdb.request("c"), "S05");
+        assert_eq!(gdb.request("p11"), "0602");
+        assert_eq!(gdb.request("Z9,200,2"), "E01");
+        gdb.send("k");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        // LD I, 0x300 ; LD V0, 0x2A ; LD [I], V0 ; JP 0x206
+        let rom = [0xA3, 0x00, 0x60, 0x2A, 0xF0, 0x55, 0x12, 0x06];
+        let (mut gdb, server) = connect(cpu(&rom));
+        assert_eq!(gdb.request("Z2,300,1"), "OK");
+        assert_eq!(gdb.request("c"), "T05watch:300;");
+        assert_eq!(gdb.request("m300,1"), "2a");
+        gdb.send("k");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_queries() {
+        let (mut gdb, server) = connect(cpu(&ROM));
+        let supported = gdb.request("qSupported:multiprocess+;xmlRegisters=i386");
+        assert!(supported.contains("qXfer:features:read+"));
+        assert_eq!(gdb.request("vMustReplyEmpty"), "");
+        assert_eq!(gdb.request("Hg0"), "OK");
+        assert_eq!(gdb.request("qAttached"), "1");
+
+        // read the target description in small chunks
+        let mut xml = String::new();
+        loop {
+            let chunk = gdb.request(&format!(
+                "qXfer:features:read:target.xml:{:x},80",
+                xml.len()
+            ));
+            xml += &chunk[1..];
+            if chunk.starts_with('l') {
+                break;
+            }
+            assert!(chunk.starts_with('m'));
+        }
+        assert_eq!(xml, TARGET_XML);
+        assert_eq!(xml.matches("<reg ").count(), 21);
+
+        assert_eq!(gdb.request("QStartNoAckMode"), "OK");
+        gdb.acks = false;
+        assert_eq!(gdb.request("m200,2"), "6005");
+        assert_eq!(gdb.request("D"), "OK");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_interrupt_and_exit() {
+        // ADD V0, 0x01 ; JP 0x200
+        let (mut gdb, server) = connect(cpu(&[0x70, 0x01, 0x12, 0x00]));
+        gdb.send("c");
+        thread::sleep(std::time::Duration::from_millis(20));
+        gdb.stream.write_all(&[0x03]).unwrap();
+        assert_eq!(gdb.reply(), "S02");
+        assert_eq!(gdb.request("?"), "S02");
+
+        // a packet sent while running is answered once it stops
+        gdb.send("c");
+        thread::sleep(std::time::Duration::from_millis(20));
+        gdb.stream.write_all(b"$?#3f\x03").unwrap();
+        assert_eq!(gdb.reply(), "S02");
+        assert_eq!(gdb.reply(), "S02");
+        gdb.send("k");
+        server.join().unwrap();
+
+        // CLS ; EXIT
+        let mut cpu = Cpu::with_mode(Mode::SuperChip);
+        cpu.load_from_bytes(&[0x00, 0xE0, 0x00, 0xFD]).unwrap();
+        let (mut gdb, server) = connect(cpu);
+        assert_eq!(gdb.request("c"), "W00");
+        gdb.send("k");
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,88 @@
+use crate::cpu::Mode;
+use crate::error::Chip8Error;
+use crate::instruction::Instruction;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(Instruction::decode(0x00E0).unwrap(), Instruction::Cls);
+        assert_eq!(Instruction::decode(0x1228).unwrap(), Instruction::Jp(0x228));
+        assert_eq!(
+            Instruction::decode(0x3A05).unwrap(),
+            Instruction::SeVxByte { x: 0xA, kk: 0x05 }
+        );
+        assert_eq!(
+            Instruction::decode(0xD125).unwrap(),
+            Instruction::Drw { x: 1, y: 2, n: 5 }
+        );
+        assert_eq!(
+            Instruction::decode(0xF265).unwrap(),
+            Instruction::LdVxI { x: 2 }
+        );
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        for opcode in &[0x5121, 0x800F, 0x9121, 0xE100, 0xF0FF] {
+            match Instruction::decode(*opcode) {
+                Err(Chip8Error::InvalidOpcode { opcode: op, .. }) => assert_eq!(op, *opcode),
+                res => panic!("unexpected {:?}", res),
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        // every valid opcode encodes back to itself
+        for opcode in 0..=0xFFFFu16 {
+            if let Ok(inst) = Instruction::decode(opcode) {
+                assert_eq!(inst.encode(), opcode, "{}", inst);
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_at_long() {
+        let memory = [0xF0, 0x00, 0x12, 0x34];
+        let inst = Instruction::decode_at(&memory, 0).unwrap();
+        assert_eq!(inst, Instruction::LdILong(0x1234));
+        assert_eq!(inst.size(), 4);
+        assert_eq!(inst.to_bytes(), memory);
+
+        assert!(Instruction::decode_at(&memory[..3], 0).is_err());
+    }
+
+    #[test]
+    fn test_display() {
+        let expected = [
+            (0x00E0, "CLS"),
+            (0x2412, "CALL 0x412"),
+            (0x6105, "LD V1, 0x05"),
+            (0x8AB4, "ADD VA, VB"),
+            (0xB208, "JP V0, 0x208"),
+            (0xD01F, "DRW V0, V1, 15"),
+            (0xF00A, "LD V0, K"),
+            (0xF355, "LD [I], V3"),
+            (0x5242, "LD [I], V2 - V4"),
+            (0x00C4, "SCD 4"),
+        ];
+        for (opcode, text) in expected.iter() {
+            assert_eq!(Instruction::decode(*opcode).unwrap().to_string(), *text);
+        }
+    }
+
+    #[test]
+    fn test_available_in() {
+        let cls = Instruction::Cls;
+        let high = Instruction::High;
+        let plane = Instruction::Plane(1);
+        assert!(cls.available_in(Mode::Chip8));
+        assert!(!high.available_in(Mode::Chip8));
+        assert!(high.available_in(Mode::SuperChip));
+        assert!(!plane.available_in(Mode::SuperChip));
+        assert!(plane.available_in(Mode::XoChip));
+    }
+}
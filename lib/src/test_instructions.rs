@@ -1,5 +1,8 @@
 use crate::cpu;
-use crate::cpu::FLAG_REGISTER;
+use crate::cpu::{KeyWait, Mode, BIG_FONT_OFFSET, FLAG_REGISTER, XO_MEM_SIZE};
+use crate::error::Chip8Error;
+use crate::quirks::{IndexIncrement, Quirks};
+use crate::rng::ScriptedSource;
 
 #[cfg(test)]
 mod test_instructions {
@@ -395,6 +398,33 @@ mod test_instructions {
         assert_eq!(cpu.registers[0], 0);
     }
 
+    #[test]
+    fn test_rnd_scripted() {
+        let mut cpu = cpu::Cpu::new();
+        cpu.set_rng(ScriptedSource::new(vec![0xAB, 0xFF]));
+
+        cpu.execute_instruction(0xC0FF).unwrap();
+        assert_eq!(cpu.registers[0], 0xAB);
+        cpu.execute_instruction(0xC10F).unwrap();
+        assert_eq!(cpu.registers[1], 0x0F);
+
+        // the script repeats
+        cpu.execute_instruction(0xC2F0).unwrap();
+        assert_eq!(cpu.registers[2], 0xA0);
+    }
+
+    #[test]
+    fn test_rnd_seeded() {
+        let mut a = cpu::Cpu::with_seed(1234);
+        let mut b = cpu::Cpu::with_seed(1234);
+        for reg in 0..16u16 {
+            a.execute_instruction(0xC0FF | reg << 8).unwrap();
+            b.execute_instruction(0xC0FF | reg << 8).unwrap();
+        }
+        assert_eq!(a.registers, b.registers);
+        assert!(a.registers.iter().any(|r| *r != a.registers[0]));
+    }
+
     // TODO test draw  #[test]
     #[test]
     fn test_drw_vx_vy_n() {
@@ -450,6 +480,57 @@ mod test_instructions {
         assert_eq!(cpu.registers[0], 0x4);
     }
 
+    #[test]
+    fn test_ld_vx_k_release() {
+        let mut cpu = cpu::Cpu::with_quirks(Quirks::cosmac_vip());
+        cpu.execute_instruction(0xF20A).unwrap(); // LD V2, K
+
+        cpu.key_down_chip8(0x7);
+        assert_eq!(cpu.key_wait(), KeyWait::Release { x: 2, key: 0x7 });
+        assert_eq!(cpu.registers[2], 0);
+
+        // other keys don't complete the wait
+        cpu.key_up_chip8(0x3);
+        assert!(cpu.is_waiting_for_key());
+
+        cpu.key_up_chip8(0x7);
+        assert!(!cpu.is_waiting_for_key());
+        assert_eq!(cpu.registers[2], 0x7);
+    }
+
+    #[test]
+    fn test_ld_vx_k_held_key() {
+        let mut cpu = cpu::Cpu::new();
+        cpu.key_down_chip8(0x1);
+        cpu.execute_instruction(0xF00A).unwrap(); // LD V0, K
+
+        // a key held before Fx0A must be pressed again
+        cpu.execute_instruction(0x6055).unwrap();
+        assert!(cpu.is_waiting_for_key());
+        cpu.key_up_chip8(0x1);
+        cpu.key_down_chip8(0x1);
+        assert!(!cpu.is_waiting_for_key());
+        assert_eq!(cpu.registers[0], 0x1);
+    }
+
+    #[test]
+    fn test_ld_vx_k_cancel() {
+        let mut cpu = cpu::Cpu::new();
+        cpu.registers[0] = 0x42;
+        cpu.execute_instruction(0xF00A).unwrap(); // LD V0, K
+        cpu.delay_timer = 2;
+
+        // timers keep running while waiting
+        cpu.decrement_timers();
+        assert_eq!(cpu.delay_timer, 1);
+
+        cpu.cancel_key_wait();
+        assert!(!cpu.is_waiting_for_key());
+        cpu.execute_instruction(0x6155).unwrap();
+        assert_eq!(cpu.program_counter, 0x204);
+        assert_eq!(cpu.registers[0], 0x42);
+    }
+
     #[test]
     fn test_ld_dt_vx() {
         let mut cpu = cpu::Cpu::new();
@@ -474,4 +555,378 @@ mod test_instructions {
     // test_ld_b_vx
     // test_ld_i_vx
     // test_ld_vx_i
+
+    #[test]
+    fn test_quirk_shift_uses_vy() {
+        let mut cpu = cpu::Cpu::with_quirks(Quirks::cosmac_vip());
+        cpu.registers[0] = 0xff;
+        cpu.registers[1] = 0x02;
+
+        // SHR V0, V1 => V0 = V1 >> 1
+        cpu.execute_instruction(0x8016).unwrap();
+        assert_eq!(cpu.registers[0], 0x01);
+        assert_eq!(cpu.registers[FLAG_REGISTER], 0);
+
+        // SHL V0, V1 => V0 = V1 << 1
+        cpu.registers[1] = 0x81;
+        cpu.execute_instruction(0x801e).unwrap();
+        assert_eq!(cpu.registers[0], 0x02);
+        assert_eq!(cpu.registers[FLAG_REGISTER], 1);
+    }
+
+    #[test]
+    fn test_quirk_logic_resets_vf() {
+        let mut cpu = cpu::Cpu::with_quirks(Quirks::cosmac_vip());
+        for opcode in &[0x8011, 0x8012, 0x8013] {
+            cpu.registers[FLAG_REGISTER] = 1;
+            cpu.execute_instruction(*opcode).unwrap();
+            assert_eq!(cpu.registers[FLAG_REGISTER], 0);
+        }
+
+        let mut cpu = cpu::Cpu::new();
+        cpu.registers[FLAG_REGISTER] = 1;
+        cpu.execute_instruction(0x8011).unwrap();
+        assert_eq!(cpu.registers[FLAG_REGISTER], 1);
+    }
+
+    #[test]
+    fn test_quirk_index_increment() {
+        let expected = [
+            (IndexIncrement::None, 0x300),
+            (IndexIncrement::ByX, 0x303),
+            (IndexIncrement::ByXPlusOne, 0x304),
+        ];
+        for (increment, i) in expected.iter() {
+            let quirks = Quirks {
+                index_increment: *increment,
+                ..Quirks::default()
+            };
+            let mut cpu = cpu::Cpu::with_quirks(quirks);
+            cpu.i_register = 0x300;
+            cpu.execute_instruction(0xF355).unwrap();
+            assert_eq!(cpu.i_register, *i);
+
+            cpu.i_register = 0x300;
+            cpu.execute_instruction(0xF365).unwrap();
+            assert_eq!(cpu.i_register, *i);
+        }
+    }
+
+    #[test]
+    fn test_quirk_jump_uses_vx() {
+        let mut cpu = cpu::Cpu::with_quirks(Quirks::chip48());
+        cpu.registers[0] = 0x10;
+        cpu.registers[2] = 0x04;
+
+        // JP V2, 0x208
+        cpu.execute_instruction(0xB208).unwrap();
+        assert_eq!(cpu.program_counter, 0x208 + 0x04);
+    }
+
+    #[test]
+    fn test_quirk_draw_wraps() {
+        // draw the 0 glyph in the bottom right corner
+        let mut cpu = cpu::Cpu::new();
+        cpu.registers[0] = 62;
+        cpu.registers[1] = 30;
+        cpu.execute_instruction(0xD015).unwrap();
+        assert_eq!(cpu.display.pixel(1), 0);
+        assert_eq!(cpu.display.iter().filter(|p| *p == 1).count(), 3);
+
+        let quirks = Quirks {
+            draw_wraps: true,
+            ..Quirks::default()
+        };
+        let mut cpu = cpu::Cpu::with_quirks(quirks);
+        cpu.registers[0] = 62;
+        cpu.registers[1] = 30;
+        cpu.execute_instruction(0xD015).unwrap();
+        assert_eq!(cpu.display.pixel(1), 1);
+        assert_eq!(cpu.display.iter().filter(|p| *p == 1).count(), 14);
+    }
+
+    #[test]
+    fn test_schip_opcodes_ignored_in_chip8() {
+        let mut cpu = cpu::Cpu::new();
+
+        // 00FF is treated as SYS
+        cpu.execute_instruction(0x00FF).unwrap();
+        assert_eq!(cpu.resolution(), (64, 32));
+        assert!(cpu.execute_instruction(0xF030).is_err());
+    }
+
+    #[test]
+    fn test_schip_resolution() {
+        let mut cpu = cpu::Cpu::with_mode(Mode::SuperChip);
+        assert_eq!(cpu.quirks, Quirks::schip());
+        assert_eq!(cpu.resolution(), (64, 32));
+
+        // HIGH
+        cpu.execute_instruction(0x00FF).unwrap();
+        let (display, _, resolution) = cpu.get_display();
+        assert_eq!(resolution, (128, 64));
+        assert_eq!(display.len(), 128 * 64);
+
+        // LOW
+        cpu.execute_instruction(0x00FE).unwrap();
+        assert_eq!(cpu.resolution(), (64, 32));
+        assert_eq!(cpu.display.len(), 64 * 32);
+    }
+
+    #[test]
+    fn test_schip_drw_16x16() {
+        let mut cpu = cpu::Cpu::with_mode(Mode::SuperChip);
+        cpu.execute_instruction(0x00FF).unwrap();
+
+        // 16x16 solid sprite
+        for b in &mut cpu.memory[0x300..0x320] {
+            *b = 0xFF;
+        }
+        cpu.i_register = 0x300;
+        cpu.registers[0] = 100;
+        cpu.registers[1] = 40;
+        cpu.execute_instruction(0xD010).unwrap();
+        assert_eq!(cpu.registers[FLAG_REGISTER], 0);
+        assert_eq!(cpu.display.iter().filter(|p| *p == 1).count(), 256);
+        assert_eq!(cpu.display.pixel(100 + 40 * 128), 1);
+        assert_eq!(cpu.display.pixel(115 + 55 * 128), 1);
+
+        cpu.execute_instruction(0xD010).unwrap();
+        assert_eq!(cpu.registers[FLAG_REGISTER], 1);
+        assert!(cpu.display.iter().all(|p| p == 0));
+    }
+
+    #[test]
+    fn test_schip_scroll() {
+        let mut cpu = cpu::Cpu::with_mode(Mode::SuperChip);
+        cpu.display.set(0, 0, 1);
+
+        // SCD 3
+        cpu.execute_instruction(0x00C3).unwrap();
+        assert_eq!(cpu.display.pixel(0), 0);
+        assert_eq!(cpu.display.pixel(3 * 64), 1);
+
+        // SCR
+        cpu.execute_instruction(0x00FB).unwrap();
+        assert_eq!(cpu.display.pixel(3 * 64 + 4), 1);
+
+        // SCL twice, the pixel falls off the left edge
+        cpu.execute_instruction(0x00FC).unwrap();
+        assert_eq!(cpu.display.pixel(3 * 64), 1);
+        cpu.execute_instruction(0x00FC).unwrap();
+        assert!(cpu.display.iter().all(|p| p == 0));
+    }
+
+    #[test]
+    fn test_schip_exit() {
+        let mut cpu = cpu::Cpu::with_mode(Mode::SuperChip);
+        cpu.execute_instruction(0x00FD).unwrap();
+        assert!(cpu.has_exited());
+        assert_eq!(cpu.program_counter, 0x200);
+
+        // nothing executes after exiting
+        cpu.execute_instruction(0x6050).unwrap();
+        assert_eq!(cpu.registers[0], 0);
+    }
+
+    #[test]
+    fn test_schip_ld_hf_vx() {
+        let mut cpu = cpu::Cpu::with_mode(Mode::SuperChip);
+        cpu.registers[0] = 9;
+        cpu.execute_instruction(0xF030).unwrap();
+        assert_eq!(cpu.i_register as usize, BIG_FONT_OFFSET + 90);
+    }
+
+    #[test]
+    fn test_schip_rpl_flags() {
+        let mut cpu = cpu::Cpu::with_mode(Mode::SuperChip);
+        for i in 0..8 {
+            cpu.registers[i] = i as u8 + 1;
+        }
+        cpu.execute_instruction(0xF775).unwrap();
+        cpu.registers = [0; 16];
+        cpu.execute_instruction(0xF385).unwrap();
+        assert_eq!(cpu.registers[0..5], [1, 2, 3, 4, 0]);
+    }
+
+    #[test]
+    fn test_xo_memory() {
+        let cpu = cpu::Cpu::new();
+        assert_eq!(cpu.memory.len(), cpu::MEM_SIZE);
+
+        let mut cpu = cpu::Cpu::with_mode(Mode::XoChip);
+        assert_eq!(cpu.memory.len(), XO_MEM_SIZE);
+        assert!(cpu.load_from_bytes(&[0u8; 0x8000]).is_ok());
+    }
+
+    #[test]
+    fn test_xo_ld_i_nnnn() {
+        let mut cpu = cpu::Cpu::with_mode(Mode::XoChip);
+        cpu.load_from_bytes(&[0xF0, 0x00, 0xBE, 0xEF]).unwrap();
+        let opcode = cpu.fetch_instruction().unwrap();
+        cpu.execute_instruction(opcode).unwrap();
+        assert_eq!(cpu.i_register, 0xBEEF);
+        assert_eq!(cpu.program_counter, 0x204);
+    }
+
+    #[test]
+    fn test_xo_skip_long_instruction() {
+        let mut cpu = cpu::Cpu::with_mode(Mode::XoChip);
+
+        // SE V0, 0 followed by F000 NNNN skips 4 bytes
+        cpu.load_from_bytes(&[0x30, 0x00, 0xF0, 0x00, 0x12, 0x34])
+            .unwrap();
+        cpu.execute_instruction(0x3000).unwrap();
+        assert_eq!(cpu.program_counter, 0x206);
+    }
+
+    #[test]
+    fn test_xo_register_range() {
+        let mut cpu = cpu::Cpu::with_mode(Mode::XoChip);
+        cpu.registers[2] = 0x22;
+        cpu.registers[3] = 0x33;
+        cpu.registers[4] = 0x44;
+        cpu.i_register = 0x400;
+
+        // LD [I], V2 - V4
+        cpu.execute_instruction(0x5242).unwrap();
+        assert_eq!(cpu.memory[0x400..0x403], [0x22, 0x33, 0x44]);
+        assert_eq!(cpu.i_register, 0x400);
+
+        // LD V4 - V2, [I] reverses the order
+        cpu.execute_instruction(0x5423).unwrap();
+        assert_eq!(cpu.registers[2..5], [0x44, 0x33, 0x22]);
+    }
+
+    #[test]
+    fn test_se_vx_vy_ignores_low_nibble() {
+        for mode in [Mode::Chip8, Mode::SuperChip] {
+            let mut cpu = cpu::Cpu::with_mode(mode);
+            cpu.registers[2] = 0x22;
+            cpu.registers[4] = 0x22;
+            cpu.i_register = 0x400;
+
+            // SE V2, V4 rather than LD [I], V2 - V4
+            cpu.execute_instruction(0x5242).unwrap();
+            assert_eq!(cpu.program_counter, 0x204);
+            assert_eq!(cpu.memory[0x400], 0);
+
+            cpu.registers[4] = 0x44;
+            cpu.execute_instruction(0x5249).unwrap();
+            assert_eq!(cpu.program_counter, 0x206);
+        }
+    }
+
+    #[test]
+    fn test_xo_planes() {
+        let mut cpu = cpu::Cpu::with_mode(Mode::XoChip);
+        cpu.memory[0x300] = 0x80; // plane 1
+        cpu.memory[0x301] = 0x80; // plane 2
+        cpu.i_register = 0x300;
+
+        // PLANE 3, draw 1 row on both planes
+        cpu.execute_instruction(0xF301).unwrap();
+        cpu.execute_instruction(0xD011).unwrap();
+        assert_eq!(cpu.display.pixel(0), 0b11);
+
+        // PLANE 2, CLS only clears the second plane
+        cpu.execute_instruction(0xF201).unwrap();
+        cpu.execute_instruction(0x00E0).unwrap();
+        assert_eq!(cpu.display.pixel(0), 0b01);
+
+        // drawing on plane 2 does not collide with plane 1
+        cpu.execute_instruction(0xD011).unwrap();
+        assert_eq!(cpu.registers[FLAG_REGISTER], 0);
+        assert_eq!(cpu.display.pixel(0), 0b11);
+    }
+
+    #[test]
+    fn test_xo_audio() {
+        let mut cpu = cpu::Cpu::with_mode(Mode::XoChip);
+        for (i, b) in cpu.memory[0x300..0x310].iter_mut().enumerate() {
+            *b = i as u8;
+        }
+        cpu.i_register = 0x300;
+        cpu.execute_instruction(0xF002).unwrap();
+        assert_eq!(cpu.audio_pattern[15], 15);
+
+        assert_eq!(cpu.pitch, 64);
+        cpu.registers[1] = 100;
+        cpu.execute_instruction(0xF13A).unwrap();
+        assert_eq!(cpu.pitch, 100);
+    }
+
+    #[test]
+    fn test_stack_errors() {
+        let mut cpu = cpu::Cpu::new();
+        match cpu.execute_instruction(0x00EE) {
+            Err(Chip8Error::StackUnderflow { pc: 0x200 }) => {}
+            res => panic!("unexpected {:?}", res),
+        }
+
+        for _ in 0..cpu::STACK_SIZE {
+            cpu.execute_instruction(0x2200).unwrap();
+        }
+        match cpu.execute_instruction(0x2200) {
+            Err(Chip8Error::StackOverflow { pc: 0x200 }) => {}
+            res => panic!("unexpected {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_memory_out_of_bounds() {
+        let mut cpu = cpu::Cpu::new();
+        cpu.i_register = 0xFFE;
+
+        // LD B, V0 writes 3 bytes
+        match cpu.execute_instruction(0xF033) {
+            Err(Chip8Error::MemoryOutOfBounds {
+                addr: 0xFFF,
+                pc: 0x200,
+            }) => {}
+            res => panic!("unexpected {:?}", res),
+        }
+        assert!(cpu.execute_instruction(0xFF55).is_err());
+        assert!(cpu.execute_instruction(0xFF65).is_err());
+        assert!(cpu.execute_instruction(0xD01F).is_err());
+
+        cpu.program_counter = 0xFFE;
+        assert!(cpu.fetch_instruction().is_err());
+    }
+
+    #[test]
+    fn test_invalid_opcode() {
+        let mut cpu = cpu::Cpu::new();
+        match cpu.execute_instruction(0xFFFF) {
+            Err(Chip8Error::InvalidOpcode {
+                opcode: 0xFFFF,
+                pc: 0x200,
+            }) => {}
+            res => panic!("unexpected {:?}", res),
+        }
+        assert_eq!(cpu.program_counter, 0x200);
+    }
+
+    #[test]
+    fn test_ld_f_vx() {
+        let mut cpu = cpu::Cpu::new();
+        cpu.registers[0] = 0xA;
+        cpu.execute_instruction(0xF029).unwrap();
+        assert_eq!(cpu.i_register, 50);
+
+        cpu.registers[0] = 0x10;
+        match cpu.execute_instruction(0xF029) {
+            Err(Chip8Error::UnsupportedFontDigit { digit: 0x10, .. }) => {}
+            res => panic!("unexpected {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_rom_too_large() {
+        let mut cpu = cpu::Cpu::new();
+        match cpu.load_from_bytes(&[0u8; 0x1000]) {
+            Err(Chip8Error::RomTooLarge { size: 0x1000, .. }) => {}
+            res => panic!("unexpected {:?}", res),
+        }
+    }
 }
@@ -0,0 +1,94 @@
+use crate::cpu::Cpu;
+use crate::keymap::Keymap;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets() {
+        let qwerty = Keymap::qwerty();
+        assert_eq!(qwerty.translate('Q' as usize), Some(0x4));
+        assert_eq!(qwerty.translate('A' as usize), Some(0x7));
+        assert_eq!(qwerty.translate('X' as usize), Some(0x0));
+        assert_eq!(qwerty.translate('P' as usize), None);
+
+        assert_eq!(Keymap::azerty().translate('A' as usize), Some(0x4));
+        assert_eq!(Keymap::azerty().translate('Q' as usize), Some(0x7));
+        assert_eq!(Keymap::dvorak().translate(188), Some(0x5));
+        assert_eq!(Keymap::preset("Dvorak"), Some(Keymap::dvorak()));
+    }
+
+    #[test]
+    fn test_parse() {
+        let keymap: Keymap = "
+# arrows and space
+[keys]
+38 = 0x5      # up
+\"40\" = 8
+q = C
+32 = 0x6
+"
+        .parse()
+        .unwrap();
+        assert_eq!(keymap.translate(38), Some(0x5));
+        assert_eq!(keymap.translate(40), Some(0x8));
+        assert_eq!(keymap.translate('Q' as usize), Some(0xC));
+        assert_eq!(keymap.translate(32), Some(0x6));
+
+        assert!("Q 4".parse::<Keymap>().is_err());
+        assert!("Q = 0x10".parse::<Keymap>().is_err());
+        assert_eq!(
+            "1 = 1\nQ = G".parse::<Keymap>().unwrap_err(),
+            "line 2: invalid CHIP-8 key 'G'"
+        );
+    }
+
+    #[test]
+    fn test_bind() {
+        let mut keymap = Keymap::empty();
+        keymap.bind(38, 0xF).unwrap();
+        keymap.bind(38, 0x2).unwrap();
+        assert_eq!(keymap.translate(38), Some(0x2));
+
+        let err = keymap.bind(40, 0x10).unwrap_err();
+        assert_eq!(err.to_string(), "no CHIP-8 key 0x10, keys are 0-F");
+        assert_eq!(keymap.translate(40), None);
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        for keymap in [Keymap::qwerty(), Keymap::azerty(), Keymap::dvorak()].iter() {
+            assert_eq!(&keymap.to_string().parse::<Keymap>().unwrap(), keymap);
+        }
+    }
+
+    #[test]
+    fn test_unmapped_keys_ignored() {
+        let mut cpu = Cpu::new();
+        cpu.execute_instruction(0xF00A).unwrap(); // LD V0, K
+        cpu.registers[0] = 0xFF;
+
+        // P is not mapped, execution stays halted
+        cpu.key_down('P' as usize);
+        assert!(cpu.is_waiting_for_key());
+        assert!(!cpu.is_key_pressed(0));
+
+        cpu.key_down('A' as usize);
+        assert!(!cpu.is_waiting_for_key());
+        assert_eq!(cpu.registers[0], 0x7);
+    }
+
+    #[test]
+    fn test_key_down_chip8() {
+        let mut cpu = Cpu::new();
+        cpu.keymap = Keymap::empty();
+        cpu.key_down_chip8(0xB);
+        assert!(cpu.is_key_pressed(0xB));
+        cpu.key_up_chip8(0xB);
+        assert!(!cpu.is_key_pressed(0xB));
+
+        // out of range keys are ignored
+        cpu.key_down_chip8(0x10);
+    }
+}
@@ -0,0 +1,94 @@
+use crate::cpu::Cpu;
+use crate::error::Chip8Error;
+use crate::machine::{Machine, FRAME_DURATION};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine(rom: &[u8]) -> Machine {
+        let mut cpu = Cpu::new();
+        cpu.load_from_bytes(rom).unwrap();
+        Machine::new(cpu)
+    }
+
+    #[test]
+    fn test_frame_timing() {
+        // LD V0, 0x01 ; ADD V0, 0x01 ; JP 0x202
+        let mut machine = machine(&[0x60, 0x01, 0x70, 0x01, 0x12, 0x02]);
+        machine.set_cycles_per_frame(9);
+        machine.cpu.delay_timer = 5;
+
+        let events = machine.run_frame();
+        assert_eq!(events.cycles, 9);
+        assert_eq!(machine.cpu.delay_timer, 4);
+        assert_eq!(machine.cpu.registers[0], 5);
+        assert!(!events.display_dirty);
+    }
+
+    #[test]
+    fn test_events() {
+        let rom = [
+            0x60, 0x02, // LD V0, 0x02
+            0xF0, 0x18, // LD ST, V0
+            0xD0, 0x05, // DRW V0, V0, 5
+            0xF1, 0x0A, // LD V1, K
+        ];
+        let mut machine = machine(&rom);
+
+        let events = machine.run_frame();
+        assert_eq!(events.cycles, 4);
+        assert!(events.display_dirty);
+        assert!(events.sound_started);
+        assert!(events.sound_on);
+        assert!(events.waiting_for_key);
+
+        // timers keep running while waiting
+        let events = machine.run_frame();
+        assert_eq!(events.cycles, 0);
+        assert!(events.sound_stopped);
+        assert!(!events.sound_on);
+    }
+
+    #[test]
+    fn test_display_dirty() {
+        let rom = [
+            0x00, 0xFF, // SYS 0x0FF, HIGH in SCHIP mode
+            0x00, 0xC1, // SYS 0x0C1, SCD 1 in SCHIP mode
+            0x00, 0xE0, // CLS on a blank screen
+            0xD0, 0x05, // DRW V0, V0, 5
+        ];
+        let mut machine = machine(&rom);
+        machine.set_cycles_per_frame(3);
+        assert!(!machine.run_frame().display_dirty);
+        machine.set_cycles_per_frame(1);
+        assert!(machine.run_frame().display_dirty);
+    }
+
+    #[test]
+    fn test_fault() {
+        let mut machine = machine(&[0x00, 0xEE]); // RET
+        let events = machine.run_frame();
+        assert!(matches!(
+            events.fault,
+            Some(Chip8Error::StackUnderflow { .. })
+        ));
+    }
+
+    #[test]
+    fn test_run_for() {
+        let mut machine = machine(&[0x12, 0x00]); // JP 0x200
+
+        let events = machine.run_for(FRAME_DURATION / 2);
+        assert_eq!(events.frames, 0);
+
+        // the remainder carries over
+        let events = machine.run_for(FRAME_DURATION * 2);
+        assert_eq!(events.frames, 2);
+        assert_eq!(events.cycles, 20);
+
+        // long pauses don't fast forward
+        let events = machine.run_for(FRAME_DURATION * 1000);
+        assert!(events.frames < 10);
+    }
+}
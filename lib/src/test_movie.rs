@@ -0,0 +1,110 @@
+use crate::cpu::Mode;
+use crate::error::Chip8Error;
+use crate::framebuffer::Framebuffer;
+use crate::machine::FRAME_DURATION;
+use crate::movie::{Input, Movie, Player, Recorder};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PONG: &[u8] = include_bytes!("../../wasm/roms/PONG");
+
+    /**
+     * Play PONG for a while, moving the left paddle
+     */
+    fn record(seed: u64) -> (Movie, Framebuffer, [u8; 16]) {
+        let movie = Movie::new(PONG, Mode::Chip8, seed);
+        let mut machine = movie.boot().unwrap();
+        let mut recorder = Recorder::new(movie);
+
+        for frame in 0..300 {
+            match frame % 40 {
+                5 => recorder.key(&mut machine, 0x1, true),
+                15 => recorder.key(&mut machine, 0x1, false),
+                20 => recorder.key(&mut machine, 0x4, true),
+                33 => recorder.key(&mut machine, 0x4, false),
+                _ => {}
+            }
+            assert!(machine.run_frame().fault.is_none());
+        }
+
+        let display = machine.cpu.display.clone();
+        let registers = machine.cpu.registers;
+        (recorder.finish(&machine), display, registers)
+    }
+
+    #[test]
+    fn test_replay() {
+        let (movie, display, registers) = record(42);
+        assert_eq!(movie.frames, 300);
+        assert_eq!(movie.inputs.len(), 30);
+        assert_eq!(
+            movie.inputs[0],
+            Input {
+                frame: 5,
+                key: 0x1,
+                pressed: true
+            }
+        );
+
+        let mut machine = movie.boot().unwrap();
+        let mut player = Player::new(movie);
+        while !player.is_finished(&machine) {
+            player.run_frame(&mut machine);
+        }
+        assert_eq!(machine.frame(), 300);
+        assert_eq!(machine.cpu.display, display);
+        assert_eq!(machine.cpu.registers, registers);
+    }
+
+    #[test]
+    fn test_replay_run_for() {
+        let (movie, display, registers) = record(7);
+        let mut machine = movie.boot().unwrap();
+        let mut player = Player::new(movie);
+        while !player.is_finished(&machine) {
+            player.run_for(&mut machine, FRAME_DURATION * 3);
+        }
+        assert_eq!(machine.frame(), 300);
+        assert_eq!(machine.cpu.display, display);
+        assert_eq!(machine.cpu.registers, registers);
+    }
+
+    #[test]
+    fn test_seed_matters() {
+        // the ball is served in a random direction
+        let (_, _, first) = record(1);
+        let (_, _, second) = record(2);
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        let (mut movie, _, _) = record(42);
+        movie.mode = Mode::SuperChip;
+        movie.quirks.draw_wraps = true;
+        movie.cycles_per_frame = 30;
+
+        let bytes = movie.to_bytes();
+        // header, ROM and 5 bytes per input
+        assert_eq!(
+            bytes.len(),
+            4 + 2 + 7 + 4 + 8 + 4 + PONG.len() + 8 + 4 + 5 * 30
+        );
+        assert_eq!(Movie::from_bytes(&bytes).unwrap(), movie);
+    }
+
+    #[test]
+    fn test_invalid_movie() {
+        let movie = Movie::new(PONG, Mode::Chip8, 0).to_bytes();
+        assert!(matches!(
+            Movie::from_bytes(b"RC8S"),
+            Err(Chip8Error::InvalidMovie(_))
+        ));
+        assert!(matches!(
+            Movie::from_bytes(&movie[..movie.len() - 1]),
+            Err(Chip8Error::InvalidMovie(_))
+        ));
+    }
+}
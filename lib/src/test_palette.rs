@@ -0,0 +1,170 @@
+use crate::cpu::{Cpu, Mode, Rect, DEFAULT_PHOSPHOR_DECAY};
+use crate::palette::{rgb, Palette};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+    const BLACK: [u8; 4] = [0x00, 0x00, 0x00, 0xFF];
+    const RED: [u8; 4] = [0xFF, 0x00, 0x00, 0xFF];
+
+    fn pixel(out: &[u8], index: usize) -> &[u8] {
+        &out[index * 4..index * 4 + 4]
+    }
+
+    /**
+     * Draw the font sprite for 0 at the top left, so
+     * pixels 0-3 of the first row are lit
+     */
+    fn draw_zero(cpu: &mut Cpu) {
+        cpu.i_register = 0;
+        cpu.registers[0] = 0;
+        cpu.execute_instruction(0xD005).unwrap();
+    }
+
+    #[test]
+    fn test_rgb() {
+        assert_eq!(rgb(0x123456), [0x12, 0x34, 0x56, 0xFF]);
+        assert_eq!(
+            Palette::new(WHITE, BLACK).colors,
+            [BLACK, WHITE, WHITE, WHITE]
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let mut cpu = Cpu::new();
+        draw_zero(&mut cpu);
+
+        let mut out = Vec::new();
+        Palette::new(WHITE, BLACK).render(&mut cpu, &mut out);
+        assert_eq!(out.len(), 64 * 32 * 4);
+        assert_eq!(pixel(&out, 0), WHITE);
+        assert_eq!(pixel(&out, 3), WHITE);
+        assert_eq!(pixel(&out, 4), BLACK);
+        assert!(out.chunks(4).all(|rgba| rgba[3] == 0xFF));
+
+        // the buffer follows resolution changes
+        let mut cpu = Cpu::with_mode(Mode::SuperChip);
+        cpu.execute_instruction(0x00FF).unwrap();
+        Palette::default().render(&mut cpu, &mut out);
+        assert_eq!(out.len(), 128 * 64 * 4);
+    }
+
+    #[test]
+    fn test_glow_decay() {
+        let mut cpu = Cpu::new();
+        assert_eq!(cpu.phosphor_decay, DEFAULT_PHOSPHOR_DECAY);
+        cpu.phosphor_decay = 3;
+        draw_zero(&mut cpu);
+        draw_zero(&mut cpu);
+        assert_eq!(cpu.phosphor_glow[0], 3);
+
+        let palette = Palette::new(WHITE, BLACK).with_glow(RED);
+        let mut out = Vec::new();
+        for _ in 0..3 {
+            palette.render(&mut cpu, &mut out);
+            assert_eq!(pixel(&out, 0), RED);
+            assert_eq!(pixel(&out, 4), BLACK);
+        }
+        palette.render(&mut cpu, &mut out);
+        assert_eq!(pixel(&out, 0), BLACK);
+
+        // no glow at all
+        cpu.phosphor_decay = 0;
+        draw_zero(&mut cpu);
+        draw_zero(&mut cpu);
+        palette.render(&mut cpu, &mut out);
+        assert_eq!(pixel(&out, 0), BLACK);
+
+        // the setting survives loading a save state
+        cpu.phosphor_decay = 7;
+        let state = cpu.save_state();
+        cpu.load_state(&state).unwrap();
+        assert_eq!(cpu.phosphor_decay, 7);
+    }
+
+    #[test]
+    fn test_render_regions() {
+        let palette = Palette::new(WHITE, BLACK).with_glow(RED);
+        let mut cpu = Cpu::new();
+        let mut out = Vec::new();
+        palette.render(&mut cpu, &mut out);
+        cpu.take_dirty_regions();
+
+        // only the dirty area is redrawn
+        draw_zero(&mut cpu);
+        cpu.display.set(63, 0, 1);
+        let regions = cpu.take_dirty_regions();
+        let redrawn = palette.render_regions(&mut cpu, &mut out, &regions);
+        assert_eq!(redrawn, vec![Rect::new(0, 0, 8, 5)]);
+        assert_eq!(pixel(&out, 0), WHITE);
+        assert_eq!(pixel(&out, 63), BLACK);
+
+        // overlapping regions are merged
+        let regions = [Rect::new(0, 0, 8, 5), Rect::new(4, 2, 8, 5)];
+        let redrawn = palette.render_regions(&mut cpu, &mut out, &regions);
+        assert_eq!(redrawn, vec![Rect::new(0, 0, 12, 7)]);
+
+        // glowing pixels stay dirty until they fade
+        cpu.phosphor_decay = 2;
+        draw_zero(&mut cpu);
+        let regions = cpu.take_dirty_regions();
+        palette.render_regions(&mut cpu, &mut out, &regions);
+        assert_eq!(pixel(&out, 0), RED);
+        let regions = cpu.take_dirty_regions();
+        assert_eq!(regions, vec![Rect::new(0, 0, 8, 5)]);
+        palette.render_regions(&mut cpu, &mut out, &regions);
+        assert_eq!(pixel(&out, 0), RED);
+        let regions = cpu.take_dirty_regions();
+        palette.render_regions(&mut cpu, &mut out, &regions);
+        assert_eq!(pixel(&out, 0), BLACK);
+        assert!(cpu.take_dirty_regions().is_empty());
+    }
+
+    #[test]
+    fn test_repaint_keeps_fading() {
+        let mut cpu = Cpu::new();
+        let mut out = Vec::new();
+        cpu.phosphor_decay = 3;
+        draw_zero(&mut cpu);
+        draw_zero(&mut cpu);
+        cpu.take_dirty_regions();
+
+        // switching palettes repaints everything while pixels glow
+        let palette = Palette::new(WHITE, BLACK).with_glow(RED);
+        palette.render(&mut cpu, &mut out);
+        assert_eq!(pixel(&out, 0), RED);
+        let regions = cpu.take_dirty_regions();
+        assert_eq!(regions, vec![Rect::new(0, 0, 4, 5)]);
+
+        palette.render_regions(&mut cpu, &mut out, &regions);
+        assert_eq!(pixel(&out, 0), RED);
+        let regions = cpu.take_dirty_regions();
+        palette.render_regions(&mut cpu, &mut out, &regions);
+        assert_eq!(pixel(&out, 0), RED);
+        let regions = cpu.take_dirty_regions();
+        palette.render_regions(&mut cpu, &mut out, &regions);
+        assert_eq!(pixel(&out, 0), BLACK);
+        assert!(cpu.take_dirty_regions().is_empty());
+    }
+
+    #[test]
+    fn test_planes() {
+        let mut cpu = Cpu::new();
+        cpu.display.set(0, 0, 1);
+        cpu.display.set(1, 0, 2);
+        cpu.display.set(2, 0, 3);
+
+        let colors = [rgb(0x101010), rgb(0x202020), rgb(0x303030), rgb(0x404040)];
+        let mut out = Vec::new();
+        Palette::default()
+            .with_planes(colors)
+            .render(&mut cpu, &mut out);
+        assert_eq!(pixel(&out, 0), colors[1]);
+        assert_eq!(pixel(&out, 1), colors[2]);
+        assert_eq!(pixel(&out, 2), colors[3]);
+        assert_eq!(pixel(&out, 3), colors[0]);
+    }
+}
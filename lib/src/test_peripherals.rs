@@ -0,0 +1,119 @@
+use crate::cpu::Cpu;
+use crate::peripherals::{Buzzer, Display, Framebuffer, Keypad};
+use std::sync::{Arc, Mutex};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default, Clone)]
+    struct Recorder {
+        frames: Arc<Mutex<Vec<Framebuffer>>>,
+        tones: Arc<Mutex<Vec<bool>>>,
+    }
+
+    impl Display for Recorder {
+        fn update(&mut self, display: &Framebuffer) {
+            self.frames.lock().unwrap().push(display.clone());
+        }
+    }
+
+    impl Buzzer for Recorder {
+        fn set_active(&mut self, active: bool) {
+            self.tones.lock().unwrap().push(active);
+        }
+    }
+
+    // only key 5 is ever held
+    struct HeldKey;
+
+    impl Keypad for HeldKey {
+        fn is_pressed(&self, key: u8) -> bool {
+            key == 5
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let recorder = Recorder::default();
+        let mut cpu = Cpu::new();
+        cpu.set_display(recorder.clone());
+
+        cpu.execute_instruction(0x6005).unwrap(); // LD V0, 0x05
+        assert!(recorder.frames.lock().unwrap().is_empty());
+
+        cpu.execute_instruction(0xD005).unwrap(); // DRW V0, V0, 5
+        cpu.execute_instruction(0x00E0).unwrap(); // CLS
+        let frames = recorder.frames.lock().unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1], cpu.display);
+        assert!(frames[0].iter().any(|p| p != 0));
+        assert!(frames[1].iter().all(|p| p == 0));
+    }
+
+    // panics if asked about anything but keys 0-F
+    struct StrictKeypad;
+
+    impl Keypad for StrictKeypad {
+        fn is_pressed(&self, key: u8) -> bool {
+            assert!(key < 16, "key {:X} out of range", key);
+            false
+        }
+    }
+
+    #[test]
+    fn test_keypad_range() {
+        let mut cpu = Cpu::new();
+        cpu.set_keypad(StrictKeypad);
+        cpu.registers[0] = 0xFF;
+
+        // SKP V0 ; SKNP V0 never skip an invalid key
+        cpu.execute_instruction(0xE09E).unwrap();
+        cpu.execute_instruction(0xE0A1).unwrap();
+        assert_eq!(cpu.program_counter, 0x204);
+        assert!(!cpu.is_key_pressed(0x10));
+    }
+
+    #[test]
+    fn test_keypad() {
+        let mut cpu = Cpu::new();
+        cpu.set_keypad(HeldKey);
+        assert!(cpu.is_key_pressed(5));
+
+        cpu.execute_instruction(0x6005).unwrap(); // LD V0, 0x05
+        cpu.execute_instruction(0xE09E).unwrap(); // SKP V0
+        assert_eq!(cpu.program_counter, 0x206);
+        cpu.execute_instruction(0xE0A1).unwrap(); // SKNP V0
+        assert_eq!(cpu.program_counter, 0x208);
+    }
+
+    #[test]
+    fn test_buzzer() {
+        let recorder = Recorder::default();
+        let mut cpu = Cpu::new();
+        cpu.set_buzzer(recorder.clone());
+
+        cpu.execute_instruction(0x6002).unwrap(); // LD V0, 0x02
+        cpu.execute_instruction(0xF018).unwrap(); // LD ST, V0
+        cpu.decrement_timers();
+        assert_eq!(*recorder.tones.lock().unwrap(), vec![true]);
+        cpu.decrement_timers();
+        assert_eq!(*recorder.tones.lock().unwrap(), vec![true, false]);
+    }
+
+    #[test]
+    fn test_load_state_keeps_peripherals() {
+        let mut cpu = Cpu::new();
+        cpu.execute_instruction(0x6003).unwrap(); // LD V0, 0x03
+        cpu.execute_instruction(0xF018).unwrap(); // LD ST, V0
+        let state = cpu.save_state();
+
+        let recorder = Recorder::default();
+        let mut restored = Cpu::new();
+        restored.set_buzzer(recorder.clone());
+        restored.set_display(recorder.clone());
+        restored.load_state(&state).unwrap();
+        assert_eq!(*recorder.tones.lock().unwrap(), vec![true]);
+        assert_eq!(recorder.frames.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,148 @@
+use crate::cpu::{Cpu, Mode};
+use crate::machine::Machine;
+use crate::movie::{Movie, Recorder};
+use crate::rewind::Rewind;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TETRIS: &[u8] = include_bytes!("../../wasm/roms/TETRIS");
+
+    fn machine() -> Machine {
+        let mut cpu = Cpu::with_seed(3);
+        cpu.load_from_bytes(TETRIS).unwrap();
+        Machine::new(cpu)
+    }
+
+    #[test]
+    fn test_rewind_restores_state() {
+        let mut machine = machine();
+        machine.enable_rewind(1 << 20);
+
+        let mut states = vec![machine.cpu.save_state()];
+        for _ in 0..120 {
+            machine.run_frame();
+            states.push(machine.cpu.save_state());
+        }
+        assert_eq!(machine.rewind_frames(), 120);
+
+        assert_eq!(machine.rewind(30).unwrap(), 30);
+        assert_eq!(machine.frame(), 90);
+        assert_eq!(machine.cpu.save_state(), states[90]);
+
+        assert_eq!(machine.rewind(1).unwrap(), 1);
+        assert_eq!(machine.cpu.save_state(), states[89]);
+
+        // only 89 frames are left to rewind
+        assert_eq!(machine.rewind(1000).unwrap(), 89);
+        assert_eq!(machine.frame(), 0);
+        assert_eq!(machine.cpu.save_state(), states[0]);
+    }
+
+    #[test]
+    fn test_rewind_then_continue() {
+        let mut machine = machine();
+        machine.enable_rewind(1 << 20);
+        for _ in 0..60 {
+            machine.run_frame();
+        }
+        machine.rewind(20).unwrap();
+        let expected = machine.cpu.save_state();
+
+        // running forward again reproduces the same frames
+        for _ in 0..20 {
+            machine.run_frame();
+        }
+        machine.rewind(20).unwrap();
+        assert_eq!(machine.cpu.save_state(), expected);
+    }
+
+    #[test]
+    fn test_budget() {
+        let mut machine = machine();
+        // the newest snapshot counts against the budget as well
+        let budget = machine.cpu.save_state().len() + 2000;
+        machine.enable_rewind(budget);
+        for _ in 0..600 {
+            machine.run_frame();
+        }
+        let frames = machine.rewind_frames();
+        assert!(frames > 0 && frames < 600);
+
+        let mut rewind = Rewind::new(budget);
+        for _ in 0..600 {
+            machine.run_frame();
+            rewind.capture(&machine.cpu);
+            assert!(rewind.memory_used() <= budget);
+        }
+    }
+
+    #[test]
+    fn test_budget_idle_frames() {
+        // frames that change nothing still cost memory
+        let cpu = Cpu::new();
+        let budget = cpu.save_state().len() + 4096;
+        let mut rewind = Rewind::new(budget);
+        for _ in 0..10_000 {
+            rewind.capture(&cpu);
+            assert!(rewind.memory_used() <= budget);
+        }
+        assert!(rewind.frames() > 0);
+        assert!(rewind.frames() <= 4096 / std::mem::size_of::<Vec<u8>>());
+    }
+
+    #[test]
+    fn test_resolution_change() {
+        let mut cpu = Cpu::with_mode(Mode::SuperChip);
+        // HIGH ; CLS ; LOW ; JP 0x206
+        cpu.load_from_bytes(&[0x00, 0xFF, 0x00, 0xE0, 0x00, 0xFE, 0x12, 0x06])
+            .unwrap();
+        let mut machine = Machine::new(cpu);
+        machine.set_cycles_per_frame(1);
+        machine.enable_rewind(1 << 20);
+
+        for _ in 0..4 {
+            machine.run_frame();
+        }
+        assert_eq!(machine.cpu.resolution(), (64, 32));
+        machine.rewind(2).unwrap();
+        assert_eq!(machine.cpu.resolution(), (128, 64));
+        machine.rewind(2).unwrap();
+        assert_eq!(machine.cpu.resolution(), (64, 32));
+        assert_eq!(machine.cpu.program_counter, 0x200);
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut machine = machine();
+        machine.run_frame();
+        assert_eq!(machine.rewind_frames(), 0);
+        assert_eq!(machine.rewind(10).unwrap(), 0);
+        assert_eq!(machine.frame(), 1);
+    }
+
+    #[test]
+    fn test_recorder_drops_undone_inputs() {
+        let movie = Movie::new(TETRIS, Mode::Chip8, 9);
+        let mut machine = movie.boot().unwrap();
+        machine.enable_rewind(1 << 20);
+        let mut recorder = Recorder::new(movie);
+
+        for frame in 0..50 {
+            if frame % 10 == 0 {
+                recorder.key(&mut machine, 0x5, true);
+            }
+            machine.run_frame();
+        }
+        machine.rewind(25).unwrap();
+        recorder.rewind(&machine);
+
+        let movie = recorder.finish(&machine);
+        assert_eq!(movie.frames, 25);
+        assert_eq!(
+            movie.inputs.iter().map(|i| i.frame).collect::<Vec<_>>(),
+            vec![0, 10, 20]
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use crate::cpu::{Cpu, Mode, HIRES_WIDTH};
+use crate::error::Chip8Error;
+use crate::quirks::Quirks;
+use crate::rng::ScriptedSource;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(cpu: &mut Cpu, cycles: usize) {
+        for _ in 0..cycles {
+            let opcode = cpu.fetch_instruction().unwrap();
+            cpu.execute_instruction(opcode).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut cpu = Cpu::new();
+        cpu.load_from_bytes(include_bytes!("../../wasm/roms/test_opcode.ch8"))
+            .unwrap();
+        run(&mut cpu, 200);
+        cpu.delay_timer = 7;
+        cpu.key_down(81);
+
+        let state = cpu.save_state();
+        let mut restored = Cpu::new();
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.save_state(), state);
+        assert_eq!(restored.program_counter, cpu.program_counter);
+        assert_eq!(restored.stack, cpu.stack);
+        assert_eq!(restored.display, cpu.display);
+        assert_eq!(restored.delay_timer, 7);
+        assert!(restored.is_key_pressed(4));
+
+        // both continue identically
+        run(&mut cpu, 50);
+        run(&mut restored, 50);
+        assert_eq!(restored.registers, cpu.registers);
+        assert_eq!(restored.program_counter, cpu.program_counter);
+    }
+
+    #[test]
+    fn test_waiting_for_key() {
+        let mut cpu = Cpu::new();
+        cpu.execute_instruction(0xF30A).unwrap(); // LD V3, K
+
+        let mut restored = Cpu::new();
+        restored.load_state(&cpu.save_state()).unwrap();
+        assert!(restored.is_waiting_for_key());
+
+        // the key lands in V3 after restoring
+        restored.key_down(81);
+        assert!(!restored.is_waiting_for_key());
+        assert_eq!(restored.registers[3], 0x4);
+
+        // waiting for a release
+        let mut cpu = Cpu::with_quirks(Quirks::cosmac_vip());
+        cpu.execute_instruction(0xF30A).unwrap();
+        cpu.key_down_chip8(0xA);
+        let mut restored = Cpu::new();
+        restored.load_state(&cpu.save_state()).unwrap();
+        assert_eq!(restored.key_wait(), cpu.key_wait());
+        restored.key_up_chip8(0xA);
+        assert_eq!(restored.registers[3], 0xA);
+    }
+
+    #[test]
+    fn test_mode_and_resolution() {
+        let mut cpu = Cpu::with_mode(Mode::XoChip);
+        cpu.quirks = Quirks::cosmac_vip();
+        cpu.execute_instruction(0x00FF).unwrap(); // HIGH
+
+        let mut restored = Cpu::new();
+        restored.load_state(&cpu.save_state()).unwrap();
+        assert_eq!(restored.mode, Mode::XoChip);
+        assert_eq!(restored.quirks, Quirks::cosmac_vip());
+        assert_eq!(restored.resolution().0, HIRES_WIDTH);
+        assert_eq!(restored.memory.len(), cpu.memory.len());
+    }
+
+    #[test]
+    fn test_rng_continues() {
+        let mut cpu = Cpu::with_seed(42);
+        cpu.execute_instruction(0xC0FF).unwrap();
+        let state = cpu.save_state();
+        cpu.execute_instruction(0xC1FF).unwrap();
+
+        let mut restored = Cpu::with_seed(7);
+        restored.load_state(&state).unwrap();
+        restored.execute_instruction(0xC1FF).unwrap();
+        assert_eq!(restored.registers[1], cpu.registers[1]);
+
+        // custom sources are kept and repositioned
+        let mut cpu = Cpu::new();
+        cpu.set_rng(ScriptedSource::new(vec![1, 2, 3]));
+        cpu.execute_instruction(0xC0FF).unwrap();
+        let state = cpu.save_state();
+
+        let mut restored = Cpu::new();
+        restored.set_rng(ScriptedSource::new(vec![1, 2, 3]));
+        restored.load_state(&state).unwrap();
+        restored.execute_instruction(0xC0FF).unwrap();
+        assert_eq!(restored.registers[0], 2);
+    }
+
+    #[test]
+    fn test_invalid_state() {
+        let mut cpu = Cpu::new();
+        cpu.registers[0] = 0x42;
+        let state = cpu.save_state();
+
+        let mut other = Cpu::new();
+        let truncated = &state[..state.len() - 1];
+        assert!(matches!(
+            other.load_state(truncated),
+            Err(Chip8Error::InvalidState(_))
+        ));
+
+        let mut trailing = state.clone();
+        trailing.push(0);
+        assert!(other.load_state(&trailing).is_err());
+
+        let mut magic = state.clone();
+        magic[0] = b'X';
+        assert!(other.load_state(&magic).is_err());
+
+        // failed loads leave the CPU untouched
+        assert_eq!(other.registers[0], 0);
+    }
+}
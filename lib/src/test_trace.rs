@@ -0,0 +1,260 @@
+use crate::cpu::Cpu;
+use crate::instruction::Instruction;
+use crate::trace::{JsonLines, OpClass, RingBuffer, TextLog, TraceEntry, TraceSink, Tracer};
+use std::sync::{Arc, Mutex};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /**
+     * A Write that can be read back after the tracer owns it
+     */
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Shared {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    const ROM: [u8; 12] = [
+        0x60, 0x7B, // LD V0, 0x7B
+        0xA3, 0x00, // LD I, 0x300
+        0xF0, 0x33, // LD B, V0
+        0x12, 0x06, // JP 0x206
+        0xD0, 0x01, // DRW V0, V0, 1
+        0x00, 0xE0, // CLS
+    ];
+
+    fn run(cpu: &mut Cpu, cycles: usize) {
+        for _ in 0..cycles {
+            let opcode = cpu.fetch_instruction().unwrap();
+            cpu.execute_instruction(opcode).unwrap();
+        }
+    }
+
+    fn cpu_with(tracer: Tracer) -> Cpu {
+        let mut cpu = Cpu::new();
+        cpu.load_from_bytes(&ROM).unwrap();
+        cpu.set_tracer(tracer);
+        cpu
+    }
+
+    #[test]
+    fn test_records_writes() {
+        let ring = RingBuffer::new(16);
+        let mut cpu = cpu_with(Tracer::new().with_sink(ring.clone()));
+        run(&mut cpu, 4);
+
+        let entries = ring.entries();
+        assert_eq!(entries.len(), 4);
+        assert_eq!(
+            entries[0],
+            TraceEntry {
+                pc: 0x200,
+                opcode: 0x607B,
+                instruction: Some(Instruction::LdVxByte { x: 0, kk: 0x7B }),
+                registers: vec![(0, 0x7B)],
+                i: None,
+                memory: vec![],
+                fault: None,
+            }
+        );
+        assert_eq!(entries[1].i, Some(0x300));
+        assert_eq!(entries[2].mnemonic(), "LD B, V0");
+        assert_eq!(entries[2].memory, vec![(0x300, 1), (0x301, 2), (0x302, 3)]);
+        assert_eq!(entries[3].pc, 0x206);
+        assert!(entries[3].registers.is_empty());
+    }
+
+    #[test]
+    fn test_ring_capacity() {
+        let ring = RingBuffer::new(3);
+        let mut cpu = cpu_with(Tracer::new().with_sink(ring.clone()));
+        run(&mut cpu, 10);
+
+        let entries = ring.entries();
+        assert_eq!(entries.len(), 3);
+        assert!(entries.iter().all(|e| e.pc == 0x206));
+        ring.clear();
+        assert!(ring.entries().is_empty());
+    }
+
+    #[test]
+    fn test_filters() {
+        let ring = RingBuffer::new(16);
+        let mut cpu = cpu_with(
+            Tracer::new()
+                .with_addresses(0x202..0x206)
+                .with_sink(ring.clone()),
+        );
+        run(&mut cpu, 4);
+        let pcs: Vec<usize> = ring.entries().iter().map(|e| e.pc).collect();
+        assert_eq!(pcs, vec![0x202, 0x204]);
+
+        let ring = RingBuffer::new(16);
+        let mut cpu = cpu_with(
+            Tracer::new()
+                .with_classes(&[OpClass::Flow, OpClass::Alu])
+                .with_sink(ring.clone()),
+        );
+        run(&mut cpu, 4);
+        let pcs: Vec<usize> = ring.entries().iter().map(|e| e.pc).collect();
+        assert_eq!(pcs, vec![0x200, 0x206]);
+
+        assert_eq!(OpClass::of(&Instruction::Cls), OpClass::Display);
+        assert_eq!("memory".parse::<OpClass>(), Ok(OpClass::Memory));
+        assert!("bogus".parse::<OpClass>().is_err());
+    }
+
+    #[test]
+    fn test_text_and_json_sinks() {
+        let text = Shared::default();
+        let json = Shared::default();
+        let mut cpu = cpu_with(
+            Tracer::new()
+                .with_sink(TextLog::new(text.clone()))
+                .with_sink(JsonLines::new(json.clone())),
+        );
+        run(&mut cpu, 3);
+
+        let lines: Vec<String> = text
+            .text()
+            .lines()
+            .map(|l| l.trim_end().to_string())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "0x200  607B  LD V0, 0x7B              V0=7B",
+                "0x202  A300  LD I, 0x300              I=300",
+                "0x204  F033  LD B, V0                 [300]=01 [301]=02 [302]=03",
+            ]
+        );
+
+        let json = json.text();
+        let lines: Vec<&str> = json.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            "{\"pc\":512,\"opcode\":24699,\"mnemonic\":\"LD V0, 0x7B\",\"registers\":{\"V0\":123},\"i\":null,\"memory\":[],\"fault\":null}"
+        );
+        assert_eq!(
+            lines[2],
+            "{\"pc\":516,\"opcode\":61491,\"mnemonic\":\"LD B, V0\",\"registers\":{},\"i\":null,\"memory\":[[768,1],[769,2],[770,3]],\"fault\":null}"
+        );
+    }
+
+    #[test]
+    fn test_faults_and_unchanged_writes() {
+        let ring = RingBuffer::new(16);
+        let mut cpu = Cpu::new();
+        cpu.set_tracer(Tracer::new().with_sink(ring.clone()));
+
+        // LD [I], V0 storing the 0 already at 0x300
+        cpu.i_register = 0x300;
+        cpu.execute_instruction(0xF055).unwrap();
+        // RET with an empty stack
+        assert!(cpu.execute_instruction(0x00EE).is_err());
+
+        let entries = ring.entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].memory, vec![(0x300, 0)]);
+        assert_eq!(entries[0].fault, None);
+        assert_eq!(entries[1].mnemonic(), "RET");
+        assert_eq!(
+            entries[1].fault.as_deref(),
+            Some("stack underflow at 0x202, no address to return to")
+        );
+        assert!(entries[1]
+            .to_string()
+            .ends_with("fault: stack underflow at 0x202, no address to return to"));
+    }
+
+    #[test]
+    fn test_fetched_opcodes() {
+        let ring = RingBuffer::new(16);
+        let mut cpu = Cpu::new();
+        cpu.set_tracer(Tracer::new().with_sink(ring.clone()));
+
+        // 5xyN runs as SE Vx, Vy before XO-CHIP
+        cpu.execute_instruction(0x5011).unwrap();
+        let err = cpu.execute_instruction(0xE0FF).unwrap_err();
+        cpu.set_tracer(
+            Tracer::new()
+                .with_addresses(0x300..0x400)
+                .with_sink(ring.clone()),
+        );
+        assert!(cpu.execute_instruction(0xE0FF).is_err());
+
+        let entries = ring.entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].opcode, 0x5011);
+        assert_eq!(entries[0].mnemonic(), "SE V0, V1");
+        assert_eq!(
+            entries[1],
+            TraceEntry {
+                pc: 0x204,
+                opcode: 0xE0FF,
+                instruction: None,
+                registers: vec![],
+                i: None,
+                memory: vec![],
+                fault: Some(err.to_string()),
+            }
+        );
+        assert_eq!(entries[1].mnemonic(), "dw 0xE0FF");
+    }
+
+    #[test]
+    fn test_json_escapes() {
+        let entry = TraceEntry {
+            pc: 0x200,
+            opcode: 0x00EE,
+            instruction: Some(Instruction::Ret),
+            registers: vec![],
+            i: None,
+            memory: vec![],
+            fault: Some("a \"quoted\" C:\\path\n".to_string()),
+        };
+        assert!(entry
+            .to_json()
+            .ends_with("\"fault\":\"a \\\"quoted\\\" C:\\\\path\\n\"}"));
+    }
+
+    #[test]
+    fn test_take_tracer() {
+        struct Count(Arc<Mutex<usize>>);
+        impl TraceSink for Count {
+            fn record(&mut self, _entry: &TraceEntry) {
+                *self.0.lock().unwrap() += 1;
+            }
+        }
+
+        let count = Arc::new(Mutex::new(0));
+        let mut cpu = cpu_with(Tracer::new().with_sink(Count(count.clone())));
+        run(&mut cpu, 2);
+        assert!(cpu.take_tracer().is_some());
+        run(&mut cpu, 2);
+        assert_eq!(*count.lock().unwrap(), 2);
+
+        // tracing survives loading a save state
+        cpu.set_tracer(Tracer::new().with_sink(Count(count.clone())));
+        let state = cpu.save_state();
+        cpu.load_state(&state).unwrap();
+        run(&mut cpu, 1);
+        assert_eq!(*count.lock().unwrap(), 3);
+    }
+}
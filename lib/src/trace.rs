@@ -0,0 +1,364 @@
+use crate::instruction::Instruction;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::Write;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+/**
+ * Broad groups of instructions, used to filter traces
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpClass {
+    /// Jumps, calls, returns and skips on register values
+    Flow,
+    /// Register loads, arithmetic, logic, shifts and RND
+    Alu,
+    /// Instructions that set I or move data to and from memory
+    Memory,
+    /// Drawing, scrolling, resolution and plane changes
+    Display,
+    /// Key skips and Fx0A
+    Input,
+    /// The delay timer
+    Timer,
+    /// The sound timer, audio pattern and pitch
+    Sound,
+}
+
+impl OpClass {
+    pub fn of(inst: &Instruction) -> Self {
+        use Instruction::*;
+        match inst {
+            Sys(_)
+            | Ret
+            | Exit
+            | Jp(_)
+            | Call(_)
+            | JpV0(_)
+            | SeVxByte { .. }
+            | SneVxByte { .. }
+            | SeVxVy { .. }
+            | SneVxVy { .. } => OpClass::Flow,
+            LdVxByte { .. }
+            | AddVxByte { .. }
+            | LdVxVy { .. }
+            | OrVxVy { .. }
+            | AndVxVy { .. }
+            | XorVxVy { .. }
+            | AddVxVy { .. }
+            | SubVxVy { .. }
+            | ShrVxVy { .. }
+            | SubnVxVy { .. }
+            | ShlVxVy { .. }
+            | Rnd { .. } => OpClass::Alu,
+            LdI(_)
+            | LdILong(_)
+            | AddIVx { .. }
+            | LdFVx { .. }
+            | LdHfVx { .. }
+            | LdBVx { .. }
+            | LdIVx { .. }
+            | LdVxI { .. }
+            | SaveRange { .. }
+            | LoadRange { .. }
+            | LdRVx { .. }
+            | LdVxR { .. } => OpClass::Memory,
+            Cls
+            | Drw { .. }
+            | ScrollDown(_)
+            | ScrollUp(_)
+            | ScrollRight
+            | ScrollLeft
+            | Low
+            | High
+            | Plane(_) => OpClass::Display,
+            Skp { .. } | Sknp { .. } | LdVxK { .. } => OpClass::Input,
+            LdVxDt { .. } | LdDtVx { .. } => OpClass::Timer,
+            LdStVx { .. } | Audio | Pitch { .. } => OpClass::Sound,
+        }
+    }
+}
+
+impl std::str::FromStr for OpClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "flow" => Ok(OpClass::Flow),
+            "alu" => Ok(OpClass::Alu),
+            "memory" => Ok(OpClass::Memory),
+            "display" => Ok(OpClass::Display),
+            "input" => Ok(OpClass::Input),
+            "timer" => Ok(OpClass::Timer),
+            "sound" => Ok(OpClass::Sound),
+            _ => Err(format!(
+                "unknown opcode class '{}', expected flow, alu, memory, display, input, timer or sound",
+                s
+            )),
+        }
+    }
+}
+
+/**
+ * One executed instruction and the state it changed
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub pc: usize,
+    pub opcode: u16,
+    /// None if the opcode could not be decoded, fault then says why
+    pub instruction: Option<Instruction>,
+    /// (register, new value) for each V register that changed
+    pub registers: Vec<(u8, u8)>,
+    /// The new value of I, if it changed
+    pub i: Option<u16>,
+    /// (address, value) for each memory byte written, including
+    /// writes that stored the value already there
+    pub memory: Vec<(usize, u8)>,
+    /// The error if the instruction faulted, the entry then
+    /// holds whatever changed before the fault
+    pub fault: Option<String>,
+}
+
+impl TraceEntry {
+    /**
+     * The instruction, or the opcode as a dw
+     * directive if it could not be decoded
+     */
+    pub fn mnemonic(&self) -> String {
+        match &self.instruction {
+            Some(inst) => inst.to_string(),
+            None => format!("dw 0x{:04X}", self.opcode),
+        }
+    }
+
+    /**
+     * A single line JSON object, for JSON Lines logs
+     */
+    pub fn to_json(&self) -> String {
+        let registers: Vec<String> = self
+            .registers
+            .iter()
+            .map(|(x, value)| format!("\"V{:X}\":{}", x, value))
+            .collect();
+        let memory: Vec<String> = self
+            .memory
+            .iter()
+            .map(|(addr, value)| format!("[{},{}]", addr, value))
+            .collect();
+        format!(
+            "{{\"pc\":{},\"opcode\":{},\"mnemonic\":\"{}\",\"registers\":{{{}}},\"i\":{},\"memory\":[{}],\"fault\":{}}}",
+            self.pc,
+            self.opcode,
+            escape(&self.mnemonic()),
+            registers.join(","),
+            self.i.map_or("null".to_string(), |i| i.to_string()),
+            memory.join(","),
+            self.fault
+                .as_ref()
+                .map_or("null".to_string(), |fault| format!("\"{}\"", escape(fault)))
+        )
+    }
+}
+
+/**
+ * Escape s for use inside a JSON string
+ */
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/**
+ * `0x200  6001  LD V0, 0x01  V0=01`
+ */
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "0x{:03X}  {:04X}  {:<24}",
+            self.pc,
+            self.opcode,
+            self.mnemonic()
+        )?;
+        for (x, value) in &self.registers {
+            write!(f, " V{:X}={:02X}", x, value)?;
+        }
+        if let Some(i) = self.i {
+            write!(f, " I={:03X}", i)?;
+        }
+        for (addr, value) in &self.memory {
+            write!(f, " [{:03X}]={:02X}", addr, value)?;
+        }
+        if let Some(fault) = &self.fault {
+            write!(f, " fault: {}", fault)?;
+        }
+        Ok(())
+    }
+}
+
+/**
+ * Receives the entries that pass the tracer's filters
+ */
+pub trait TraceSink: Send {
+    fn record(&mut self, entry: &TraceEntry);
+}
+
+/**
+ * Keeps the most recent entries in memory
+ *
+ * Clones share the same buffer, so keep one to read the
+ * entries after giving the other to the tracer.
+ */
+#[derive(Debug, Clone)]
+pub struct RingBuffer {
+    capacity: usize,
+    entries: Arc<Mutex<VecDeque<TraceEntry>>>,
+}
+
+impl RingBuffer {
+    pub fn new(capacity: usize) -> Self {
+        RingBuffer {
+            capacity,
+            entries: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+        }
+    }
+
+    /**
+     * The buffered entries, oldest first
+     */
+    pub fn entries(&self) -> Vec<TraceEntry> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+impl TraceSink for RingBuffer {
+    fn record(&mut self, entry: &TraceEntry) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        if self.capacity > 0 {
+            entries.push_back(entry.clone());
+        }
+    }
+}
+
+/**
+ * Writes one line of text per entry, write errors
+ * are ignored so tracing never stops the program
+ */
+pub struct TextLog<W: Write + Send> {
+    out: W,
+}
+
+impl<W: Write + Send> TextLog<W> {
+    pub fn new(out: W) -> Self {
+        TextLog { out }
+    }
+}
+
+impl<W: Write + Send> TraceSink for TextLog<W> {
+    fn record(&mut self, entry: &TraceEntry) {
+        writeln!(self.out, "{}", entry).ok();
+    }
+}
+
+/**
+ * Writes one JSON object per line, write errors are
+ * ignored so tracing never stops the program
+ */
+pub struct JsonLines<W: Write + Send> {
+    out: W,
+}
+
+impl<W: Write + Send> JsonLines<W> {
+    pub fn new(out: W) -> Self {
+        JsonLines { out }
+    }
+}
+
+impl<W: Write + Send> TraceSink for JsonLines<W> {
+    fn record(&mut self, entry: &TraceEntry) {
+        writeln!(self.out, "{}", entry.to_json()).ok();
+    }
+}
+
+/**
+ * Filters executed instructions and forwards them to sinks,
+ * attach one with Cpu::set_tracer
+ */
+#[derive(Default)]
+pub struct Tracer {
+    addresses: Option<Range<usize>>,
+    classes: Option<Vec<OpClass>>,
+    sinks: Vec<Box<dyn TraceSink>>,
+}
+
+impl Tracer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+     * Only trace instructions located in addresses
+     */
+    pub fn with_addresses(mut self, addresses: Range<usize>) -> Self {
+        self.addresses = Some(addresses);
+        self
+    }
+
+    /**
+     * Only trace instructions of these classes
+     */
+    pub fn with_classes(mut self, classes: &[OpClass]) -> Self {
+        self.classes = Some(classes.to_vec());
+        self
+    }
+
+    pub fn with_sink<S: TraceSink + 'static>(mut self, sink: S) -> Self {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+
+    /**
+     * True if an instruction at pc passes the filters
+     */
+    pub fn wants(&self, pc: usize, inst: &Instruction) -> bool {
+        self.wants_address(pc)
+            && self
+                .classes
+                .as_ref()
+                .is_none_or(|classes| classes.contains(&OpClass::of(inst)))
+    }
+
+    /**
+     * True if pc passes the address filter, opcodes that
+     * fail to decode have no class and only check this
+     */
+    pub fn wants_address(&self, pc: usize) -> bool {
+        self.addresses
+            .as_ref()
+            .is_none_or(|range| range.contains(&pc))
+    }
+
+    pub(crate) fn record(&mut self, entry: &TraceEntry) {
+        for sink in &mut self.sinks {
+            sink.record(entry);
+        }
+    }
+}
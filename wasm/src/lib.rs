@@ -1,5 +1,14 @@
-use rchip8::cpu::Cpu;
+use rchip8::audio::{Synth, Waveform};
+use rchip8::cpu::{Cpu, Rect};
+use rchip8::keymap::Keymap;
+use rchip8::machine::{self, Machine};
+use rchip8::movie::{Movie, Player, Recorder};
+use rchip8::palette::{rgb, Palette};
+use rchip8::rewind::DEFAULT_REWIND_BUDGET;
+use rchip8::rng::SplitMix64;
+use rchip8::trace::{OpClass, RingBuffer, Tracer};
 use std::sync::Mutex;
+use std::time::Duration;
 use wasm_bindgen::prelude::*;
 
 #[macro_use]
@@ -7,9 +16,10 @@ extern crate lazy_static;
 
 lazy_static! {
     /**
-     * Our runtime will instantiate a global CPU instance
+     * The emulator driven by the free functions, kept so
+     * pages written before the Emulator class still work
      */
-    static ref CPU: Mutex<Cpu> = Mutex::new(Cpu::new());
+    static ref EMULATOR: Mutex<Emulator> = Mutex::new(Emulator::new());
 }
 
 #[wasm_bindgen]
@@ -34,91 +44,651 @@ pub fn entry() -> Result<(), JsValue> {
 }
 
 /**
- * Load a binary blob as the Chip8 program
- *
- * This is required because the rust wasm runtime can't
- * yet read files by itself, so we will fetch the file with
- * JS first
+ * Events reported by run_for
  */
 #[wasm_bindgen]
-pub fn load_program(prog: &[u8]) -> Result<(), JsValue> {
-    let mut cpu = CPU.lock().unwrap();
-    match cpu.load_from_bytes(&prog) {
-        Ok(_) => {}
-        Err(e) => {
-            console_log!("{:?}", e);
-            return Err(format!("{:?}", e).into());
+pub struct FrameEvents {
+    pub frames: u32,
+    pub display_dirty: bool,
+    pub sound_on: bool,
+    pub waiting_for_key: bool,
+    pub exited: bool,
+    fault: Option<String>,
+}
+
+#[wasm_bindgen]
+impl FrameEvents {
+    /**
+     * Description of the fault that stopped execution, if any
+     */
+    #[wasm_bindgen(getter)]
+    pub fn fault(&self) -> Option<String> {
+        self.fault.clone()
+    }
+}
+
+impl From<machine::FrameEvents> for FrameEvents {
+    fn from(events: machine::FrameEvents) -> Self {
+        if let Some(e) = &events.fault {
+            console_log!("[-] {}", e);
+        }
+        FrameEvents {
+            frames: events.frames,
+            display_dirty: events.display_dirty,
+            sound_on: events.sound_on,
+            waiting_for_key: events.waiting_for_key,
+            exited: events.exited,
+            fault: events.fault.map(|e| e.to_string()),
         }
     }
-    console_log!("[+] loaded ROM");
-    Ok(())
 }
 
 /**
- * Complete a full fetch -> execute cycle for the next
- * instruction
+ * A self contained emulator, create as many as the
+ * page needs with `new Emulator()`
  */
 #[wasm_bindgen]
-pub fn execute_cycle() -> Result<(), JsValue> {
-    let mut cpu = CPU.lock().unwrap();
-    let opcode = cpu.fetch_instruction();
-    console_log!("{:x}", opcode);
-    match cpu.execute_instruction(opcode) {
-        Ok(_) => {}
-        Err(e) => {
+pub struct Emulator {
+    // owns the CPU and the instruction/timer cadence
+    machine: Machine,
+    // tone generator for the sound timer, configured by set_audio
+    synth: Synth,
+    // the last ROM loaded, movies are recorded from a fresh boot of it
+    rom: Vec<u8>,
+    // the movie being recorded or played back, if any
+    recorder: Option<Recorder>,
+    player: Option<Player>,
+    // the most recent traced instructions, while tracing
+    trace: Option<RingBuffer>,
+    // colors and RGBA pixels of the last render
+    palette: Palette,
+    framebuffer: Vec<u8>,
+    // the palette changed, so every pixel needs redrawing
+    repaint: bool,
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl Emulator {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Emulator {
+        Emulator {
+            machine: Machine::new(Cpu::new()),
+            synth: Synth::new(44100),
+            rom: Vec::new(),
+            recorder: None,
+            player: None,
+            trace: None,
+            palette: Palette::default(),
+            framebuffer: Vec::new(),
+            repaint: true,
+        }
+    }
+
+    /**
+     * Load a binary blob as the Chip8 program
+     *
+     * This is required because the rust wasm runtime can't
+     * yet read files by itself, so we will fetch the file with
+     * JS first
+     */
+    pub fn load_program(&mut self, prog: &[u8]) -> Result<(), JsValue> {
+        if let Err(e) = self.machine.cpu.load_from_bytes(prog) {
             console_log!("{:?}", e);
             return Err(format!("{:?}", e).into());
         }
+        self.machine.enable_rewind(DEFAULT_REWIND_BUDGET);
+        self.rom = prog.to_vec();
+        self.recorder = None;
+        self.player = None;
+        console_log!("[+] loaded ROM");
+        Ok(())
+    }
+
+    /**
+     * Complete a full fetch -> execute cycle for the next
+     * instruction
+     */
+    pub fn step(&mut self) -> Result<(), JsValue> {
+        let cpu = &mut self.machine.cpu;
+        let result = cpu
+            .fetch_instruction()
+            .and_then(|opcode| cpu.execute_instruction(opcode));
+        if let Err(e) = result {
+            console_log!("[-] {}", e);
+            return Err(e.to_string().into());
+        }
+        Ok(())
+    }
+
+    /**
+     * Run a single 60Hz frame
+     */
+    pub fn run_frame(&mut self) -> FrameEvents {
+        let events = match self.player.as_mut() {
+            Some(movie) => movie.run_frame(&mut self.machine),
+            None => self.machine.run_frame(),
+        };
+        self.finish_movie();
+        events.into()
+    }
+
+    /**
+     * Run the frames that fit in the elapsed milliseconds, each
+     * frame executes a fixed number of instructions and ticks
+     * the timers once
+     */
+    pub fn run_for(&mut self, elapsed_ms: f64) -> FrameEvents {
+        let elapsed = Duration::from_secs_f64(elapsed_ms.max(0.0) / 1000.0);
+        let events = match self.player.as_mut() {
+            Some(movie) => movie.run_for(&mut self.machine, elapsed),
+            None => self.machine.run_for(elapsed),
+        };
+        self.finish_movie();
+        events.into()
+    }
+
+    /**
+     * Set the number of instructions executed per 60Hz frame
+     */
+    pub fn set_cycles_per_frame(&mut self, cycles: usize) {
+        self.machine.set_cycles_per_frame(cycles);
+    }
+
+    /**
+     *  Update the timers, should get called at 60Hz
+     */
+    pub fn update_timers(&mut self) {
+        self.machine.cpu.decrement_timers();
+    }
+
+    /**
+     * Handle all key events by updating the emulator
+     * state appropriately
+     */
+    pub fn handle_key_event(&mut self, code: u32, event_type: &str) {
+        // keys are ignored while a movie plays back
+        if self.player.is_some() {
+            return;
+        }
+        let pressed = match event_type {
+            "keydown" => true,
+            "keyup" => false,
+            _ => return,
+        };
+
+        let machine = &mut self.machine;
+        match self.recorder.as_mut() {
+            Some(recorder) => {
+                if let Some(key) = machine.cpu.keymap.translate(code as usize) {
+                    recorder.key(machine, key, pressed);
+                }
+            }
+            None if pressed => machine.cpu.key_down(code as usize),
+            None => machine.cpu.key_up(code as usize),
+        }
+    }
+
+    /**
+     * Change how key codes map to CHIP-8 keys, either with
+     * a preset name (qwerty, azerty, dvorak) or a list of
+     * `host = chip8` bindings
+     */
+    pub fn set_keymap(&mut self, spec: &str) -> Result<(), JsValue> {
+        let keymap = match Keymap::preset(spec.trim()) {
+            Some(keymap) => keymap,
+            None => spec.parse::<Keymap>()?,
+        };
+        self.machine.cpu.keymap = keymap;
+        Ok(())
+    }
+
+    /**
+     * The current display resolution as [width, height], the
+     * JS buffer passed to update_display must be resized to
+     * match whenever this changes
+     */
+    pub fn display_resolution(&self) -> Vec<u32> {
+        let (width, height) = self.machine.cpu.resolution();
+        vec![width as u32, height as u32]
+    }
+
+    /**
+     * Two color palette as 0xRRGGBB values, glow is shown
+     * while an erased pixel fades out
+     */
+    pub fn set_palette(&mut self, foreground: u32, background: u32, glow: u32) {
+        self.palette = Palette::new(rgb(foreground), rgb(background)).with_glow(rgb(glow));
+        self.repaint = true;
+    }
+
+    /**
+     * XO-CHIP colors as 0xRRGGBB values for pixels lit on no
+     * plane, plane 1, plane 2 and both planes
+     */
+    pub fn set_plane_colors(&mut self, background: u32, plane1: u32, plane2: u32, both: u32) {
+        self.palette.colors = [rgb(background), rgb(plane1), rgb(plane2), rgb(both)];
+        self.repaint = true;
+    }
+
+    /**
+     * Frames a pixel erased by DRW keeps glowing for, 0 disables it
+     */
+    pub fn set_phosphor_decay(&mut self, frames: u8) {
+        self.machine.cpu.phosphor_decay = frames;
+    }
+
+    /**
+     * Draw what changed on the display into the framebuffer
+     * with the current palette, returning the redrawn areas
+     * as [x, y, width, height, ...], empty if nothing changed.
+     * Call once per animation frame since the glow fades by
+     * one frame per render.
+     */
+    pub fn render(&mut self) -> Vec<u32> {
+        let cpu = &mut self.machine.cpu;
+        let (width, height) = cpu.resolution();
+        let regions = cpu.take_dirty_regions();
+
+        let redrawn = if self.repaint || self.framebuffer.len() != width * height * 4 {
+            self.repaint = false;
+            self.palette.render(cpu, &mut self.framebuffer);
+            vec![Rect::new(0, 0, width, height)]
+        } else {
+            self.palette
+                .render_regions(cpu, &mut self.framebuffer, &regions)
+        };
+        redrawn
+            .iter()
+            .flat_map(|r| [r.x, r.y, r.width, r.height])
+            .map(|n| n as u32)
+            .collect()
+    }
+
+    /**
+     * The display areas changed since the last call, as
+     * [x, y, width, height, ...], for pages that draw the
+     * display themselves instead of calling render
+     */
+    pub fn take_dirty_regions(&mut self) -> Vec<u32> {
+        self.machine
+            .cpu
+            .take_dirty_regions()
+            .iter()
+            .flat_map(|r| [r.x, r.y, r.width, r.height])
+            .map(|n| n as u32)
+            .collect()
+    }
+
+    /**
+     * Location of the RGBA framebuffer in wasm memory, so JS can
+     * wrap it without copying:
+     *
+     * new ImageData(new Uint8ClampedArray(memory.buffer, ptr, len), width, height)
+     *
+     * Read it again after every render, it moves when the
+     * resolution changes or the wasm memory grows
+     */
+    pub fn framebuffer_ptr(&self) -> *const u8 {
+        self.framebuffer.as_ptr()
+    }
+
+    pub fn framebuffer_len(&self) -> usize {
+        self.framebuffer.len()
+    }
+
+    /**
+     * Render and copy the display into the provided JS buffer
+     *
+     * JS reference for the buffer:
+     * https://developer.mozilla.org/en-US/docs/Web/API/CanvasRenderingContext2D/createImageData
+     */
+    pub fn update_display(&mut self, display: &mut [u8]) {
+        self.render();
+
+        // skip frames drawn into a buffer of the wrong size
+        if display.len() == self.framebuffer.len() {
+            display.copy_from_slice(&self.framebuffer);
+        }
+    }
+
+    /**
+     * Snapshot the full emulator state so it
+     * can be resumed later with load_state
+     */
+    pub fn save_state(&self) -> Vec<u8> {
+        self.machine.cpu.save_state()
+    }
+
+    /**
+     * Restore a snapshot taken by save_state
+     */
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), JsValue> {
+        self.machine
+            .cpu
+            .load_state(state)
+            .map_err(|e| JsValue::from(e.to_string()))?;
+        // frames from before the load can't be rewound into
+        self.machine.enable_rewind(DEFAULT_REWIND_BUDGET);
+        Ok(())
+    }
+
+    /**
+     * Go back up to frames frames (60 per second), returning
+     * how many were rewound. Does nothing during movie playback.
+     */
+    pub fn rewind(&mut self, frames: u32) -> Result<u32, JsValue> {
+        if self.player.is_some() {
+            return Ok(0);
+        }
+        let rewound = self
+            .machine
+            .rewind(frames as usize)
+            .map_err(|e| e.to_string())?;
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.rewind(&self.machine);
+        }
+        Ok(rewound as u32)
+    }
+
+    /**
+     * Restart the loaded ROM with a random seed and record
+     * every key event until stop_movie is called
+     */
+    pub fn start_recording(&mut self) -> Result<(), JsValue> {
+        let cpu = &self.machine.cpu;
+        let mut movie = Movie::new(&self.rom, cpu.mode, SplitMix64::from_entropy().next_u64());
+        movie.quirks = cpu.quirks;
+        movie.cycles_per_frame = self.machine.cycles_per_frame();
+        self.boot(&movie)?;
+
+        self.player = None;
+        self.recorder = Some(Recorder::new(movie));
+        console_log!("[+] recording");
+        Ok(())
+    }
+
+    /**
+     * Restart from the start of a movie and replay its
+     * inputs, the keyboard is ignored until it finishes
+     */
+    pub fn play_movie(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+        let movie = Movie::from_bytes(bytes).map_err(|e| e.to_string())?;
+        self.boot(&movie)?;
+
+        self.rom = movie.rom.clone();
+        self.recorder = None;
+        self.player = Some(Player::new(movie));
+        Ok(())
+    }
+
+    /**
+     * Stop recording or playing back, returning the
+     * recorded movie file if there was one
+     */
+    pub fn stop_movie(&mut self) -> Option<Vec<u8>> {
+        self.player = None;
+        let recorder = self.recorder.take()?;
+        Some(recorder.finish(&self.machine).to_bytes())
+    }
+
+    /**
+     * Keep the last capacity executed instructions, optionally
+     * only those located in [from, to) and of the comma separated
+     * classes (flow, alu, memory, display, input, timer, sound)
+     */
+    pub fn start_trace(
+        &mut self,
+        capacity: usize,
+        from: Option<u32>,
+        to: Option<u32>,
+        classes: Option<String>,
+    ) -> Result<(), JsValue> {
+        let ring = RingBuffer::new(capacity);
+        let mut tracer = Tracer::new().with_sink(ring.clone());
+        if from.is_some() || to.is_some() {
+            let from = from.unwrap_or(0) as usize;
+            let to = to.map_or(usize::MAX, |to| to as usize);
+            tracer = tracer.with_addresses(from..to);
+        }
+        if let Some(classes) = classes.filter(|c| !c.trim().is_empty()) {
+            let classes = classes
+                .split(',')
+                .map(|class| class.trim().parse::<OpClass>())
+                .collect::<Result<Vec<_>, _>>()?;
+            tracer = tracer.with_classes(&classes);
+        }
+
+        self.machine.cpu.set_tracer(tracer);
+        self.trace = Some(ring);
+        Ok(())
+    }
+
+    pub fn stop_trace(&mut self) {
+        self.machine.cpu.take_tracer();
+        self.trace = None;
+    }
+
+    /**
+     * The traced instructions as text, one per line
+     */
+    pub fn trace_text(&self) -> String {
+        let entries = self
+            .trace
+            .as_ref()
+            .map(|ring| ring.entries())
+            .unwrap_or_default();
+        entries.iter().map(|entry| format!("{}\n", entry)).collect()
+    }
+
+    /**
+     * The traced instructions as JSON Lines
+     */
+    pub fn trace_json(&self) -> String {
+        let entries = self
+            .trace
+            .as_ref()
+            .map(|ring| ring.entries())
+            .unwrap_or_default();
+        entries
+            .iter()
+            .map(|entry| format!("{}\n", entry.to_json()))
+            .collect()
+    }
+
+    /**
+     * Configure the tone played while the sound timer runs,
+     * waveform is one of square, triangle, sawtooth, sine or
+     * pattern (the XO-CHIP audio buffer)
+     */
+    pub fn set_audio(
+        &mut self,
+        sample_rate: u32,
+        frequency: f32,
+        volume: f32,
+        waveform: &str,
+    ) -> Result<(), JsValue> {
+        let waveform = waveform.parse::<Waveform>()?;
+        let synth = &mut self.synth;
+        synth.sample_rate = sample_rate;
+        synth.frequency = frequency;
+        synth.volume = volume.clamp(0.0, 1.0);
+        synth.waveform = waveform;
+        Ok(())
+    }
+
+    /**
+     * Fill a Float32Array with samples for the current sound
+     * timer state, meant to be posted to an AudioWorklet after
+     * every run_for so the tone follows the timer ticks
+     */
+    pub fn fill_audio(&mut self, buffer: &mut [f32]) {
+        let cpu = &self.machine.cpu;
+        self.synth.sync(cpu);
+        self.synth.fill(cpu.sound_timer > 0, buffer);
     }
-    Ok(())
 }
 
-/**
- * Handle all key events by updating the emulator
- * state appropriately
+impl Emulator {
+    /**
+     * Replace the machine with a fresh boot of the movie,
+     * keeping the user's keymap and any tracer
+     */
+    fn boot(&mut self, movie: &Movie) -> Result<(), JsValue> {
+        let machine = &mut self.machine;
+        let keymap = machine.cpu.keymap.clone();
+        let tracer = machine.cpu.take_tracer();
+        *machine = movie.boot().map_err(|e| e.to_string())?;
+        machine.cpu.keymap = keymap;
+        if let Some(tracer) = tracer {
+            machine.cpu.set_tracer(tracer);
+        }
+        machine.enable_rewind(DEFAULT_REWIND_BUDGET);
+        Ok(())
+    }
+
+    /**
+     * Hand control back to the keyboard once the movie ends
+     */
+    fn finish_movie(&mut self) {
+        if self
+            .player
+            .as_ref()
+            .is_some_and(|movie| movie.is_finished(&self.machine))
+        {
+            console_log!("[+] movie finished");
+            self.player = None;
+        }
+    }
+}
+
+/*
+ * The functions below drive a single shared Emulator, they
+ * predate the Emulator class and are kept for existing pages
  */
+
+#[wasm_bindgen]
+pub fn load_program(prog: &[u8]) -> Result<(), JsValue> {
+    EMULATOR.lock().unwrap().load_program(prog)
+}
+
+#[wasm_bindgen]
+pub fn execute_cycle() -> Result<(), JsValue> {
+    EMULATOR.lock().unwrap().step()
+}
+
+#[wasm_bindgen]
+pub fn run_for(elapsed_ms: f64) -> FrameEvents {
+    EMULATOR.lock().unwrap().run_for(elapsed_ms)
+}
+
+#[wasm_bindgen]
+pub fn set_cycles_per_frame(cycles: usize) {
+    EMULATOR.lock().unwrap().set_cycles_per_frame(cycles)
+}
+
+#[wasm_bindgen]
+pub fn update_timers() {
+    EMULATOR.lock().unwrap().update_timers()
+}
+
 #[wasm_bindgen]
 pub fn handle_key_event(code: u32, event_type: &str) {
-    let mut cpu = CPU.lock().unwrap();
-    match event_type {
-        "keydown" => cpu.key_down(code as usize),
-        "keyup" => cpu.key_up(code as usize),
-        _ => {}
-    }
-    console_log!("got key {:?}, type: {:?}", code, event_type)
+    EMULATOR.lock().unwrap().handle_key_event(code, event_type)
+}
+
+#[wasm_bindgen]
+pub fn set_keymap(spec: &str) -> Result<(), JsValue> {
+    EMULATOR.lock().unwrap().set_keymap(spec)
+}
+
+#[wasm_bindgen]
+pub fn display_resolution() -> Vec<u32> {
+    EMULATOR.lock().unwrap().display_resolution()
 }
 
-/**
- * Update the display by writing into the provided JS buffer
- *
- * JS reference for the buffer:
- * https://developer.mozilla.org/en-US/docs/Web/API/CanvasRenderingContext2D/createImageData
- */
 #[wasm_bindgen]
 pub fn update_display(display: &mut [u8]) {
-    let mut cpu = CPU.lock().unwrap();
-    let (data, glow) = cpu.get_display();
-    for i in 0..data.len() {
-        if glow[i] > 0 {
-            display[i * 4] = 0x33;
-            display[i * 4 + 1] = 0xff;
-            display[i * 4 + 2] = 0x99 + glow[i];
-            glow[i] -= 1;
-            continue;
-        }
+    EMULATOR.lock().unwrap().update_display(display)
+}
 
-        display[i * 4] = if data[i] == 1 { 0x33 } else { 0x0 };
-        display[i * 4 + 1] = if data[i] == 1 { 0xff } else { 0x0 };
-        display[i * 4 + 2] = if data[i] == 1 { 0x66 } else { 0x0 };
-        display[i * 4 + 3] = 255;
-    }
+#[wasm_bindgen]
+pub fn save_state() -> Vec<u8> {
+    EMULATOR.lock().unwrap().save_state()
 }
 
-/**
- *  Update the timers, should get called at 60Hz
- */
 #[wasm_bindgen]
-pub fn update_timers() {
-    let mut cpu = CPU.lock().unwrap();
-    cpu.decrement_timers();
+pub fn load_state(state: &[u8]) -> Result<(), JsValue> {
+    EMULATOR.lock().unwrap().load_state(state)
+}
+
+#[wasm_bindgen]
+pub fn rewind(frames: u32) -> Result<u32, JsValue> {
+    EMULATOR.lock().unwrap().rewind(frames)
+}
+
+#[wasm_bindgen]
+pub fn start_recording() -> Result<(), JsValue> {
+    EMULATOR.lock().unwrap().start_recording()
+}
+
+#[wasm_bindgen]
+pub fn play_movie(bytes: &[u8]) -> Result<(), JsValue> {
+    EMULATOR.lock().unwrap().play_movie(bytes)
+}
+
+#[wasm_bindgen]
+pub fn stop_movie() -> Option<Vec<u8>> {
+    EMULATOR.lock().unwrap().stop_movie()
+}
+
+#[wasm_bindgen]
+pub fn start_trace(
+    capacity: usize,
+    from: Option<u32>,
+    to: Option<u32>,
+    classes: Option<String>,
+) -> Result<(), JsValue> {
+    EMULATOR
+        .lock()
+        .unwrap()
+        .start_trace(capacity, from, to, classes)
+}
+
+#[wasm_bindgen]
+pub fn stop_trace() {
+    EMULATOR.lock().unwrap().stop_trace()
+}
+
+#[wasm_bindgen]
+pub fn trace_text() -> String {
+    EMULATOR.lock().unwrap().trace_text()
+}
+
+#[wasm_bindgen]
+pub fn trace_json() -> String {
+    EMULATOR.lock().unwrap().trace_json()
+}
+
+#[wasm_bindgen]
+pub fn set_audio(
+    sample_rate: u32,
+    frequency: f32,
+    volume: f32,
+    waveform: &str,
+) -> Result<(), JsValue> {
+    EMULATOR
+        .lock()
+        .unwrap()
+        .set_audio(sample_rate, frequency, volume, waveform)
+}
+
+#[wasm_bindgen]
+pub fn fill_audio(buffer: &mut [f32]) {
+    EMULATOR.lock().unwrap().fill_audio(buffer)
 }